@@ -1,28 +1,30 @@
 #import bevy_pbr::mesh_view_bindings
 #import bevy_pbr::mesh_types
 
+// Packed vertex. See VoxelVertex docs for more info.
 struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @location(2) uv: vec2<f32>,
-    @location(3) tile_coord_start: vec2<f32>,
-    @location(4) light: vec3<f32>,
-    @location(5) voxel: u32,
+    @location(0) position: u32,
+    @location(1) light: u32,
+    @location(2) tile: u32,
+    @location(3) artificial_light: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) light_intensity: vec3<f32>,
-    @location(1) uv: vec2<f32>,
-    @location(2) tile_coord_start: vec2<f32>,
-    @location(3) world_normal: vec3<f32>,
-    @location(4) world_pos: vec3<f32>,
+    @location(0) natural_light: f32,
+    @location(1) artificial_light: f32,
+    @location(2) uv: vec2<f32>,
+    @location(3) tile_coord_start: vec2<f32>,
+    @location(4) world_normal: vec3<f32>,
+    @location(5) world_pos: vec3<f32>,
 };
 
 struct MaterialData {
     tile_texture_size: f32,
     clip_map_origin: vec2<f32>,
     clip_height: f32,
+    clip_map_axis_size: u32,
+    sky_brightness: f32,
 };
 
 @group(1) @binding(0)
@@ -35,18 +37,20 @@ var atlas_sampler: sampler;
 var<uniform> material_data: MaterialData;
 
 @group(1) @binding(3)
-var clip_map: texture_1d<u32>;
+var clip_map: texture_2d<u32>;
 
 @group(2) @binding(0)
 var<uniform> mesh: Mesh;
 
 let CLIPPED_VERTEX: vec4<f32> = vec4<f32>(-2.0, -2.0, -2.0, -2.0);
-let CLIPPED_LIGHT: vec3<f32> = vec3<f32>(1.0, 1.0, 1.0);
+// Clipped faces are fully lit by the sky, so they still follow the time of day
+let CLIPPED_NATURAL_LIGHT: f32 = 1.0;
+let CLIPPED_ARTIFICIAL_LIGHT: f32 = 0.0;
 let CLIPPED_TILE_COORD_START: vec2<f32> = vec2<f32>(0.0, 0.0);
 
+let MAX_LIGHT_INTENSITY: f32 = 15.0;
+
 let NO_CLIP: f32 = 9999.0;
-let CLIP_AXIS_SIZE: u32 = 144u;
-let CLIP_SIZE: u32 = 20736u;
 
 let UP: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
 let DOWN: vec3<f32> = vec3<f32>(0.0, -1.0, 0.0);
@@ -59,31 +63,92 @@ fn to_world(position: vec3<f32>) -> vec4<f32> {
     return mesh.model * vec4<f32>(position, 1.0);
 }
 
+fn unpack_position(packed: u32) -> vec3<f32> {
+    return vec3<f32>(
+        f32(packed & 0x1Fu),
+        f32((packed >> 5u) & 0x1FFu),
+        f32((packed >> 14u) & 0x1Fu)
+    );
+}
+
+fn unpack_normal(packed: u32) -> vec3<f32> {
+    let side = (packed >> 19u) & 0x7u;
+    if (side == 0u) {
+        return RIGHT;
+    } else if (side == 1u) {
+        return LEFT;
+    } else if (side == 2u) {
+        return UP;
+    } else if (side == 3u) {
+        return DOWN;
+    } else if (side == 4u) {
+        return FRONT;
+    } else {
+        return BACK;
+    }
+}
+
 fn unpack_voxel(packed: u32) -> vec3<f32> {
-    return unpack4x8unorm(packed).xyz * 255.0;
+    let offset = (packed >> 22u) & 0x7u;
+    return unpack_position(packed) - vec3<f32>(
+        f32(offset & 1u),
+        f32((offset >> 1u) & 1u),
+        f32((offset >> 2u) & 1u)
+    );
+}
+
+// Each opaque neighbor removes a quarter of vertex light
+fn unpack_occlusion_factor(packed: u32) -> f32 {
+    let occlusion = f32((packed >> 4u) & 0xFu);
+    return (4.0 - occlusion) / 4.0;
 }
 
-fn get_voxel_index(voxel: vec3<f32>) -> u32 {
+fn unpack_natural_light(packed: u32) -> f32 {
+    let intensity = f32(packed & 0xFu) / MAX_LIGHT_INTENSITY;
+    return intensity * unpack_occlusion_factor(packed);
+}
+
+// Artificial light comes on its own attribute, but shares occlusion with natural light
+fn unpack_artificial_light(packed: u32, light: u32) -> f32 {
+    let intensity = f32(packed & 0xFu) / MAX_LIGHT_INTENSITY;
+    return intensity * unpack_occlusion_factor(light);
+}
+
+fn unpack_uv(packed: u32) -> vec2<f32> {
+    let uv = vec2<f32>(f32((packed >> 12u) & 0x3FFu), f32((packed >> 22u) & 0x3FFu));
+    return uv * material_data.tile_texture_size;
+}
+
+fn unpack_tile_coord_start(packed: u32) -> vec2<f32> {
+    let tile = packed & 0xFFFu;
+    let tiles_per_row = u32(round(1.0 / material_data.tile_texture_size));
+    let tile_coord = vec2<f32>(f32(tile % tiles_per_row), f32(tile / tiles_per_row));
+    return tile_coord * material_data.tile_texture_size;
+}
+
+// Each clip map row is a X axis column and each texel is a Z axis column.
+fn get_voxel_coords(voxel: vec3<f32>) -> vec2<i32> {
     let world = to_world(voxel);
-    let landscape = world.xz - material_data.clip_map_origin;
-    return u32(landscape.x) * CLIP_AXIS_SIZE + u32(landscape.y);
+    let landscape = vec2<i32>(floor(world.xz - material_data.clip_map_origin));
+    return landscape.yx;
 }
 
 fn get_voxel_clip_data(voxel: vec3<f32>) -> u32 {
-    let index = i32(get_voxel_index(voxel));
-    if (index < 0 || index >= i32(CLIP_SIZE)) {
+    let coords = get_voxel_coords(voxel);
+    let axis_size = i32(material_data.clip_map_axis_size);
+    if (any(coords < vec2<i32>(0)) || any(coords >= vec2<i32>(axis_size))) {
         return 0u;
     } else {
-        return textureLoad(clip_map, index, 0).x;
+        return textureLoad(clip_map, coords, 0).x;
     }
 }
 
-fn is_top_face(vertex: Vertex) -> bool {
-    return vertex.normal.y > 0.0;
+fn is_top_face(normal: vec3<f32>) -> bool {
+    return normal.y > 0.0;
 }
 
-fn is_side_face(vertex: Vertex) -> bool {
-    return vertex.normal.y == 0.0;
+fn is_side_face(normal: vec3<f32>) -> bool {
+    return normal.y == 0.0;
 }
 
 fn get_side_clip(voxel: vec3<f32>) -> u32 {
@@ -113,32 +178,37 @@ fn vertex(
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    var position = vec4<f32>(vertex.position, 1.0);
-    var light_intensity = vertex.light;
-    var tile_coord_start = vertex.tile_coord_start;
+    let normal = unpack_normal(vertex.position);
+
+    var position = vec4<f32>(unpack_position(vertex.position), 1.0);
+    var natural_light = unpack_natural_light(vertex.light);
+    var artificial_light = unpack_artificial_light(vertex.artificial_light, vertex.light);
+    var tile_coord_start = unpack_tile_coord_start(vertex.tile);
     var should_clip = false;
 
     if (material_data.clip_height < NO_CLIP) {
-        let voxel = unpack_voxel(vertex.voxel);
-        let neighbor = voxel + vertex.normal;
+        let voxel = unpack_voxel(vertex.position);
+        let neighbor = voxel + normal;
 
         let neighbor_clip_height = f32(get_voxel_clip_data(neighbor));
         let voxel_clip_height = f32(get_voxel_clip_data(voxel));
 
-        if (is_top_face(vertex)) {
+        if (is_top_face(normal)) {
             // If the top voxel is equals or bellow the clipping, do nothing
             if (voxel.y <= voxel_clip_height) {
                 //Do nothing
 
             // If current voxel isn't on line on sight but is on the side of a voxel, which is on line of sight.
             } else if (voxel_clip_height == 0.0 && get_side_clip(voxel) > 0u) {
-                light_intensity = CLIPPED_LIGHT;
+                natural_light = CLIPPED_NATURAL_LIGHT;
+                artificial_light = CLIPPED_ARTIFICIAL_LIGHT;
                 tile_coord_start = CLIPPED_TILE_COORD_START;
                 position.y = material_data.clip_height + 1.0;
 
             // If there is some visible voxel bellow it
             } else if (voxel_clip_height > 0.0 && voxel_clip_height < material_data.clip_height) {
-                light_intensity = CLIPPED_LIGHT;
+                natural_light = CLIPPED_NATURAL_LIGHT;
+                artificial_light = CLIPPED_ARTIFICIAL_LIGHT;
                 tile_coord_start = CLIPPED_TILE_COORD_START;
                 position.y = material_data.clip_height + 1.0;
 
@@ -148,11 +218,12 @@ fn vertex(
 
             // Just clip everything else
             } else {
-                light_intensity = CLIPPED_LIGHT;
+                natural_light = CLIPPED_NATURAL_LIGHT;
+                artificial_light = CLIPPED_ARTIFICIAL_LIGHT;
                 tile_coord_start = CLIPPED_TILE_COORD_START;
                 position.y = material_data.clip_height + 1.0;
             }
-        } else if (is_side_face(vertex)) {
+        } else if (is_side_face(normal)) {
             // Only clip side faces that isn't on line of sight
             if (voxel.y > voxel_clip_height && voxel.y > neighbor_clip_height) {
                 should_clip = true;
@@ -168,21 +239,23 @@ fn vertex(
         out.clip_position = view.view_proj * mesh.model * position ;
     }
 
-    out.light_intensity = light_intensity;
-    out.uv = vertex.uv;
+    out.natural_light = natural_light;
+    out.artificial_light = artificial_light;
+    out.uv = unpack_uv(vertex.tile);
     out.tile_coord_start = tile_coord_start;
-    out.world_normal = vertex.normal;
+    out.world_normal = normal;
     out.world_pos = (mesh.model * position).xyz;
 
     return out;
 }
 
 struct FragmentInput {
-    @location(0) light_intensity: vec3<f32>,
-    @location(1) uv: vec2<f32>,
-    @location(2) tile_coord_start: vec2<f32>,
-    @location(3) world_normal: vec3<f32>,
-    @location(4) world_pos: vec3<f32>,
+    @location(0) natural_light: f32,
+    @location(1) artificial_light: f32,
+    @location(2) uv: vec2<f32>,
+    @location(3) tile_coord_start: vec2<f32>,
+    @location(4) world_normal: vec3<f32>,
+    @location(5) world_pos: vec3<f32>,
 };
 
 @fragment
@@ -194,5 +267,8 @@ fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
 
     color.a = 1.0;//clamp(d, 0.0, 1.0);
 
-    return color * vec4<f32>(in.light_intensity, 1.0);
+    // Night only darkens natural light, so artificial light stays lit
+    let light = max(in.natural_light * material_data.sky_brightness, in.artificial_light);
+
+    return color * vec4<f32>(vec3<f32>(light), 1.0);
 }
\ No newline at end of file
@@ -16,6 +16,10 @@ pub const Z_END: i32 = (Z_AXIS_SIZE - 1) as i32;
 
 pub const BUFFER_SIZE: usize = X_AXIS_SIZE * Z_AXIS_SIZE * Y_AXIS_SIZE;
 
+/// Height of each vertical section of a chunk.
+pub const SECTION_AXIS_SIZE: usize = 16;
+pub const SECTION_COUNT: usize = Y_AXIS_SIZE / SECTION_AXIS_SIZE;
+
 const X_SHIFT: usize = (Z_AXIS_SIZE.ilog2() + Z_SHIFT as u32) as usize;
 const Z_SHIFT: usize = Y_AXIS_SIZE.ilog2() as usize;
 const Y_SHIFT: usize = 0;
@@ -32,7 +36,122 @@ pub static ALLOC_COUNT: once_cell::sync::Lazy<std::sync::atomic::AtomicUsize> =
 pub struct Chunk {
     pub kinds: ChunkKind,
     pub lights: ChunkLight,
-    pub vertices: Vec<voxel::VoxelVertex>,
+    pub vertices: ChunkVertex,
+    pub connectivity: ChunkConnectivity,
+}
+
+/// Vertices of a chunk, one list per section.
+pub type ChunkVertex = [Vec<voxel::VoxelVertex>; SECTION_COUNT];
+
+/// Holds a flag for each vertical section of a chunk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SectionFlags(u16);
+
+impl SectionFlags {
+    /// Creates a new flags with all sections set.
+    pub fn all() -> Self {
+        Self(u16::MAX)
+    }
+
+    /// Creates a new flags with only the section containing the given voxel set.
+    pub fn from_voxel(voxel: IVec3) -> Self {
+        std::iter::once(to_section(voxel)).collect()
+    }
+
+    pub fn set(&mut self, section: usize) {
+        debug_assert!(section < SECTION_COUNT);
+        self.0 |= 1 << section;
+    }
+
+    pub fn is_set(&self, section: usize) -> bool {
+        section < SECTION_COUNT && self.0 & (1 << section) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns an iterator of all set sections, from bottom to top.
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let flags = *self;
+        (0..SECTION_COUNT).filter(move |&s| flags.is_set(s))
+    }
+
+    /// Returns an iterator of all voxels of all set sections.
+    pub fn voxels(&self) -> impl Iterator<Item = IVec3> {
+        self.iter().flat_map(section_voxels)
+    }
+
+    /// Expands the set sections to also include the section above and below each one.
+    ///
+    /// **Returns** the expanded flags.
+    pub fn expanded(&self) -> Self {
+        Self(self.0 | self.0 << 1 | self.0 >> 1)
+    }
+}
+
+impl FromIterator<usize> for SectionFlags {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::default(), |mut flags, section| {
+            flags.set(section);
+            flags
+        })
+    }
+}
+
+impl std::ops::BitOr for SectionFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for SectionFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Faces connectivity of a chunk, one per section.
+pub type ChunkConnectivity = [FacesConnectivity; SECTION_COUNT];
+
+/// Holds which faces of a chunk section can be reached from each other through non-opaque voxels.
+///
+/// This is used to skip rendering of sections which can't be seen, like caves behind solid
+/// ground. Since chunks are created empty, the default value has all faces connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FacesConnectivity(u64);
+
+impl Default for FacesConnectivity {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FacesConnectivity {
+    /// Creates a new connectivity with all faces connected to each other.
+    pub fn all() -> Self {
+        Self((1 << (voxel::SIDE_COUNT * voxel::SIDE_COUNT)) - 1)
+    }
+
+    /// Creates a new connectivity with no faces connected.
+    pub fn none() -> Self {
+        Self(0)
+    }
+
+    /// Connects both given faces to each other.
+    pub fn connect(&mut self, a: voxel::Side, b: voxel::Side) {
+        self.0 |= 1 << Self::bit(a, b) | 1 << Self::bit(b, a);
+    }
+
+    pub fn is_connected(&self, a: voxel::Side, b: voxel::Side) -> bool {
+        self.0 & (1 << Self::bit(a, b)) != 0
+    }
+
+    fn bit(a: voxel::Side, b: voxel::Side) -> usize {
+        a as usize * voxel::SIDE_COUNT + b as usize
+    }
 }
 
 impl PartialEq for Chunk {
@@ -71,6 +190,8 @@ impl ChunkStorageType for u8 {}
 pub struct ChunkStorage<T> {
     main: Vec<T>,
     pub neighborhood: ChunkNeighborhood<T>,
+    #[serde(skip)]
+    dirty: SectionFlags,
 }
 
 impl<T: ChunkStorageType> Default for ChunkStorage<T> {
@@ -83,6 +204,7 @@ impl<T: ChunkStorageType> Clone for ChunkStorage<T> {
     fn clone(&self) -> Self {
         let mut cloned = Self::new(self.main.clone());
         cloned.neighborhood = self.neighborhood.clone();
+        cloned.dirty = self.dirty;
         cloned
     }
 }
@@ -101,6 +223,7 @@ impl<T: ChunkStorageType> ChunkStorage<T> {
         Self {
             main,
             neighborhood: ChunkNeighborhood::default(),
+            dirty: SectionFlags::default(),
         }
     }
 
@@ -109,8 +232,14 @@ impl<T: ChunkStorageType> ChunkStorage<T> {
         self.main[to_index(local)]
     }
 
+    /// Sets the value of the given voxel, flagging it's section as dirty when the value changes.
     pub fn set(&mut self, local: IVec3, value: T) {
-        self.main[to_index(local)] = value;
+        let index = to_index(local);
+
+        if self.main[index] != value {
+            self.main[index] = value;
+            self.dirty.set(to_section(local));
+        }
     }
 
     #[inline]
@@ -128,6 +257,21 @@ impl<T: ChunkStorageType> ChunkStorage<T> {
 
     pub fn set_all(&mut self, value: T) {
         self.main.fill(value);
+        self.dirty = SectionFlags::all();
+    }
+
+    /// Sections which had any value changed since last [`ChunkStorage::clear_dirty_sections`].
+    pub fn dirty_sections(&self) -> SectionFlags {
+        self.dirty
+    }
+
+    pub fn clear_dirty_sections(&mut self) {
+        self.dirty = SectionFlags::default();
+    }
+
+    /// Checks if all voxels of the given section has the given value.
+    pub fn is_section_all(&self, section: usize, value: T) -> bool {
+        section_voxels(section).all(|voxel| self.get(voxel) == value)
     }
 
     pub fn iter(&self) -> std::slice::Iter<'_, T> {
@@ -156,6 +300,7 @@ impl<T: ChunkStorageType> std::ops::Index<usize> for ChunkStorage<T> {
 impl<T: ChunkStorageType> std::ops::IndexMut<usize> for ChunkStorage<T> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         debug_assert!(index < BUFFER_SIZE);
+        self.dirty.set(to_section(from_index(index)));
         &mut self.main[index]
     }
 }
@@ -200,6 +345,20 @@ pub fn voxels() -> impl Iterator<Item = IVec3> {
     ChunkIter::default()
 }
 
+/// **Returns** the section index which contains the given voxel.
+#[inline]
+pub fn to_section(local: IVec3) -> usize {
+    local.y as usize / SECTION_AXIS_SIZE
+}
+
+/// **Returns** an iterator over all voxels of the given section.
+pub fn section_voxels(section: usize) -> impl Iterator<Item = IVec3> {
+    let begin = (section * SECTION_AXIS_SIZE) as i32;
+    let end = begin + SECTION_AXIS_SIZE as i32 - 1;
+
+    query::range_inclusive((0, begin, 0).into(), (X_END, end, Z_END).into())
+}
+
 #[inline]
 pub fn is_within_bounds(local: IVec3) -> bool {
     local.x >= 0
@@ -298,6 +457,11 @@ impl<T: ChunkStorageType> ChunkNeighborhood<T> {
         self.0[side as usize] = Some(neighborhood_side);
     }
 
+    /// Checks if the neighborhood data of the given side was set.
+    pub fn has_side(&self, side: voxel::Side) -> bool {
+        self.0[side as usize].is_some()
+    }
+
     #[inline]
     pub fn get(&self, side: voxel::Side, pos: IVec3) -> Option<T> {
         if let Some(side_vec) = &self.0[side as usize] {
@@ -630,6 +794,9 @@ mod tests {
 
         neighborhood.set(voxel::Side::Up, &top);
 
+        assert!(neighborhood.has_side(voxel::Side::Up));
+        assert!(!neighborhood.has_side(voxel::Side::Down));
+
         assert_eq!(
             neighborhood.get(voxel::Side::Up, (1, 0, 3).into()),
             Some(1.into())
@@ -741,4 +908,72 @@ mod tests {
             "Voxel isn't on the edge, so no neighbor should be returned"
         );
     }
+
+    #[test]
+    fn section_flags() {
+        let mut flags = SectionFlags::default();
+        assert!(flags.is_empty());
+
+        flags.set(0);
+        flags.set(5);
+        assert_eq!(flags.iter().collect::<Vec<_>>(), vec![0, 5]);
+
+        assert_eq!(
+            flags.expanded().iter().collect::<Vec<_>>(),
+            vec![0, 1, 4, 5, 6],
+            "Expanded flags should include vertical neighbors, within bounds"
+        );
+
+        assert_eq!(
+            SectionFlags::from_voxel((3, 17, 2).into()),
+            SectionFlags(0b10)
+        );
+        assert_eq!(SectionFlags::all().iter().count(), SECTION_COUNT);
+        assert_eq!(
+            SectionFlags::from_voxel((0, 0, 0).into()).voxels().count(),
+            X_AXIS_SIZE * SECTION_AXIS_SIZE * Z_AXIS_SIZE
+        );
+    }
+
+    #[test]
+    fn dirty_sections() {
+        let mut kinds = ChunkKind::default();
+        assert!(kinds.dirty_sections().is_empty());
+
+        kinds.set((0, 0, 0).into(), 0.into());
+        assert!(
+            kinds.dirty_sections().is_empty(),
+            "Setting the same value shouldn't flag section as dirty"
+        );
+
+        kinds.set((1, 40, 2).into(), 1.into());
+        assert_eq!(kinds.dirty_sections().iter().collect::<Vec<_>>(), vec![2]);
+
+        kinds.clear_dirty_sections();
+        assert!(kinds.dirty_sections().is_empty());
+
+        assert!(!kinds.is_section_all(2, 0.into()));
+        assert!(kinds.is_section_all(3, 0.into()));
+    }
+
+    #[test]
+    fn faces_connectivity() {
+        use voxel::Side;
+
+        let mut connectivity = FacesConnectivity::none();
+        assert!(!connectivity.is_connected(Side::Up, Side::Down));
+
+        connectivity.connect(Side::Up, Side::Left);
+        assert!(connectivity.is_connected(Side::Up, Side::Left));
+        assert!(
+            connectivity.is_connected(Side::Left, Side::Up),
+            "Connectivity should be symmetric"
+        );
+        assert!(!connectivity.is_connected(Side::Up, Side::Right));
+
+        let all = FacesConnectivity::default();
+        assert!(voxel::SIDES
+            .iter()
+            .all(|&a| voxel::SIDES.iter().all(|&b| all.is_connected(a, b))));
+    }
 }
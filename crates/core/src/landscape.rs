@@ -1,6 +1,115 @@
-pub const HORIZONTAL_RADIUS: usize = 4;
-pub const HORIZONTAL_SIZE: usize = (HORIZONTAL_RADIUS * 2) + 1; // Includes the center one
+use bevy_math::IVec3;
 
-// pub fn is_within_bounds(local: IVec3) -> bool {
-//     math::is_within_cubic_bounds(local, BEGIN, END)
-// }
+/// Default horizontal radius, in chunks, of the full detail landscape.
+pub const DEFAULT_VIEW_DISTANCE: u32 = 4;
+
+/// Max horizontal radius, in chunks, of the full detail landscape. Rendering resources, like the
+/// clip map, grows quadratically with view distance, so it must be bounded.
+pub const MAX_VIEW_DISTANCE: u32 = 32;
+
+/// Horizontal radius, in chunks, of the full detail landscape around the center chunk. It's never
+/// greater than [`MAX_VIEW_DISTANCE`].
+///
+/// This is meant to be used as a resource, so it can be changed at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewDistance(u32);
+
+impl Default for ViewDistance {
+    fn default() -> Self {
+        Self(DEFAULT_VIEW_DISTANCE)
+    }
+}
+
+impl ViewDistance {
+    /// Creates a new view distance, clamped to [`MAX_VIEW_DISTANCE`].
+    pub fn new(distance: u32) -> Self {
+        Self(distance.min(MAX_VIEW_DISTANCE))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.0 as i32
+    }
+
+    /// Number of chunks on each horizontal axis. Includes the center one.
+    pub fn size(&self) -> usize {
+        (self.0 as usize * 2) + 1
+    }
+
+    /// Radius of each level of detail ring, after full detail radius.
+    /// Each level halves the resolution of the previous one.
+    pub fn lod_radius(&self) -> [i32; 2] {
+        [self.radius() + 4, self.radius() + 12]
+    }
+
+    /// Computes the level of detail of a chunk, based on it's horizontal distance to the center
+    /// chunk.
+    ///
+    /// **Returns** 0 for full detail, the level of detail ring or [`None`] if the chunk is too far
+    /// away.
+    pub fn lod_level(&self, center: IVec3, local: IVec3) -> Option<u8> {
+        let distance = (local - center).abs();
+        let distance = distance.x.max(distance.z);
+
+        if distance <= self.radius() {
+            Some(0)
+        } else {
+            self.lod_radius()
+                .iter()
+                .position(|&radius| distance <= radius)
+                .map(|level| level as u8 + 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lod_level() {
+        let center = IVec3::new(1, 0, -2);
+        let view_distance = ViewDistance::default();
+        let lod_radius = view_distance.lod_radius();
+
+        assert_eq!(view_distance.lod_level(center, center), Some(0));
+        assert_eq!(
+            view_distance.lod_level(center, center + IVec3::new(view_distance.radius(), 0, 1)),
+            Some(0)
+        );
+        assert_eq!(
+            view_distance.lod_level(center, center + IVec3::new(0, 0, -lod_radius[0])),
+            Some(1)
+        );
+        assert_eq!(
+            view_distance.lod_level(center, center + IVec3::new(lod_radius[0] + 1, 0, 0)),
+            Some(2)
+        );
+        assert_eq!(
+            view_distance.lod_level(center, center + IVec3::new(lod_radius[1] + 1, 0, 0)),
+            None
+        );
+
+        let view_distance = ViewDistance(1);
+        assert_eq!(
+            view_distance.lod_level(center, center + IVec3::new(2, 0, 0)),
+            Some(1),
+            "Level of detail rings should follow view distance"
+        );
+    }
+
+    #[test]
+    fn new() {
+        assert_eq!(ViewDistance::new(7).get(), 7);
+        assert_eq!(ViewDistance::new(u32::MAX).get(), MAX_VIEW_DISTANCE);
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(ViewDistance(0).size(), 1);
+        assert_eq!(ViewDistance(4).size(), 9);
+    }
+}
@@ -3,6 +3,7 @@
 pub mod chunk;
 pub mod landscape;
 pub mod math;
+pub mod pathfinding;
 pub mod query;
 pub mod voxel;
 mod voxworld;
@@ -0,0 +1,721 @@
+//! Pathfinding over walkable voxels.
+//!
+//! A voxel is walkable when the voxel below it is solid and both the voxel and the one above it
+//! are free, so a character two voxels tall can stand on it. Characters can walk to any side, step
+//! up a single voxel or drop down up to [`MAX_DROP`] voxels.
+//!
+//! Short routes can use [`find_path`], which is a plain A* search. Long routes should use
+//! [`NavGraph`], which splits the world in chunks and searches an abstract graph of chunk
+//! entrances before refining the result into voxels.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy_math::IVec3;
+use bevy_utils::{HashMap, HashSet};
+
+use crate::{chunk, query::KindLookup};
+
+/// Max number of voxels a character can drop down on a single move.
+pub const MAX_DROP: i32 = 3;
+
+/// Max number of nodes expanded by a search before giving up.
+pub const MAX_SEARCH_NODES: usize = 100_000;
+
+/// Cost of moving to a side voxel.
+const WALK_COST: u32 = 10;
+
+/// Additional cost of each voxel moved up or down.
+const CLIMB_COST: u32 = 5;
+
+/// Horizontal directions, which are the only directions characters can walk.
+const SIDES: [IVec3; 4] = [
+    IVec3::new(1, 0, 0),
+    IVec3::new(-1, 0, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(0, 0, -1),
+];
+
+/// Checks if a character can stand at the given world voxel.
+pub fn is_walkable(lookup: &impl KindLookup, voxel: IVec3) -> bool {
+    lookup.is_solid(voxel - IVec3::Y)
+        && !lookup.is_solid(voxel)
+        && !lookup.is_solid(voxel + IVec3::Y)
+}
+
+/// **Returns** all walkable voxels which can be reached on a single move from the given walkable
+/// voxel and the cost of each move.
+pub fn moves(lookup: &impl KindLookup, voxel: IVec3) -> Vec<(IVec3, u32)> {
+    let mut result = vec![];
+
+    for dir in SIDES {
+        let side = voxel + dir;
+
+        if is_walkable(lookup, side) {
+            result.push((side, WALK_COST));
+        } else if lookup.is_solid(side) {
+            // Step up, which needs room above the head to jump
+            let up = side + IVec3::Y;
+
+            if !lookup.is_solid(voxel + IVec3::Y * 2) && is_walkable(lookup, up) {
+                result.push((up, WALK_COST + CLIMB_COST));
+            }
+        } else if !lookup.is_solid(side + IVec3::Y) {
+            // Drop down, falling through free voxels until reaching the ground
+            for depth in 1..=MAX_DROP {
+                let below = side - IVec3::Y * depth;
+
+                if lookup.is_solid(below) {
+                    break;
+                }
+
+                if lookup.is_solid(below - IVec3::Y) {
+                    result.push((below, WALK_COST + CLIMB_COST * depth as u32));
+                    break;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Finds the cheapest path between two walkable voxels, using A*. Gives up after expanding
+/// [`MAX_SEARCH_NODES`] voxels, so prefer [`NavGraph::find_path`] on long routes.
+///
+/// **Returns** world voxels of the path, including both `start` and `goal`, or `None` if there is
+/// no path.
+pub fn find_path(lookup: &impl KindLookup, start: IVec3, goal: IVec3) -> Option<Vec<IVec3>> {
+    if !is_walkable(lookup, start) || !is_walkable(lookup, goal) {
+        return None;
+    }
+
+    search(lookup, start, goal, None).map(|(path, _)| path)
+}
+
+fn chunk_of(voxel: IVec3) -> IVec3 {
+    chunk::to_local(voxel.as_vec3())
+}
+
+/// Lower bound of the cost between two voxels. Each move goes a single voxel to a side and costs
+/// [`CLIMB_COST`] for each voxel it goes up or down.
+fn estimate(from: IVec3, to: IVec3) -> u32 {
+    let distance = (to - from).abs();
+
+    (distance.x + distance.z) as u32 * WALK_COST + distance.y as u32 * CLIMB_COST
+}
+
+/// A* search from `start` to `goal`. If `within` is set, only voxels of that chunk are visited.
+///
+/// **Returns** the path, including both `start` and `goal`, and its cost.
+fn search(
+    lookup: &impl KindLookup,
+    start: IVec3,
+    goal: IVec3,
+    within: Option<IVec3>,
+) -> Option<(Vec<IVec3>, u32)> {
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::default();
+    let mut came_from = HashMap::default();
+    let mut expanded = 0;
+
+    costs.insert(start, 0);
+    open.push(Reverse((estimate(start, goal), 0, start.to_array())));
+
+    while let Some(Reverse((_, cost, voxel))) = open.pop() {
+        let voxel = IVec3::from(voxel);
+
+        if voxel == goal {
+            return Some((rebuild_path(&came_from, goal), cost));
+        }
+
+        // Stale entry of a voxel which was reached by a cheaper path later on
+        if cost > costs[&voxel] {
+            continue;
+        }
+
+        expanded += 1;
+        if expanded > MAX_SEARCH_NODES {
+            return None;
+        }
+
+        for (next, step) in moves(lookup, voxel) {
+            if within.map_or(false, |local| chunk_of(next) != local) {
+                continue;
+            }
+
+            let next_cost = cost + step;
+
+            if costs.get(&next).map_or(true, |&c| next_cost < c) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, voxel);
+                open.push(Reverse((
+                    next_cost + estimate(next, goal),
+                    next_cost,
+                    next.to_array(),
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+fn rebuild_path(came_from: &HashMap<IVec3, IVec3>, goal: IVec3) -> Vec<IVec3> {
+    let mut path = vec![goal];
+    let mut current = goal;
+
+    while let Some(&previous) = came_from.get(&current) {
+        path.push(previous);
+        current = previous;
+    }
+
+    path.reverse();
+    path
+}
+
+/// Dijkstra search from `start`, visiting only voxels of the given chunk.
+///
+/// **Returns** the cost of the cheapest path to each of the given targets which was reached.
+fn costs_within(
+    lookup: &impl KindLookup,
+    start: IVec3,
+    local: IVec3,
+    targets: &[IVec3],
+) -> Vec<(IVec3, u32)> {
+    let mut remaining = targets.iter().copied().collect::<HashSet<_>>();
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::default();
+    let mut result = vec![];
+
+    remaining.remove(&start);
+    costs.insert(start, 0);
+    open.push(Reverse((0, start.to_array())));
+
+    while let Some(Reverse((cost, voxel))) = open.pop() {
+        if remaining.is_empty() {
+            break;
+        }
+
+        let voxel = IVec3::from(voxel);
+
+        if cost > costs[&voxel] {
+            continue;
+        }
+
+        if remaining.remove(&voxel) {
+            result.push((voxel, cost));
+        }
+
+        for (next, step) in moves(lookup, voxel) {
+            if chunk_of(next) != local {
+                continue;
+            }
+
+            let next_cost = cost + step;
+
+            if costs.get(&next).map_or(true, |&c| next_cost < c) {
+                costs.insert(next, next_cost);
+                open.push(Reverse((next_cost, next.to_array())));
+            }
+        }
+    }
+
+    result
+}
+
+/// **Returns** the pairs of walkable voxels, one on each chunk, which connects two neighbor
+/// chunks. Pairs are merged when they are next to each other along the border, so a wide opening
+/// has a single pair on its middle.
+///
+/// The same pairs are returned no matter the order of the given chunks, so both chunks agrees on
+/// which voxels are entrances.
+fn portals(lookup: &impl KindLookup, a: IVec3, b: IVec3) -> Vec<(IVec3, IVec3)> {
+    if a.to_array() > b.to_array() {
+        return portals(lookup, b, a)
+            .into_iter()
+            .map(|(pa, pb)| (pb, pa))
+            .collect();
+    }
+
+    let dir = b - a;
+    let along = if dir.x != 0 { 2 } else { 0 };
+    let size = chunk::X_AXIS_SIZE as i32;
+
+    let border = move |local: IVec3, facing: IVec3| {
+        let origin = chunk::to_world(local).as_ivec3();
+
+        (0..size).flat_map(move |i| {
+            (0..chunk::Y_AXIS_SIZE as i32).map(move |y| {
+                let mut voxel = IVec3::new(0, y, 0);
+                voxel[along] = i;
+
+                // Local coordinate on the side facing the other chunk
+                let side = if dir.x != 0 { 0 } else { 2 };
+                voxel[side] = if facing[side] > 0 { size - 1 } else { 0 };
+
+                origin + voxel
+            })
+        })
+    };
+
+    let mut pairs = HashSet::default();
+
+    for voxel in border(a, dir).filter(|&v| is_walkable(lookup, v)) {
+        for (next, _) in moves(lookup, voxel) {
+            if chunk_of(next) == b {
+                pairs.insert((voxel.to_array(), next.to_array()));
+            }
+        }
+    }
+
+    for voxel in border(b, -dir).filter(|&v| is_walkable(lookup, v)) {
+        for (next, _) in moves(lookup, voxel) {
+            if chunk_of(next) == a {
+                pairs.insert((next.to_array(), voxel.to_array()));
+            }
+        }
+    }
+
+    let mut pairs = pairs.into_iter().collect::<Vec<_>>();
+    pairs.sort_by_key(|(pa, pb)| (pa[1], pb[1], pa[along], pb[along]));
+
+    // Split pairs in runs of the same heights which are next to each other along the border
+    let mut runs: Vec<Vec<([i32; 3], [i32; 3])>> = vec![];
+    for pair in pairs {
+        match runs.last_mut() {
+            Some(run)
+                if run.last().map_or(false, |(pa, pb)| {
+                    pa[1] == pair.0[1]
+                        && pb[1] == pair.1[1]
+                        && pair.0[along] - pa[along] == 1
+                        && pair.1[along] - pb[along] == 1
+                }) =>
+            {
+                run.push(pair)
+            }
+            _ => runs.push(vec![pair]),
+        }
+    }
+
+    runs.into_iter()
+        .map(|run| {
+            let (pa, pb) = run[run.len() / 2];
+            (pa.into(), pb.into())
+        })
+        .collect()
+}
+
+/// Abstract graph data of a single chunk.
+#[derive(Default, Debug)]
+struct Cluster {
+    /// Entrances of this chunk and the moves from each of them into neighbor chunks entrances.
+    entrances: HashMap<IVec3, Vec<(IVec3, u32)>>,
+    /// Cost from each entrance to other entrances of this chunk, computed only when needed.
+    intra: HashMap<IVec3, Vec<(IVec3, u32)>>,
+}
+
+impl Cluster {
+    fn build(lookup: &impl KindLookup, local: IVec3) -> Self {
+        let mut entrances: HashMap<IVec3, Vec<(IVec3, u32)>> = HashMap::default();
+
+        for dir in SIDES {
+            for (entrance, other) in portals(lookup, local, local + dir) {
+                let edges = entrances.entry(entrance).or_default();
+
+                if let Some(&(_, cost)) = moves(lookup, entrance)
+                    .iter()
+                    .find(|(next, _)| *next == other)
+                {
+                    edges.push((other, cost));
+                }
+            }
+        }
+
+        Self {
+            entrances,
+            intra: HashMap::default(),
+        }
+    }
+}
+
+/// Hierarchical pathfinding graph. Each chunk is a cluster, connected to its neighbors through
+/// entrances at its borders. Long routes are searched over entrances first and only then refined
+/// into voxels, one chunk at a time.
+///
+/// Clusters are built lazily when a search needs them and kept until they are invalidated, so
+/// [`NavGraph::invalidate`] must be called whenever a chunk is loaded, unloaded or has its voxels
+/// changed. Paths found are always valid, but may be a bit longer than the cheapest path.
+#[derive(Default, Debug)]
+pub struct NavGraph {
+    clusters: HashMap<IVec3, Cluster>,
+}
+
+impl NavGraph {
+    /// Discards the abstract graph of the given chunk and of its neighbors, since entrances
+    /// depends on voxels of both sides of a border.
+    pub fn invalidate(&mut self, local: IVec3) {
+        self.clusters.remove(&local);
+
+        for dir in SIDES {
+            self.clusters.remove(&(local + dir));
+        }
+    }
+
+    /// Discards the abstract graph of all chunks.
+    pub fn clear(&mut self) {
+        self.clusters.clear();
+    }
+
+    /// Checks if the abstract graph of the given chunk is built.
+    pub fn is_built(&self, local: IVec3) -> bool {
+        self.clusters.contains_key(&local)
+    }
+
+    fn cluster(&mut self, lookup: &impl KindLookup, local: IVec3) -> &mut Cluster {
+        self.clusters
+            .entry(local)
+            .or_insert_with(|| Cluster::build(lookup, local))
+    }
+
+    /// **Returns** the abstract edges leaving the given voxel, which is either the start of the
+    /// search or an entrance.
+    fn edges(&mut self, lookup: &impl KindLookup, voxel: IVec3) -> Vec<(IVec3, u32)> {
+        let local = chunk_of(voxel);
+        let cluster = self.cluster(lookup, local);
+
+        let mut edges = cluster.entrances.get(&voxel).cloned().unwrap_or_default();
+
+        let intra = match cluster.intra.get(&voxel) {
+            Some(intra) => intra.clone(),
+            None => {
+                let targets = cluster.entrances.keys().copied().collect::<Vec<_>>();
+                let intra = costs_within(lookup, voxel, local, &targets);
+
+                if cluster.entrances.contains_key(&voxel) {
+                    cluster.intra.insert(voxel, intra.clone());
+                }
+
+                intra
+            }
+        };
+
+        edges.extend(intra);
+        edges
+    }
+
+    /// Finds a path between two walkable voxels, which may be on distant chunks.
+    ///
+    /// **Returns** world voxels of the path, including both `start` and `goal`, or `None` if there
+    /// is no path.
+    pub fn find_path(
+        &mut self,
+        lookup: &impl KindLookup,
+        start: IVec3,
+        goal: IVec3,
+    ) -> Option<Vec<IVec3>> {
+        if !is_walkable(lookup, start) || !is_walkable(lookup, goal) {
+            return None;
+        }
+
+        let goal_chunk = chunk_of(goal);
+
+        if chunk_of(start) == goal_chunk {
+            if let Some((path, _)) = search(lookup, start, goal, Some(goal_chunk)) {
+                return Some(path);
+            }
+        }
+
+        // Paths from entrances of the goal chunk to the goal, found while searching
+        let mut to_goal = HashMap::default();
+
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::default();
+        let mut came_from = HashMap::default();
+        let mut expanded = 0;
+
+        costs.insert(start, 0);
+        open.push(Reverse((estimate(start, goal), 0, start.to_array())));
+
+        while let Some(Reverse((_, cost, voxel))) = open.pop() {
+            let voxel = IVec3::from(voxel);
+
+            if voxel == goal {
+                let nodes = rebuild_path(&came_from, goal);
+                return Some(refine(lookup, &nodes, to_goal));
+            }
+
+            if cost > costs[&voxel] {
+                continue;
+            }
+
+            expanded += 1;
+            if expanded > MAX_SEARCH_NODES {
+                return None;
+            }
+
+            let mut edges = self.edges(lookup, voxel);
+
+            if chunk_of(voxel) == goal_chunk {
+                if let Some((path, path_cost)) = search(lookup, voxel, goal, Some(goal_chunk)) {
+                    edges.push((goal, path_cost));
+                    to_goal.insert(voxel, path);
+                }
+            }
+
+            for (next, step) in edges {
+                let next_cost = cost + step;
+
+                if costs.get(&next).map_or(true, |&c| next_cost < c) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, voxel);
+                    open.push(Reverse((
+                        next_cost + estimate(next, goal),
+                        next_cost,
+                        next.to_array(),
+                    )));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Converts abstract path nodes into world voxels. Nodes on different chunks are a single move
+/// apart, while nodes on the same chunk are connected by a path within that chunk.
+fn refine(
+    lookup: &impl KindLookup,
+    nodes: &[IVec3],
+    mut to_goal: HashMap<IVec3, Vec<IVec3>>,
+) -> Vec<IVec3> {
+    let goal = *nodes.last().unwrap();
+    let mut path = vec![nodes[0]];
+
+    for pair in nodes.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let local = chunk_of(from);
+
+        if local != chunk_of(to) {
+            path.push(to);
+            continue;
+        }
+
+        let segment = match to_goal.remove(&from) {
+            Some(segment) if to == goal => Some(segment),
+            _ => search(lookup, from, to, Some(local)).map(|(segment, _)| segment),
+        };
+
+        // Abstract edges are built from paths within the chunk, so there is always a segment
+        path.extend(segment.unwrap().into_iter().skip(1));
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{chunk::Chunk, VoxWorld};
+
+    use super::*;
+
+    /// Creates a world with the given chunks, with a flat ground whose walkable voxels are at y = 1
+    fn flat_world(chunks: &[IVec3]) -> VoxWorld {
+        let mut world = VoxWorld::default();
+
+        for &local in chunks {
+            let mut chunk = Chunk::default();
+
+            for x in 0..chunk::X_AXIS_SIZE as i32 {
+                for z in 0..chunk::Z_AXIS_SIZE as i32 {
+                    chunk.kinds.set((x, 0, z).into(), 1.into());
+                }
+            }
+
+            world.add(local, chunk);
+        }
+
+        world
+    }
+
+    fn set_solid(world: &mut VoxWorld, voxels: impl Iterator<Item = IVec3>) {
+        for voxel in voxels {
+            let local = chunk_of(voxel);
+            let voxel = crate::voxel::to_local(voxel.as_vec3());
+
+            world.get_mut(local).unwrap().kinds.set(voxel, 1.into());
+        }
+    }
+
+    /// Checks if path starts and ends at the given voxels and each voxel is a single move apart.
+    fn assert_path(world: &VoxWorld, path: &[IVec3], start: IVec3, goal: IVec3) {
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+
+        for pair in path.windows(2) {
+            assert!(
+                moves(world, pair[0])
+                    .iter()
+                    .any(|(next, _)| *next == pair[1]),
+                "Invalid move from {} to {}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn is_walkable() {
+        let mut world = flat_world(&[IVec3::ZERO]);
+        set_solid(&mut world, [IVec3::new(3, 2, 3)].into_iter());
+
+        assert!(super::is_walkable(&world, (1, 1, 1).into()));
+        assert!(!super::is_walkable(&world, (1, 2, 1).into()), "No ground");
+        assert!(!super::is_walkable(&world, (1, 0, 1).into()), "Solid");
+        assert!(
+            !super::is_walkable(&world, (3, 1, 3).into()),
+            "No head room"
+        );
+        assert!(
+            !super::is_walkable(&world, (-1, 1, 1).into()),
+            "Missing chunks are solid"
+        );
+    }
+
+    #[test]
+    fn moves() {
+        let mut world = flat_world(&[IVec3::ZERO]);
+        // A step at x = 5, a pit at x = 3 and a pillar blocking z = 5
+        set_solid(
+            &mut world,
+            [
+                IVec3::new(5, 1, 4),
+                IVec3::new(4, 1, 5),
+                IVec3::new(4, 2, 5),
+            ]
+            .into_iter(),
+        );
+        world
+            .get_mut(IVec3::ZERO)
+            .unwrap()
+            .kinds
+            .set((3, 0, 4).into(), 0.into());
+
+        let mut moves = super::moves(&world, (4, 1, 4).into());
+        moves.sort_by_key(|(voxel, _)| voxel.to_array());
+
+        assert_eq!(
+            moves,
+            vec![
+                ((3, 0, 4).into(), WALK_COST + CLIMB_COST),
+                ((4, 1, 3).into(), WALK_COST),
+                ((5, 2, 4).into(), WALK_COST + CLIMB_COST),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_path() {
+        let mut world = flat_world(&[IVec3::ZERO]);
+        // Wall along x = 5, except on z = 10
+        set_solid(
+            &mut world,
+            (0..16)
+                .filter(|&z| z != 10)
+                .flat_map(|z| [IVec3::new(5, 1, z), IVec3::new(5, 2, z)]),
+        );
+
+        let (start, goal) = (IVec3::new(2, 1, 2), IVec3::new(8, 1, 2));
+        let path = super::find_path(&world, start, goal).unwrap();
+
+        assert_path(&world, &path, start, goal);
+        assert!(path.contains(&IVec3::new(5, 1, 10)), "Should pass the gap");
+
+        set_solid(
+            &mut world,
+            [IVec3::new(5, 1, 10), IVec3::new(5, 2, 10)].into_iter(),
+        );
+        assert_eq!(super::find_path(&world, start, goal), None);
+        assert_eq!(super::find_path(&world, start, (5, 1, 2).into()), None);
+    }
+
+    #[test]
+    fn portals() {
+        let mut world = flat_world(&[IVec3::ZERO, IVec3::X]);
+        // Wall along the border, except on z = 2..=4 and z = 10. It's too high to drop down from
+        set_solid(
+            &mut world,
+            (0..16)
+                .filter(|&z| !(2..=4).contains(&z) && z != 10)
+                .flat_map(|z| (1..=4).map(move |y| IVec3::new(16, y, z))),
+        );
+
+        let portals = super::portals(&world, IVec3::ZERO, IVec3::X);
+        assert_eq!(
+            portals,
+            vec![
+                ((15, 1, 3).into(), (16, 1, 3).into()),
+                ((15, 1, 10).into(), (16, 1, 10).into()),
+            ]
+        );
+
+        let swapped = super::portals(&world, IVec3::X, IVec3::ZERO)
+            .into_iter()
+            .map(|(a, b)| (b, a))
+            .collect::<Vec<_>>();
+        assert_eq!(portals, swapped, "Both chunks should agree on portals");
+    }
+
+    #[test]
+    fn nav_graph_find_path() {
+        let chunks = (0..3).map(|x| IVec3::new(x, 0, 0)).collect::<Vec<_>>();
+        let mut world = flat_world(&chunks);
+        // Wall on the middle chunk, except on z = 13
+        set_solid(
+            &mut world,
+            (0..16)
+                .filter(|&z| z != 13)
+                .flat_map(|z| [IVec3::new(24, 1, z), IVec3::new(24, 2, z)]),
+        );
+
+        let mut graph = NavGraph::default();
+        let (start, goal) = (IVec3::new(1, 1, 2), IVec3::new(45, 1, 2));
+        let path = graph.find_path(&world, start, goal).unwrap();
+
+        assert_path(&world, &path, start, goal);
+        assert!(path.contains(&IVec3::new(24, 1, 13)), "Should pass the gap");
+        assert!(chunks.iter().all(|&local| graph.is_built(local)));
+
+        // Paths within a single chunk doesn't need the abstract graph
+        let path = NavGraph::default()
+            .find_path(&world, start, (10, 1, 10).into())
+            .unwrap();
+        assert_path(&world, &path, start, (10, 1, 10).into());
+    }
+
+    #[test]
+    fn nav_graph_invalidate() {
+        let chunks = (0..3).map(|x| IVec3::new(x, 0, 0)).collect::<Vec<_>>();
+        let mut world = flat_world(&chunks);
+        set_solid(
+            &mut world,
+            (0..16)
+                .filter(|&z| z != 13)
+                .flat_map(|z| [IVec3::new(24, 1, z), IVec3::new(24, 2, z)]),
+        );
+
+        let mut graph = NavGraph::default();
+        let (start, goal) = (IVec3::new(1, 1, 2), IVec3::new(45, 1, 2));
+        assert!(graph.find_path(&world, start, goal).is_some());
+
+        set_solid(
+            &mut world,
+            [IVec3::new(24, 1, 13), IVec3::new(24, 2, 13)].into_iter(),
+        );
+        graph.invalidate(IVec3::X);
+
+        assert!(!graph.is_built(IVec3::ZERO));
+        assert!(!graph.is_built(IVec3::X));
+        assert!(!graph.is_built(IVec3::new(2, 0, 0)));
+        assert_eq!(graph.find_path(&world, start, goal), None);
+    }
+}
@@ -1,6 +1,6 @@
 use bevy_math::{IVec3, Vec3};
 
-use crate::{chunk, math, voxel};
+use crate::{chunk, math, voxel, VoxWorld};
 
 /// An interator which produced a finite number of [`IVec3`] ranging from `begin` until `end`
 /// exclusive
@@ -213,6 +213,232 @@ fn voxel_raycast(
     (visited_locals, visited_positions, visited_normals)
 }
 
+/// Provides voxel kinds by world voxel coordinates, across chunk borders. Used by AABB queries,
+/// like [`sweep_aabb`].
+pub trait KindLookup {
+    /// **Returns** the kind of the voxel at the given world voxel coordinates or `None` if its
+    /// chunk doesn't exists.
+    fn kind_at(&self, world: IVec3) -> Option<voxel::Kind>;
+
+    /// Checks if the voxel at the given world voxel coordinates blocks movement. Voxels of chunks
+    /// which doesn't exists are solid, so nothing moves into unloaded areas.
+    fn is_solid(&self, world: IVec3) -> bool {
+        self.kind_at(world).map_or(true, |kind| !kind.is_none())
+    }
+}
+
+impl KindLookup for VoxWorld {
+    fn kind_at(&self, world: IVec3) -> Option<voxel::Kind> {
+        let world = world.as_vec3();
+
+        self.get(chunk::to_local(world))
+            .map(|chunk| chunk.kinds.get(voxel::to_local(world)))
+    }
+}
+
+impl<F> KindLookup for F
+where
+    F: Fn(IVec3) -> Option<voxel::Kind>,
+{
+    fn kind_at(&self, world: IVec3) -> Option<voxel::Kind> {
+        self(world)
+    }
+}
+
+/// Axis aligned bounding box in world coordinates.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min: min.min(max),
+            max: min.max(max),
+        }
+    }
+
+    pub fn from_center(center: Vec3, half_extents: Vec3) -> Self {
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) / 2.0
+    }
+
+    /// **Returns** a copy of this box moved by the given offset.
+    pub fn translate(&self, offset: Vec3) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// **Returns** the smallest box containing both this box and the given one.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// **Returns** an iterator of all voxels which this box overlaps. Voxels which only touches
+    /// this box faces aren't included.
+    pub fn voxels(&self) -> impl Iterator<Item = IVec3> {
+        let begin = math::floor(self.min);
+        let end = self.max.ceil().as_ivec3() - IVec3::ONE;
+
+        range_inclusive(begin, end)
+    }
+}
+
+/// Result of [`sweep_aabb`].
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    /// Time of impact, from `0.0` at the start of motion to `1.0` at the end of motion.
+    pub time: f32,
+    /// Normal of the hit voxel face.
+    pub normal: IVec3,
+    /// World coordinates of the hit voxel.
+    pub voxel: IVec3,
+}
+
+/// Max distance, in voxels, which [`penetration_resolve`] moves a box.
+pub const MAX_PENETRATION_RESOLVE: f32 = 16.0;
+
+/// Gap left between a box moved by [`penetration_resolve`] and the voxels it was overlapping.
+const PENETRATION_SKIN: f32 = 0.001;
+
+/// Moves the given box by `motion`, looking for the first solid voxel it hits. Voxels already
+/// overlapping the box at the start of motion are ignored, see [`penetration_resolve`].
+///
+/// Boxes which touches a voxel face and moves towards it hits it at time zero, while boxes which
+/// slides along a voxel face never hits it.
+///
+/// **Returns** the first hit or `None` if the box can move freely.
+pub fn sweep_aabb(aabb: &Aabb, motion: Vec3, lookup: &impl KindLookup) -> Option<SweepHit> {
+    if motion == Vec3::ZERO {
+        return None;
+    }
+
+    let mut hit: Option<SweepHit> = None;
+
+    for voxel in aabb.union(&aabb.translate(motion)).voxels() {
+        if !lookup.is_solid(voxel) {
+            continue;
+        }
+
+        let voxel_min = voxel.as_vec3();
+        let voxel_max = voxel_min + Vec3::ONE;
+
+        let mut entry = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        let mut normal = IVec3::ZERO;
+
+        for axis in 0..3 {
+            let (axis_entry, axis_exit) = if motion[axis] > 0.0 {
+                (
+                    (voxel_min[axis] - aabb.max[axis]) / motion[axis],
+                    (voxel_max[axis] - aabb.min[axis]) / motion[axis],
+                )
+            } else if motion[axis] < 0.0 {
+                (
+                    (voxel_max[axis] - aabb.min[axis]) / motion[axis],
+                    (voxel_min[axis] - aabb.max[axis]) / motion[axis],
+                )
+            } else if aabb.max[axis] <= voxel_min[axis] || aabb.min[axis] >= voxel_max[axis] {
+                // Never overlaps on this axis
+                (f32::INFINITY, f32::NEG_INFINITY)
+            } else {
+                (f32::NEG_INFINITY, f32::INFINITY)
+            };
+
+            if axis_entry > entry {
+                entry = axis_entry;
+                normal = IVec3::ZERO;
+                normal[axis] = -motion[axis].signum() as i32;
+            }
+
+            exit = exit.min(axis_exit);
+        }
+
+        if entry < exit && (0.0..=1.0).contains(&entry) && hit.map_or(true, |h| entry < h.time) {
+            hit = Some(SweepHit {
+                time: entry,
+                normal,
+                voxel,
+            });
+        }
+    }
+
+    hit
+}
+
+/// Checks if the given box overlaps any solid voxel. Touching a voxel face isn't overlapping.
+pub fn overlaps(aabb: &Aabb, lookup: &impl KindLookup) -> bool {
+    aabb.voxels().any(|voxel| lookup.is_solid(voxel))
+}
+
+/// Finds the smallest translation along a single axis which moves the given box out of all solid
+/// voxels. Upwards translation is preferred when many translations have the same length.
+///
+/// **Returns** the translation, which is zero when the box doesn't overlaps anything, or `None` if
+/// the box can't be freed within [`MAX_PENETRATION_RESOLVE`] voxels.
+pub fn penetration_resolve(aabb: &Aabb, lookup: &impl KindLookup) -> Option<Vec3> {
+    if !overlaps(aabb, lookup) {
+        return Some(Vec3::ZERO);
+    }
+
+    // Axis and direction of each translation, in order of preference
+    const DIRECTIONS: [(usize, f32); 6] = [
+        (1, 1.0),
+        (0, 1.0),
+        (0, -1.0),
+        (2, 1.0),
+        (2, -1.0),
+        (1, -1.0),
+    ];
+
+    DIRECTIONS
+        .iter()
+        .filter_map(|&(axis, sign)| {
+            let mut offset = Vec3::ZERO;
+
+            while offset[axis].abs() <= MAX_PENETRATION_RESOLVE {
+                let moved = aabb.translate(offset);
+                let solids = moved
+                    .voxels()
+                    .filter(|&voxel| lookup.is_solid(voxel))
+                    .map(|voxel| voxel[axis]);
+
+                // Push the box just past the farthest solid voxel on the direction. A small gap
+                // is added, so float errors never leaves the box touching the voxel
+                let push = if sign > 0.0 {
+                    solids.max().map(|max| (max + 1) as f32 - moved.min[axis])
+                } else {
+                    solids.min().map(|min| moved.max[axis] - min as f32)
+                };
+
+                match push {
+                    Some(push) => offset[axis] += sign * (push + PENETRATION_SKIN),
+                    None => return Some(offset),
+                }
+            }
+
+            None
+        })
+        .fold(None, |best: Option<Vec3>, offset| match best {
+            Some(best) if best.length_squared() <= offset.length_squared() => Some(best),
+            _ => Some(offset),
+        })
+}
+
 #[cfg(test)]
 mod test {
     use std::vec;
@@ -294,4 +520,123 @@ mod test {
             );
         }
     }
+
+    /// Solid voxels below y = 0
+    fn floor(voxel: IVec3) -> Option<voxel::Kind> {
+        Some(if voxel.y < 0 {
+            1.into()
+        } else {
+            voxel::Kind::none()
+        })
+    }
+
+    #[test]
+    fn aabb_voxels() {
+        let aabb = Aabb::new((1.5, 2.0, 1.5).into(), (0.5, 0.0, 0.5).into());
+
+        assert_eq!(aabb.min, Vec3::new(0.5, 0.0, 0.5));
+        assert_eq!(aabb.voxels().count(), 8);
+        assert!(aabb
+            .voxels()
+            .all(|v| v.cmpge(IVec3::ZERO).all() && v.cmple(IVec3::ONE).all()));
+
+        let aabb = Aabb::from_center((0.5, 0.5, 0.5).into(), Vec3::splat(0.5));
+        assert_eq!(
+            aabb.voxels().collect::<Vec<_>>(),
+            vec![IVec3::ZERO],
+            "Touched voxels shouldn't be included"
+        );
+    }
+
+    #[test]
+    fn sweep_aabb() {
+        let aabb = Aabb::new((0.2, 1.0, 0.2).into(), (0.8, 3.0, 0.8).into());
+
+        assert_eq!(
+            super::sweep_aabb(&aabb, Vec3::new(0.0, -5.0, 0.0), &floor),
+            Some(SweepHit {
+                time: 0.2,
+                normal: IVec3::Y,
+                voxel: (0, -1, 0).into(),
+            })
+        );
+
+        let resting = aabb.translate(Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(
+            super::sweep_aabb(&resting, Vec3::new(10.0, 0.0, -3.0), &floor),
+            None,
+            "Sliding along a face shouldn't hit"
+        );
+        assert_eq!(
+            super::sweep_aabb(&resting, Vec3::new(1.0, -1.0, 0.0), &floor).map(|h| h.time),
+            Some(0.0),
+            "Moving towards a touching face should hit at once"
+        );
+        assert_eq!(
+            super::sweep_aabb(&aabb, Vec3::new(0.0, -0.5, 0.0), &floor),
+            None
+        );
+    }
+
+    #[test]
+    fn sweep_aabb_across_chunks() {
+        let mut world = VoxWorld::default();
+        world.add((0, 0, 0).into(), Default::default());
+        world.add((1, 0, 0).into(), Default::default());
+        world
+            .get_mut((1, 0, 0).into())
+            .unwrap()
+            .kinds
+            .set((0, 5, 0).into(), 1.into());
+
+        let aabb = Aabb::from_center((14.5, 5.5, 0.5).into(), Vec3::splat(0.25));
+        let hit = super::sweep_aabb(&aabb, Vec3::new(5.0, 0.0, 0.0), &world).unwrap();
+
+        assert_eq!(hit.voxel, (16, 5, 0).into());
+        assert_eq!(hit.normal, IVec3::new(-1, 0, 0));
+        assert!((hit.time - 0.25).abs() < f32::EPSILON);
+
+        assert!(
+            super::sweep_aabb(&aabb, Vec3::new(0.0, 0.0, -5.0), &world).is_some(),
+            "Voxels of missing chunks should be solid"
+        );
+    }
+
+    #[test]
+    fn overlaps() {
+        let aabb = Aabb::new((0.0, 0.0, 0.0).into(), (1.0, 2.0, 1.0).into());
+
+        assert!(!super::overlaps(&aabb, &floor));
+        assert!(super::overlaps(
+            &aabb.translate(Vec3::new(0.0, -0.1, 0.0)),
+            &floor
+        ));
+    }
+
+    #[test]
+    fn penetration_resolve() {
+        let aabb = Aabb::new((0.2, -0.5, 0.2).into(), (0.8, 1.5, 0.8).into());
+
+        let offset = super::penetration_resolve(&aabb, &floor).unwrap();
+        assert_eq!(offset.x, 0.0);
+        assert_eq!(offset.z, 0.0);
+        assert!((offset.y - 0.5).abs() < 0.01);
+        assert!(!super::overlaps(&aabb.translate(offset), &floor));
+
+        assert_eq!(
+            super::penetration_resolve(&aabb.translate(Vec3::Y), &floor),
+            Some(Vec3::ZERO)
+        );
+
+        // An infinite wall on x = 0 can only be left sideways, using the shortest way
+        let wall = |voxel: IVec3| Some(voxel::Kind::from((voxel.x == 0) as u16));
+        let aabb = Aabb::new((0.2, 5.0, 0.0).into(), (0.6, 6.0, 1.0).into());
+
+        let offset = super::penetration_resolve(&aabb, &wall).unwrap();
+        assert!((offset.x + 0.6).abs() < 0.01);
+        assert_eq!(offset.y, 0.0);
+
+        let solid = |_: IVec3| Some(voxel::Kind::from(1));
+        assert_eq!(super::penetration_resolve(&aabb, &solid), None);
+    }
 }
@@ -82,6 +82,12 @@ pub struct KindsDescs {
 }
 
 impl KindsDescs {
+    /// Checks if there is a description of the given [`Kind`]. Kinds without description panics
+    /// when used, so kinds received from outside should be checked first.
+    pub fn contains(&self, kind: Kind) -> bool {
+        self.descriptions.iter().any(|desc| desc.id == kind.0)
+    }
+
     /// Counts how many tiles there are on a single texture atlas row
     pub fn count_tiles(&self) -> u16 {
         self.atlas_size / self.atlas_tile_size
@@ -243,4 +249,18 @@ mod tests {
 
         let _: KindsDescs = from_reader(f).unwrap();
     }
+
+    #[test]
+    fn contains() {
+        let descs = KindsDescs {
+            descriptions: vec![KindDescItem {
+                id: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert!(descs.contains(1.into()));
+        assert!(!descs.contains(2.into()));
+    }
 }
@@ -1,4 +1,4 @@
-use bevy_math::{IVec3, Vec2, Vec3};
+use bevy_math::{IVec2, IVec3, Vec3};
 use serde::{Deserialize, Serialize};
 
 use crate::{chunk::ChunkStorage, math};
@@ -37,6 +37,7 @@ pub struct Light(u8);
 
 impl Light {
     pub const MAX_NATURAL_INTENSITY: u8 = 15;
+    pub const MAX_ARTIFICIAL_INTENSITY: u8 = 15;
 
     pub fn natural(intensity: u8) -> Self {
         let mut light = Light::default();
@@ -115,6 +116,18 @@ impl Side {
         }
     }
 
+    /// **Returns** the side facing the opposite direction.
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Right => Side::Left,
+            Side::Left => Side::Right,
+            Side::Up => Side::Down,
+            Side::Down => Side::Up,
+            Side::Front => Side::Back,
+            Side::Back => Side::Front,
+        }
+    }
+
     #[inline]
     pub fn from_dir(dir: IVec3) -> Side {
         if dir == IVec3::X {
@@ -199,19 +212,156 @@ pub struct VoxelFace {
     pub vertices: [IVec3; 4],
     pub side: Side,
     pub kind: Kind,
-    pub light: [f32; 4],
-    pub voxel: [u32; 4],
+    pub natural_light: [f32; 4],
+    pub artificial_light: [f32; 4],
+    pub occlusion: [u8; 4],
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+const POSITION_X_BITS: u32 = 5;
+const POSITION_Y_BITS: u32 = 9;
+const POSITION_Z_BITS: u32 = 5;
+const POSITION_SIDE_BITS: u32 = 3;
+
+const POSITION_Y_SHIFT: u32 = POSITION_X_BITS;
+const POSITION_Z_SHIFT: u32 = POSITION_Y_SHIFT + POSITION_Y_BITS;
+const POSITION_SIDE_SHIFT: u32 = POSITION_Z_SHIFT + POSITION_Z_BITS;
+const POSITION_OFFSET_SHIFT: u32 = POSITION_SIDE_SHIFT + POSITION_SIDE_BITS;
+
+const LIGHT_BITS: u32 = 4;
+const LIGHT_OCCLUSION_SHIFT: u32 = LIGHT_BITS;
+
+const TILE_INDEX_BITS: u32 = 12;
+const TILE_UV_BITS: u32 = 10;
+const TILE_U_SHIFT: u32 = TILE_INDEX_BITS;
+const TILE_V_SHIFT: u32 = TILE_U_SHIFT + TILE_UV_BITS;
+
+const fn mask(bits: u32) -> u32 {
+    (1 << bits) - 1
+}
+
+/// Packed vertex data of a chunk mesh.
+///
+/// Vertices are stored on [`chunk::Chunk`], on genesis resources and on render meshes, so each
+/// vertex is packed into four [`u32`], which are decoded on `voxel.wgsl` shader.
+///
+/// Each field is packed as following, starting at the least significant bit:
+/// - `position`: X (5 bits), Y (9 bits), Z (5 bits), normal [`Side`] (3 bits) and the offset of
+///   this vertex relative to the owning voxel (1 bit per axis).
+/// - `light`: natural light intensity (4 bits) and ambient occlusion (4 bits).
+/// - `artificial_light`: artificial light intensity (4 bits). It is a separated vertex attribute,
+///   so the shader can dim only natural light at night.
+/// - `tile`: texture atlas tile index (12 bits), U (10 bits) and V (10 bits).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VoxelVertex {
-    pub position: Vec3,
-    pub normal: Vec3,
-    pub uv: Vec2,
-    pub tile_coord_start: Vec2,
-    pub light: Vec3,
-    pub voxel: u32,
-    // TODO: color
+    pub position: u32,
+    pub light: u32,
+    pub artificial_light: u32,
+    pub tile: u32,
+}
+
+impl VoxelVertex {
+    /// Packs the given vertex `position` in chunk space, facing the given `side`.
+    /// The `voxel` which owns this vertex must be at most one unit behind `position` on each axis.
+    pub fn pack_position(position: IVec3, side: Side, voxel: IVec3) -> u32 {
+        let offset = position - voxel;
+
+        debug_assert!(
+            position.cmpge(IVec3::ZERO).all()
+                && position.x <= chunk::X_AXIS_SIZE as i32
+                && position.y <= chunk::Y_AXIS_SIZE as i32
+                && position.z <= chunk::Z_AXIS_SIZE as i32,
+            "Vertex position {position} is out of chunk bounds"
+        );
+        debug_assert!(
+            offset.cmpge(IVec3::ZERO).all() && offset.cmple(IVec3::ONE).all(),
+            "Vertex {position} is too far from voxel {voxel}"
+        );
+
+        position.x as u32
+            | (position.y as u32) << POSITION_Y_SHIFT
+            | (position.z as u32) << POSITION_Z_SHIFT
+            | (side as u32) << POSITION_SIDE_SHIFT
+            | (offset.x as u32 | (offset.y as u32) << 1 | (offset.z as u32) << 2)
+                << POSITION_OFFSET_SHIFT
+    }
+
+    /// Packs the given `natural` light intensity and ambient `occlusion`, both must fit in 4 bits.
+    pub fn pack_light(natural: u8, occlusion: u8) -> u32 {
+        debug_assert!(natural <= 0xF && occlusion <= 0xF);
+
+        natural as u32 | (occlusion as u32) << LIGHT_OCCLUSION_SHIFT
+    }
+
+    /// Packs the given `artificial` light intensity, which must fit in 4 bits.
+    pub fn pack_artificial_light(artificial: u8) -> u32 {
+        debug_assert!(artificial <= 0xF);
+
+        artificial as u32
+    }
+
+    /// Packs the given texture atlas tile `index` and `uv`, which is in voxel units.
+    pub fn pack_tile(index: u16, uv: IVec2) -> u32 {
+        debug_assert!((index as u32) <= mask(TILE_INDEX_BITS));
+        debug_assert!(
+            uv.cmpge(IVec2::ZERO).all() && uv.cmple(IVec2::splat(mask(TILE_UV_BITS) as i32)).all()
+        );
+
+        index as u32 | (uv.x as u32) << TILE_U_SHIFT | (uv.y as u32) << TILE_V_SHIFT
+    }
+
+    /// **Returns** the vertex position in chunk space
+    pub fn position(&self) -> IVec3 {
+        IVec3::new(
+            (self.position & mask(POSITION_X_BITS)) as i32,
+            (self.position >> POSITION_Y_SHIFT & mask(POSITION_Y_BITS)) as i32,
+            (self.position >> POSITION_Z_SHIFT & mask(POSITION_Z_BITS)) as i32,
+        )
+    }
+
+    /// **Returns** which [`Side`] this vertex is facing
+    pub fn side(&self) -> Side {
+        SIDES[(self.position >> POSITION_SIDE_SHIFT & mask(POSITION_SIDE_BITS)) as usize]
+    }
+
+    /// **Returns** the local of voxel which owns this vertex
+    pub fn voxel(&self) -> IVec3 {
+        let offset = self.position >> POSITION_OFFSET_SHIFT;
+
+        self.position()
+            - IVec3::new(
+                (offset & 1) as i32,
+                (offset >> 1 & 1) as i32,
+                (offset >> 2 & 1) as i32,
+            )
+    }
+
+    /// **Returns** the natural light intensity, which comes from the sky
+    pub fn natural_light(&self) -> u8 {
+        (self.light & mask(LIGHT_BITS)) as u8
+    }
+
+    /// **Returns** the artificial light intensity, which isn't affected by time of day
+    pub fn artificial_light(&self) -> u8 {
+        (self.artificial_light & mask(LIGHT_BITS)) as u8
+    }
+
+    /// **Returns** how many neighbors are occluding this vertex
+    pub fn occlusion(&self) -> u8 {
+        (self.light >> LIGHT_OCCLUSION_SHIFT & mask(LIGHT_BITS)) as u8
+    }
+
+    /// **Returns** the texture atlas tile index
+    pub fn tile_index(&self) -> u16 {
+        (self.tile & mask(TILE_INDEX_BITS)) as u16
+    }
+
+    /// **Returns** the texture coordinates in voxel units
+    pub fn uv(&self) -> IVec2 {
+        IVec2::new(
+            (self.tile >> TILE_U_SHIFT & mask(TILE_UV_BITS)) as i32,
+            (self.tile >> TILE_V_SHIFT & mask(TILE_UV_BITS)) as i32,
+        )
+    }
 }
 
 pub fn to_local(world: Vec3) -> IVec3 {
@@ -302,6 +452,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn voxel_vertex() {
+        let mut rnd = rand::thread_rng();
+
+        for _ in 0..1000 {
+            let voxel = IVec3::new(
+                rnd.gen_range(0..chunk::X_AXIS_SIZE as i32),
+                rnd.gen_range(0..chunk::Y_AXIS_SIZE as i32),
+                rnd.gen_range(0..chunk::Z_AXIS_SIZE as i32),
+            );
+            let position = voxel
+                + IVec3::new(
+                    rnd.gen_range(0..=1),
+                    rnd.gen_range(0..=1),
+                    rnd.gen_range(0..=1),
+                );
+            let side = SIDES[rnd.gen_range(0..SIDE_COUNT)];
+            let natural = rnd.gen_range(0..=15);
+            let artificial = rnd.gen_range(0..=15);
+            let occlusion = rnd.gen_range(0..=3);
+            let tile = rnd.gen_range(0..4096);
+            let uv = IVec2::new(rnd.gen_range(0..=256), rnd.gen_range(0..=256));
+
+            let vertex = VoxelVertex {
+                position: VoxelVertex::pack_position(position, side, voxel),
+                light: VoxelVertex::pack_light(natural, occlusion),
+                artificial_light: VoxelVertex::pack_artificial_light(artificial),
+                tile: VoxelVertex::pack_tile(tile, uv),
+            };
+
+            assert_eq!(vertex.position(), position);
+            assert_eq!(vertex.side(), side);
+            assert_eq!(vertex.voxel(), voxel);
+            assert_eq!(vertex.natural_light(), natural);
+            assert_eq!(vertex.artificial_light(), artificial);
+            assert_eq!(vertex.occlusion(), occlusion);
+            assert_eq!(vertex.tile_index(), tile);
+            assert_eq!(vertex.uv(), uv);
+        }
+    }
+
     #[test]
     fn to_world() {
         use super::*;
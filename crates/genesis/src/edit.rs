@@ -0,0 +1,235 @@
+use bevy_math::IVec3;
+use bevy_utils::HashMap;
+use itertools::Itertools;
+use projekto_core::{chunk, query, voxel};
+
+use super::ChunkKindRes;
+
+/// Chunk local and a list of voxels local and their new kinds.
+pub type ChunkUpdate = (IVec3, Vec<(IVec3, voxel::Kind)>);
+
+/// World space voxel edits, which are split across chunks automatically.
+///
+/// All coordinates are voxel world coordinates. Voxels outside the vertical bounds of chunks are
+/// ignored. When the same voxel is edited more than once, the last edit wins.
+///
+/// Use [`super::GenesisCommandBuffer::apply`] or [`super::VoxelEditor::apply`] to send all edits at
+/// once, so light and vertices of affected chunks are computed together.
+#[derive(Default, Debug, Clone)]
+pub struct WorldEdit {
+    voxels: HashMap<IVec3, voxel::Kind>,
+}
+
+impl WorldEdit {
+    /// Sets a single voxel.
+    pub fn set(&mut self, world: IVec3, kind: voxel::Kind) -> &mut Self {
+        if (0..chunk::Y_AXIS_SIZE as i32).contains(&world.y) {
+            self.voxels.insert(world, kind);
+        }
+
+        self
+    }
+
+    /// Sets all voxels of the box between `begin` and `end`, both inclusive.
+    pub fn fill_box(&mut self, begin: IVec3, end: IVec3, kind: voxel::Kind) -> &mut Self {
+        query::range_inclusive(begin.min(end), begin.max(end)).for_each(|world| {
+            self.set(world, kind);
+        });
+
+        self
+    }
+
+    /// Sets all voxels which center is within `radius` of `center` voxel center.
+    pub fn fill_sphere(&mut self, center: IVec3, radius: f32, kind: voxel::Kind) -> &mut Self {
+        let extent = IVec3::splat(radius.max(0.0).ceil() as i32);
+
+        query::range_inclusive(center - extent, center + extent)
+            .filter(|&world| (world - center).as_vec3().length_squared() <= radius * radius)
+            .for_each(|world| {
+                self.set(world, kind);
+            });
+
+        self
+    }
+
+    /// Replaces all voxels of kind `from` with kind `to` inside the box between `begin` and `end`,
+    /// both inclusive. Edits already on this [`WorldEdit`] are taken into account. Voxels of
+    /// chunks which aren't loaded are skipped.
+    pub fn replace(
+        &mut self,
+        kinds: &ChunkKindRes,
+        begin: IVec3,
+        end: IVec3,
+        from: voxel::Kind,
+        to: voxel::Kind,
+    ) -> &mut Self {
+        for world in query::range_inclusive(begin.min(end), begin.max(end)) {
+            let current = self.voxels.get(&world).copied().or_else(|| {
+                let (local, voxel) = split(world);
+                kinds.get(local).map(|kind| kind.get(voxel))
+            });
+
+            if current == Some(from) {
+                self.set(world, to);
+            }
+        }
+
+        self
+    }
+
+    /// Sets all voxels on a line between `begin` and `end`, both inclusive. There is no gaps
+    /// between voxels, but they may be connected only by edges or corners.
+    pub fn line(&mut self, begin: IVec3, end: IVec3, kind: voxel::Kind) -> &mut Self {
+        let delta = end - begin;
+        let steps = delta.abs().max_element();
+
+        if steps == 0 {
+            return self.set(begin, kind);
+        }
+
+        for step in 0..=steps {
+            let offset = delta.as_vec3() * (step as f32 / steps as f32);
+            self.set(begin + offset.round().as_ivec3(), kind);
+        }
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Number of voxels edited.
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    /// Splits the edits by chunk.
+    ///
+    /// **Returns** a list of chunks updates, sorted by chunk local and voxel local.
+    pub fn into_updates(self) -> Vec<ChunkUpdate> {
+        self.voxels
+            .into_iter()
+            .map(|(world, kind)| {
+                let (local, voxel) = split(world);
+                (local, (voxel, kind))
+            })
+            .into_group_map()
+            .into_iter()
+            .map(|(local, mut voxels)| {
+                voxels.sort_by_key(|(voxel, _)| voxel.to_array());
+                (local, voxels)
+            })
+            .sorted_by_key(|(local, _)| local.to_array())
+            .collect()
+    }
+}
+
+/// Splits voxel world coordinates into chunk local and voxel local.
+fn split(world: IVec3) -> (IVec3, IVec3) {
+    let world = world.as_vec3();
+    (chunk::to_local(world), voxel::to_local(world))
+}
+
+#[cfg(test)]
+mod tests {
+    use projekto_core::chunk::ChunkKind;
+
+    use super::*;
+
+    #[test]
+    fn set() {
+        let mut edit = WorldEdit::default();
+        edit.set((-1, 0, 17).into(), 1.into())
+            .set((0, 0, 0).into(), 2.into())
+            .set((1, 0, 0).into(), 3.into())
+            .set((1, 0, 0).into(), 4.into())
+            .set((0, -1, 0).into(), 1.into())
+            .set((0, chunk::Y_AXIS_SIZE as i32, 0).into(), 1.into());
+
+        assert_eq!(
+            edit.len(),
+            3,
+            "Out of vertical bounds voxels should be ignored"
+        );
+
+        assert_eq!(
+            edit.into_updates(),
+            vec![
+                ((-1, 0, 1).into(), vec![((15, 0, 1).into(), 1.into())]),
+                (
+                    (0, 0, 0).into(),
+                    vec![((0, 0, 0).into(), 2.into()), ((1, 0, 0).into(), 4.into())]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_box() {
+        let mut edit = WorldEdit::default();
+        edit.fill_box((17, 3, 2).into(), (14, 1, 0).into(), 1.into());
+
+        assert_eq!(edit.len(), 4 * 3 * 3);
+
+        let updates = edit.into_updates();
+        assert_eq!(updates.len(), 2, "Box should be split across two chunks");
+        assert_eq!(updates[0].1.len(), 2 * 3 * 3);
+    }
+
+    #[test]
+    fn fill_sphere() {
+        let mut edit = WorldEdit::default();
+        edit.fill_sphere((0, 10, 0).into(), 1.0, 1.into());
+
+        assert_eq!(edit.len(), 7, "Center and its six neighbors");
+
+        let mut edit = WorldEdit::default();
+        edit.fill_sphere((0, 10, 0).into(), 0.0, 1.into());
+
+        assert_eq!(edit.len(), 1);
+    }
+
+    #[test]
+    fn line() {
+        let mut edit = WorldEdit::default();
+        edit.line((0, 0, 0).into(), (5, 2, -3).into(), 1.into());
+
+        assert_eq!(edit.len(), 6);
+        assert!(edit.voxels.contains_key(&(0, 0, 0).into()));
+        assert!(edit.voxels.contains_key(&(5, 2, -3).into()));
+
+        let mut edit = WorldEdit::default();
+        edit.line((3, 3, 3).into(), (3, 3, 3).into(), 1.into());
+
+        assert_eq!(edit.len(), 1);
+    }
+
+    #[test]
+    fn replace() {
+        let mut kind = ChunkKind::default();
+        kind.set((0, 0, 0).into(), 1.into());
+        kind.set((1, 0, 0).into(), 2.into());
+
+        let mut kinds = ChunkKindRes::default();
+        kinds.insert((0, 0, 0).into(), kind);
+
+        let mut edit = WorldEdit::default();
+        edit.set((2, 0, 0).into(), 1.into()).replace(
+            &kinds,
+            (-1, 0, 0).into(),
+            (2, 0, 0).into(),
+            1.into(),
+            3.into(),
+        );
+
+        assert_eq!(
+            edit.into_updates(),
+            vec![(
+                (0, 0, 0).into(),
+                vec![((0, 0, 0).into(), 3.into()), ((2, 0, 0).into(), 3.into())]
+            )],
+            "Only existing voxels of the given kind should be replaced"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Keeps the [`GeneratorConfig`] used to generate the chunks on cache next to them, so chunks
+//! generated later, either by the game or by tools, match the chunks already on cache.
+
+use std::sync::Arc;
+
+use bevy_math::IVec2;
+use projekto_shaping::{GeneratorConfig, Heightmap};
+use serde::{Deserialize, Serialize};
+
+use super::task;
+
+/// Noise settings of a [`GeneratorConfig`] and the origin of its heightmap. The heightmap itself is
+/// kept encoded as PNG, which is much smaller than its heights.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct GeneratorSettings {
+    pub seed: u64,
+    pub frequency: f32,
+    pub octaves: i32,
+    pub gain: f32,
+    pub lacunarity: f32,
+    pub heightmap_origin: Option<IVec2>,
+}
+
+impl GeneratorSettings {
+    /// Splits the given config into its settings and its heightmap encoded as PNG.
+    ///
+    /// **Returns** the settings and heightmap or a message describing why it failed.
+    pub fn encode(config: &GeneratorConfig) -> Result<(Self, Option<Vec<u8>>), String> {
+        let heightmap = config
+            .heightmap
+            .as_ref()
+            .map(|heightmap| heightmap.to_png())
+            .transpose()?;
+
+        let settings = Self {
+            seed: config.seed,
+            frequency: config.frequency,
+            octaves: config.octaves,
+            gain: config.gain,
+            lacunarity: config.lacunarity,
+            heightmap_origin: config.heightmap.as_ref().map(|heightmap| heightmap.origin),
+        };
+
+        Ok((settings, heightmap))
+    }
+
+    /// Joins these settings and the given PNG heightmap back into a [`GeneratorConfig`].
+    ///
+    /// **Returns** the config or a message describing why it failed.
+    pub fn decode(self, heightmap: Option<&[u8]>) -> Result<GeneratorConfig, String> {
+        let heightmap = match (self.heightmap_origin, heightmap) {
+            (Some(origin), Some(png)) => Some(Arc::new(Heightmap::from_png(png, origin)?)),
+            (None, None) => None,
+            (Some(_), None) => return Err("Missing heightmap".to_string()),
+            (None, Some(_)) => return Err("Missing heightmap origin".to_string()),
+        };
+
+        Ok(GeneratorConfig {
+            seed: self.seed,
+            frequency: self.frequency,
+            octaves: self.octaves,
+            gain: self.gain,
+            lacunarity: self.lacunarity,
+            heightmap,
+        })
+    }
+}
+
+/// Loads the config saved on cache by [`save`].
+///
+/// **Returns** the saved config, `None` if there is no config saved or a message describing why it
+/// failed.
+pub(super) fn load() -> Result<Option<GeneratorConfig>, String> {
+    let path = task::cache_file(super::GENERATOR_FILE);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let ron = std::fs::read_to_string(&path)
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    let settings: GeneratorSettings =
+        ron::from_str(&ron).map_err(|err| format!("Failed to parse {}: {err}", path.display()))?;
+
+    let heightmap = if settings.heightmap_origin.is_some() {
+        let path = task::cache_file(super::HEIGHTMAP_FILE);
+
+        Some(
+            std::fs::read(&path)
+                .map_err(|err| format!("Failed to read {}: {err}", path.display()))?,
+        )
+    } else {
+        None
+    };
+
+    settings.decode(heightmap.as_deref()).map(Some)
+}
+
+/// Saves the given config on cache, replacing any config saved before. Settings are saved as ron
+/// and the heightmap, if any, as PNG.
+///
+/// **Returns** a message describing why it failed, if so.
+pub(super) fn save(config: &GeneratorConfig) -> Result<(), String> {
+    let (settings, heightmap) = GeneratorSettings::encode(config)?;
+
+    if let Some(png) = heightmap {
+        let path = task::cache_file(super::HEIGHTMAP_FILE);
+
+        std::fs::write(&path, png)
+            .map_err(|err| format!("Failed to write {}: {err}", path.display()))?;
+    }
+
+    let ron = ron::ser::to_string_pretty(&settings, Default::default())
+        .map_err(|err| format!("Failed to serialize generator config: {err}"))?;
+    let path = task::cache_file(super::GENERATOR_FILE);
+
+    std::fs::write(&path, ron).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
+
+/// Makes sure chunks are generated using the same config of the chunks already on cache.
+///
+/// **Returns** the config saved on cache, which replaces the given one, or the given config when
+/// there is no config saved yet, which is saved then.
+pub(super) fn sync(config: &GeneratorConfig) -> Result<GeneratorConfig, String> {
+    match load()? {
+        Some(saved) => Ok(saved),
+        None => save(config).map(|_| config.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let config = GeneratorConfig {
+            seed: 42,
+            ..Default::default()
+        };
+
+        let (settings, heightmap) = GeneratorSettings::encode(&config).unwrap();
+        assert_eq!(settings.seed, 42);
+        assert_eq!(heightmap, None);
+        assert_eq!(settings.decode(None).unwrap(), config);
+
+        let mut heightmap = Heightmap::new(IVec2::new(-3, 4), 2, 2);
+        heightmap.set(-2, 5, 100);
+
+        let config = GeneratorConfig {
+            heightmap: Some(Arc::new(heightmap)),
+            ..Default::default()
+        };
+
+        let (settings, png) = GeneratorSettings::encode(&config).unwrap();
+        assert_eq!(settings.heightmap_origin, Some(IVec2::new(-3, 4)));
+        assert!(settings.clone().decode(None).is_err());
+        assert_eq!(settings.decode(png.as_deref()).unwrap(), config);
+    }
+}
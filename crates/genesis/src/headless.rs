@@ -0,0 +1,104 @@
+//! Runs genesis without a bevy app or renderer. Used by tools like world pre-generation.
+
+use std::time::Instant;
+
+use bevy_math::IVec3;
+use bevy_tasks::{AsyncComputeTaskPool, IoTaskPool, TaskPool};
+use futures_lite::future;
+use projekto_core::{chunk::ChunkKind, VoxWorld};
+use projekto_shaping::GeneratorConfig;
+
+use super::{generator, task, ChunkCmd, StageTimings};
+
+/// Initializes task pools and chunk cache. Must be called before [`generate`].
+pub fn init() {
+    AsyncComputeTaskPool::init(TaskPool::default);
+    IoTaskPool::init(TaskPool::default);
+
+    super::init_cache();
+}
+
+/// Saves the given config on cache, so the game generates chunks around chunks generated by
+/// [`generate`] the same way.
+///
+/// **Returns** an error if chunks on cache were generated using another config.
+pub fn save_config(config: &GeneratorConfig) -> Result<(), String> {
+    let saved = generator::sync(config)?;
+
+    if saved == *config {
+        Ok(())
+    } else {
+        Err(format!(
+            "Chunks on cache were generated using {saved:?}. Clear the cache to use another config"
+        ))
+    }
+}
+
+/// **Returns** the config used to generate chunks on cache or `None` if there is no config saved.
+pub fn load_config() -> Result<Option<GeneratorConfig>, String> {
+    generator::load()
+}
+
+/// Loads or generates the given chunks, blocking the current thread until they are saved on cache.
+/// Chunks which already exists on cache are loaded instead of generated.
+///
+/// Chunks which already exists on the given world are skipped. Neighbors of new chunks are updated
+/// and saved again. Neighbors which aren't on the given world are loaded from cache, so chunks
+/// generated by earlier calls are refreshed too, the same way as [`import`]. Keeping chunks which
+/// still may have new neighbors on the given world avoids loading them again.
+///
+/// **Returns** the updated world and the time spent on each stage.
+pub fn generate(
+    mut world: VoxWorld,
+    locals: &[IVec3],
+    config: GeneratorConfig,
+) -> (VoxWorld, StageTimings) {
+    let new = locals
+        .iter()
+        .copied()
+        .filter(|&local| !world.exists(local) && !task::cache_path(local).exists())
+        .collect::<Vec<_>>();
+
+    let now = Instant::now();
+    future::block_on(task::load_cached_neighbors(&mut world, &new));
+    let load = now.elapsed();
+
+    let commands = locals
+        .iter()
+        .filter(|&&local| !world.exists(local))
+        .map(|&local| ChunkCmd::Load(local))
+        .collect();
+
+    let mut result = future::block_on(task::process_batch(world, commands, config, true));
+    result.timings.load += load;
+
+    (result.world, result.timings)
+}
+
+/// Loads or generates the given chunks the same way as [`generate`], but nothing is saved on
+/// cache, so the cache is only read.
+///
+/// **Returns** the updated world.
+pub fn load(world: VoxWorld, locals: &[IVec3], config: GeneratorConfig) -> VoxWorld {
+    let commands = locals
+        .iter()
+        .filter(|&&local| !world.exists(local))
+        .map(|&local| ChunkCmd::Load(local))
+        .collect();
+
+    future::block_on(task::process_batch(world, commands, config, false)).world
+}
+
+/// Imports the given chunks kinds, replacing existing chunks, and saves them on cache, blocking the
+/// current thread. Light and vertices are computed the same way as generated chunks.
+///
+/// Existing neighbors of imported chunks are updated and saved again, the same way as [`generate`].
+/// Neighbors which aren't on the given world are loaded from cache, so chunks imported by earlier
+/// calls are refreshed too.
+///
+/// **Returns** the updated world and the time spent on each stage.
+pub fn import(world: VoxWorld, chunks: Vec<(IVec3, ChunkKind)>) -> (VoxWorld, StageTimings) {
+    let result = future::block_on(task::import_chunks(world, chunks));
+
+    (result.world, result.timings)
+}
@@ -0,0 +1,630 @@
+use std::{collections::VecDeque, marker::PhantomData};
+
+use bevy_ecs::{
+    prelude::EventReader,
+    system::{Res, ResMut, SystemParam},
+};
+use bevy_math::IVec3;
+use bevy_utils::HashMap;
+use projekto_core::voxel;
+
+use super::{events, ChunkKindRes, ChunkUpdate, GenesisCommandBuffer, WorldEdit};
+
+/// Default number of transactions kept by [`EditHistory`].
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+pub type TransactionId = u32;
+
+/// Chunk local, voxel local, old kind and new kind.
+pub type VoxelEdit = (IVec3, IVec3, voxel::Kind, voxel::Kind);
+
+/// A group of voxel edits which are undone and redone together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub id: TransactionId,
+    pub edits: Vec<VoxelEdit>,
+}
+
+/// Bounded undo and redo history of voxel edits.
+///
+/// Edits are recorded inside transactions and sent to [`GenesisCommandBuffer`]. Undoing a
+/// transaction sends the inverse updates, restoring old kinds, while redoing it sends the same
+/// updates again. Only edits made through the history are tracked, so [`VoxelEditor`] should be
+/// used instead of [`GenesisCommandBuffer::update`] for undoable edits.
+pub struct EditHistory {
+    capacity: usize,
+    next_id: TransactionId,
+    open: Option<Transaction>,
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    /// Kinds sent to genesis which weren't applied yet, so old kinds are known before
+    /// [`ChunkKindRes`] is updated.
+    expected: HashMap<(IVec3, IVec3), voxel::Kind>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl EditHistory {
+    /// Creates a new history which keeps at most `capacity` transactions. Oldest transactions are
+    /// discarded first.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: 0,
+            open: None,
+            undo: VecDeque::default(),
+            redo: vec![],
+            expected: HashMap::default(),
+        }
+    }
+
+    /// Starts a new transaction. Any transaction still open is committed.
+    ///
+    /// **Returns** the id of the new transaction.
+    pub fn begin(&mut self) -> TransactionId {
+        self.commit();
+
+        self.next_id = self.next_id.wrapping_add(1);
+        self.open = Some(Transaction {
+            id: self.next_id,
+            edits: vec![],
+        });
+
+        self.next_id
+    }
+
+    /// Commits the open transaction, if any. Committing a transaction discards the redo history.
+    /// Transactions without edits are discarded.
+    ///
+    /// **Returns** the id of the committed transaction.
+    pub fn commit(&mut self) -> Option<TransactionId> {
+        let transaction = self.open.take()?;
+
+        if transaction.edits.is_empty() {
+            return None;
+        }
+
+        let id = transaction.id;
+
+        self.redo.clear();
+        self.undo.push_back(transaction);
+
+        while self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+
+        Some(id)
+    }
+
+    /// Records and sends the given voxel updates of a chunk. If there is no open transaction, the
+    /// updates are committed on their own transaction.
+    ///
+    /// Voxels which already have the given kind or which chunk isn't loaded aren't recorded.
+    pub fn update(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+        local: IVec3,
+        voxels: Vec<(IVec3, voxel::Kind)>,
+    ) {
+        self.update_group(kinds, buffer, vec![(local, voxels)]);
+    }
+
+    /// Records and sends all updates of the given [`WorldEdit`]. If there is no open transaction,
+    /// the updates are committed on their own transaction.
+    pub fn apply(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+        edit: WorldEdit,
+    ) {
+        self.update_group(kinds, buffer, edit.into_updates());
+    }
+
+    /// Records and sends the given updates of each chunk as a single group, so they are applied
+    /// together. If there is no open transaction, the updates are committed on their own
+    /// transaction.
+    fn update_group(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+        updates: Vec<ChunkUpdate>,
+    ) {
+        let auto_commit = self.open.is_none();
+
+        if auto_commit {
+            self.begin();
+        }
+
+        let mut applied = HashMap::default();
+
+        for (local, voxels) in &updates {
+            for &(voxel, new) in voxels {
+                let old = match self.current_kind(kinds, *local, voxel) {
+                    Some(old) if old != new => old,
+                    _ => continue,
+                };
+
+                self.expect(kinds, &mut applied, *local, voxel, new);
+                self.open
+                    .as_mut()
+                    .expect("Transaction should be open")
+                    .edits
+                    .push((*local, voxel, old, new));
+            }
+        }
+
+        self.forget_unchanged(applied);
+        buffer.update_group(updates);
+
+        if auto_commit {
+            self.commit();
+        }
+    }
+
+    /// Undoes the last transaction, committing the open transaction first.
+    ///
+    /// **Returns** the id of the undone transaction.
+    pub fn undo(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+    ) -> Option<TransactionId> {
+        self.commit();
+
+        let transaction = self.undo.pop_back()?;
+        let id = transaction.id;
+
+        // Revert backwards, so voxels edited many times end up with their first old kind
+        self.send(
+            kinds,
+            buffer,
+            transaction
+                .edits
+                .iter()
+                .rev()
+                .map(|&(local, voxel, old, _)| (local, voxel, old)),
+        );
+
+        self.redo.push(transaction);
+
+        Some(id)
+    }
+
+    /// Redoes the last undone transaction.
+    ///
+    /// **Returns** the id of the redone transaction.
+    pub fn redo(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+    ) -> Option<TransactionId> {
+        let transaction = self.redo.pop()?;
+        let id = transaction.id;
+
+        self.send(
+            kinds,
+            buffer,
+            transaction
+                .edits
+                .iter()
+                .map(|&(local, voxel, _, new)| (local, voxel, new)),
+        );
+
+        self.undo.push_back(transaction);
+
+        Some(id)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().map_or(false, |t| !t.edits.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Discards all transactions, including the open one, and forgets kinds which weren't applied
+    /// yet, so old kinds are read from [`ChunkKindRes`] again.
+    pub fn clear(&mut self) {
+        self.open = None;
+        self.undo.clear();
+        self.redo.clear();
+        self.expected.clear();
+    }
+
+    fn current_kind(
+        &self,
+        kinds: &ChunkKindRes,
+        local: IVec3,
+        voxel: IVec3,
+    ) -> Option<voxel::Kind> {
+        self.expected
+            .get(&(local, voxel))
+            .copied()
+            .or_else(|| kinds.get(local).map(|kind| kind.get(voxel)))
+    }
+
+    /// Sends the given voxels kinds as a single group, split by chunk while keeping the order of
+    /// each chunk.
+    fn send(
+        &mut self,
+        kinds: &ChunkKindRes,
+        buffer: &mut GenesisCommandBuffer,
+        voxels: impl Iterator<Item = (IVec3, IVec3, voxel::Kind)>,
+    ) {
+        let mut chunks = Vec::<ChunkUpdate>::new();
+        let mut applied = HashMap::default();
+
+        for (local, voxel, kind) in voxels {
+            self.expect(kinds, &mut applied, local, voxel, kind);
+
+            match chunks.iter_mut().find(|(l, _)| *l == local) {
+                Some((_, list)) => list.push((voxel, kind)),
+                None => chunks.push((local, vec![(voxel, kind)])),
+            }
+        }
+
+        self.forget_unchanged(applied);
+        buffer.update_group(chunks);
+    }
+
+    /// Records the kind a voxel will have once the group being sent is applied. Voxels without an
+    /// expected kind yet have their applied kind kept on `applied`, so
+    /// [`EditHistory::forget_unchanged`] can find them later.
+    fn expect(
+        &mut self,
+        kinds: &ChunkKindRes,
+        applied: &mut HashMap<(IVec3, IVec3), Option<voxel::Kind>>,
+        local: IVec3,
+        voxel: IVec3,
+        kind: voxel::Kind,
+    ) {
+        if !self.expected.contains_key(&(local, voxel)) {
+            applied.insert((local, voxel), kinds.get(local).map(|kind| kind.get(voxel)));
+        }
+
+        self.expected.insert((local, voxel), kind);
+    }
+
+    /// Forgets expected kinds of voxels which end up with the kind they already have, like a voxel
+    /// set and then reverted on the same group. Genesis doesn't report those as changed, so they
+    /// would be expected until the chunk is unloaded.
+    fn forget_unchanged(&mut self, applied: HashMap<(IVec3, IVec3), Option<voxel::Kind>>) {
+        for (key, kind) in applied {
+            if self.expected.get(&key).copied() == kind {
+                self.expected.remove(&key);
+            }
+        }
+    }
+
+    /// Forgets expected kinds which were applied by genesis.
+    fn applied(&mut self, local: IVec3, changes: &[events::VoxelChange]) {
+        for &(voxel, _, new) in changes {
+            if self.expected.get(&(local, voxel)) == Some(&new) {
+                self.expected.remove(&(local, voxel));
+            }
+        }
+    }
+}
+
+/// Keeps track of which edits recorded on [`EditHistory`] were already applied.
+pub(super) fn track_applied_edits(
+    mut history: ResMut<EditHistory>,
+    mut changed_reader: EventReader<events::VoxelsChanged>,
+    mut unloaded_reader: EventReader<events::ChunkUnloaded>,
+) {
+    for events::VoxelsChanged { chunk, changes } in changed_reader.iter() {
+        history.applied(*chunk, changes);
+    }
+
+    for events::ChunkUnloaded(local) in unloaded_reader.iter() {
+        history.expected.retain(|(l, _), _| l != local);
+    }
+}
+
+/// Utility [`SystemParam`] to do undoable voxel edits. See [`EditHistory`] for more info.
+#[derive(SystemParam)]
+pub struct VoxelEditor<'w, 's> {
+    history: ResMut<'w, EditHistory>,
+    buffer: ResMut<'w, GenesisCommandBuffer>,
+    kinds: Res<'w, ChunkKindRes>,
+
+    #[system_param(ignore)]
+    _pd: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> VoxelEditor<'w, 's> {
+    /// See [`EditHistory::begin`].
+    pub fn begin(&mut self) -> TransactionId {
+        self.history.begin()
+    }
+
+    /// See [`EditHistory::commit`].
+    pub fn commit(&mut self) -> Option<TransactionId> {
+        self.history.commit()
+    }
+
+    /// See [`EditHistory::update`].
+    pub fn update(&mut self, local: IVec3, voxels: Vec<(IVec3, voxel::Kind)>) {
+        self.history
+            .update(&self.kinds, &mut self.buffer, local, voxels);
+    }
+
+    /// See [`EditHistory::apply`].
+    pub fn apply(&mut self, edit: WorldEdit) {
+        self.history.apply(&self.kinds, &mut self.buffer, edit);
+    }
+
+    /// See [`EditHistory::undo`].
+    pub fn undo(&mut self) -> Option<TransactionId> {
+        self.history.undo(&self.kinds, &mut self.buffer)
+    }
+
+    /// See [`EditHistory::redo`].
+    pub fn redo(&mut self) -> Option<TransactionId> {
+        self.history.redo(&self.kinds, &mut self.buffer)
+    }
+
+    pub fn kinds(&self) -> &ChunkKindRes {
+        &self.kinds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use projekto_core::chunk::ChunkKind;
+
+    use super::*;
+    use crate::ChunkCmd;
+
+    fn kinds() -> ChunkKindRes {
+        let mut kind = ChunkKind::default();
+        kind.set((0, 0, 0).into(), 1.into());
+
+        let mut kinds = ChunkKindRes::default();
+        kinds.insert((0, 0, 0).into(), kind);
+        kinds.insert((1, 0, 0).into(), ChunkKind::default());
+        kinds
+    }
+
+    #[test]
+    fn undo_redo() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        let id = history.begin();
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 2.into()), ((1, 0, 0).into(), 0.into())],
+        );
+        history.update(
+            &kinds,
+            &mut buffer,
+            (1, 0, 0).into(),
+            vec![((0, 0, 0).into(), 3.into())],
+        );
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 4.into())],
+        );
+        assert_eq!(history.commit(), Some(id));
+
+        assert_eq!(
+            history.undo.back().unwrap().edits,
+            vec![
+                ((0, 0, 0).into(), (0, 0, 0).into(), 1.into(), 2.into()),
+                ((1, 0, 0).into(), (0, 0, 0).into(), 0.into(), 3.into()),
+                ((0, 0, 0).into(), (0, 0, 0).into(), 2.into(), 4.into()),
+            ],
+            "Unchanged voxels should be skipped and pending edits should be used as old kind"
+        );
+
+        buffer.edits.clear();
+        assert_eq!(history.undo(&kinds, &mut buffer), Some(id));
+        assert_eq!(
+            buffer
+                .edits
+                .iter()
+                .map(|(_, group)| group.clone())
+                .collect::<Vec<_>>(),
+            vec![vec![
+                ChunkCmd::Update(
+                    (0, 0, 0).into(),
+                    vec![((0, 0, 0).into(), 2.into()), ((0, 0, 0).into(), 1.into())]
+                ),
+                ChunkCmd::Update((1, 0, 0).into(), vec![((0, 0, 0).into(), 0.into())]),
+            ]]
+        );
+        assert!(!history.can_undo());
+        assert!(history.can_redo());
+
+        buffer.edits.clear();
+        assert_eq!(history.redo(&kinds, &mut buffer), Some(id));
+        assert_eq!(
+            buffer
+                .edits
+                .iter()
+                .map(|(_, group)| group.clone())
+                .collect::<Vec<_>>(),
+            vec![vec![
+                ChunkCmd::Update(
+                    (0, 0, 0).into(),
+                    vec![((0, 0, 0).into(), 2.into()), ((0, 0, 0).into(), 4.into())]
+                ),
+                ChunkCmd::Update((1, 0, 0).into(), vec![((0, 0, 0).into(), 3.into())]),
+            ]]
+        );
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn new_edit_discards_redo() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (1, 0, 0).into(),
+            vec![((0, 0, 0).into(), 1.into())],
+        );
+        history.undo(&kinds, &mut buffer);
+        assert!(history.can_redo());
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (1, 0, 0).into(),
+            vec![((0, 0, 0).into(), 2.into())],
+        );
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(&kinds, &mut buffer), None);
+    }
+
+    #[test]
+    fn bounded_history() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::with_capacity(2);
+
+        for kind in 1..=3 {
+            history.update(
+                &kinds,
+                &mut buffer,
+                (1, 0, 0).into(),
+                vec![((0, 0, 0).into(), kind.into())],
+            );
+        }
+
+        assert!(history.undo(&kinds, &mut buffer).is_some());
+        assert!(history.undo(&kinds, &mut buffer).is_some());
+        assert_eq!(
+            history.undo(&kinds, &mut buffer),
+            None,
+            "Oldest transactions should be discarded"
+        );
+    }
+
+    #[test]
+    fn applied_edits() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (1, 0, 0).into(),
+            vec![((0, 0, 0).into(), 1.into()), ((1, 0, 0).into(), 2.into())],
+        );
+
+        history.applied(
+            (1, 0, 0).into(),
+            &[
+                ((0, 0, 0).into(), 0.into(), 1.into()),
+                ((1, 0, 0).into(), 0.into(), 5.into()),
+            ],
+        );
+
+        assert_eq!(
+            history.expected.len(),
+            1,
+            "Only expected kinds which matches applied changes should be forgotten"
+        );
+    }
+
+    #[test]
+    fn unchanged_edits() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 2.into()), ((0, 0, 0).into(), 1.into())],
+        );
+
+        assert!(
+            history.expected.is_empty(),
+            "Voxels which end up with the same kind are never applied, so shouldn't be expected"
+        );
+
+        history.undo(&kinds, &mut buffer);
+        assert!(history.expected.is_empty());
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 2.into())],
+        );
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 3.into()), ((0, 0, 0).into(), 2.into())],
+        );
+
+        assert_eq!(
+            history.current_kind(&kinds, (0, 0, 0).into(), (0, 0, 0).into()),
+            Some(2.into()),
+            "Pending edits should still be expected"
+        );
+    }
+
+    #[test]
+    fn clear() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        history.update(
+            &kinds,
+            &mut buffer,
+            (0, 0, 0).into(),
+            vec![((0, 0, 0).into(), 2.into())],
+        );
+        history.clear();
+
+        assert!(!history.can_undo());
+        assert_eq!(
+            history.current_kind(&kinds, (0, 0, 0).into(), (0, 0, 0).into()),
+            Some(1.into()),
+            "Stale expected kinds should be forgotten"
+        );
+    }
+
+    #[test]
+    fn apply_world_edit() {
+        let kinds = kinds();
+        let mut buffer = GenesisCommandBuffer::default();
+        let mut history = EditHistory::default();
+
+        let mut edit = WorldEdit::default();
+        edit.fill_box((15, 0, 0).into(), (16, 0, 0).into(), 5.into());
+        history.apply(&kinds, &mut buffer, edit);
+
+        assert_eq!(buffer.edits.len(), 1, "Whole edit should be a single group");
+        assert_eq!(buffer.edits[0].1.len(), 2);
+        assert_eq!(
+            history.undo.len(),
+            1,
+            "Whole edit should be a single transaction"
+        );
+        assert_eq!(history.undo.back().unwrap().edits.len(), 2);
+    }
+}
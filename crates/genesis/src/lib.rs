@@ -1,13 +1,12 @@
 use std::marker::PhantomData;
 
 use bevy_app::{App, CoreStage, Plugin};
-use bevy_derive::{Deref, DerefMut};
 use bevy_ecs::{
     prelude::EventWriter,
-    schedule::{SystemLabel, SystemSet},
-    system::{ResMut, SystemParam},
+    schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+    system::{Res, ResMut, SystemParam},
 };
-use bevy_log::debug;
+use bevy_log::{debug, error, warn};
 use bevy_math::IVec3;
 use bevy_reflect::Reflect;
 use bevy_tasks::{AsyncComputeTaskPool, Task};
@@ -16,50 +15,112 @@ use bevy_utils::{HashMap, HashSet};
 use futures_lite::future;
 
 use projekto_core::{
-    chunk::Chunk,
+    chunk::{Chunk, SectionFlags},
+    pathfinding::NavGraph,
+    query,
     voxel::{self},
     VoxWorld,
 };
-
+use projekto_shaping::GeneratorConfig;
+
+mod edit;
+mod generator;
+pub mod headless;
+mod history;
+mod lod;
+mod nav;
+mod net;
 mod resources;
 mod task;
+mod vox;
 
+pub use edit::{ChunkUpdate, WorldEdit};
+pub use history::{EditHistory, Transaction, TransactionId, VoxelEdit, VoxelEditor};
+pub use lod::LodCommandBuffer;
+pub use net::{GenesisClient, GenesisClientPlugin, GenesisServer, GenesisServerPlugin};
 pub use resources::*;
+pub use task::{cache_path, decode_chunk, StageTimings};
+pub use vox::{kind_color, VoxColor, VoxColorMapping, VoxMapping, VoxModel, VOX_MAX_SIZE};
 
 use self::task::TaskResult;
 
 const CACHE_PATH: &str = "cache/chunks/";
 const CACHE_EXT: &str = "bin";
 
+/// Files inside cache directory which holds the [`GeneratorConfig`] used to generate cached chunks.
+const GENERATOR_FILE: &str = "generator.ron";
+const HEIGHTMAP_FILE: &str = "heightmap.png";
+
+/// Version of the [`Chunk`] layout stored on cache files, which is written before the chunk data.
+/// It must be bumped whenever [`Chunk`] or its vertex layout changes, so old caches are generated
+/// again instead of being decoded with the wrong layout.
+const CACHE_VERSION: u32 = 2;
+
+/// Max number of chunks loaded on a single batch. Loads are processed in the order they were
+/// requested, so smaller batches allows near chunks to be shown earlier.
+const MAX_LOADS_PER_BATCH: usize = 32;
+
+/// Max number of batches processed at the same time. See [`WorldRes`] for more info.
+const MAX_RUNNING_TASKS: usize = 4;
+
 pub struct GenesisPlugin;
 
 impl Plugin for GenesisPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<GenesisCommandBuffer>()
-            .init_resource::<RunningTask>()
-            .init_resource::<ChunkKindRes>()
-            .init_resource::<ChunkLightRes>()
-            .init_resource::<ChunkVertexRes>()
-            .insert_resource(WorldRes(Some(Default::default())))
-            .add_system_set_to_stage(
+        app.init_resource::<RunningTasks>()
+            .init_resource::<WorldRes>()
+            .init_resource::<GeneratorConfig>()
+            .add_system_to_stage(
                 CoreStage::PreUpdate,
-                SystemSet::new()
-                    .with_system(collect_completed_task_results)
-                    .label(GenesisLabel::Collect),
+                collect_completed_task_results.label(GenesisLabel::Collect),
             )
-            .add_system_set_to_stage(
+            .add_system_to_stage(
                 CoreStage::PostUpdate,
-                SystemSet::new()
-                    .with_system(dispatch_task)
-                    .label(GenesisLabel::Dispatch),
+                dispatch_tasks.label(GenesisLabel::Dispatch),
             )
-            .add_startup_system(init_cache);
+            .add_startup_system(init_generator);
 
-        events::register(app);
+        register_common(app);
     }
 }
 
-/// [`SystemSet`] labels used by [`GenesisPlugin`] to do interact with [`VoxWorld`]
+/// Registers resources, events and systems used by both [`GenesisPlugin`] and
+/// [`GenesisClientPlugin`]. Level of detail chunks are always generated locally, once there is a
+/// [`GeneratorConfig`], which clients receive from the server.
+///
+/// [`NavGraph`] is kept up to date with loaded chunks, so paths can be found over
+/// [`ChunkKindRes`].
+fn register_common(app: &mut App) {
+    app.init_resource::<GenesisCommandBuffer>()
+        .init_resource::<LodCommandBuffer>()
+        .init_resource::<lod::RunningLodTasks>()
+        .init_resource::<ChunkKindRes>()
+        .init_resource::<ChunkLightRes>()
+        .init_resource::<ChunkVertexRes>()
+        .init_resource::<ChunkConnectivityRes>()
+        .init_resource::<EditHistory>()
+        .init_resource::<NavGraph>()
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            lod::collect_lod_tasks.label(GenesisLabel::Collect),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            history::track_applied_edits.after(GenesisLabel::Collect),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            nav::invalidate_nav_graph.after(GenesisLabel::Collect),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            lod::dispatch_lod_tasks.label(GenesisLabel::Dispatch),
+        );
+
+    events::register(app);
+}
+
+/// [`SystemLabel`]s used by [`GenesisPlugin`] to do interact with [`VoxWorld`]
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel, Reflect)]
 pub enum GenesisLabel {
     /// Collects all tasks results and place them in resource
@@ -73,65 +134,333 @@ pub enum GenesisLabel {
 pub mod events {
     use bevy_app::App;
     use bevy_math::IVec3;
+    use projekto_core::{
+        chunk::SectionFlags,
+        voxel::{self, VoxelVertex},
+    };
+
+    /// Raised whenever a chunk is loaded or updated. Holds which sections had vertices changed.
+    #[derive(Debug, Default)]
+    pub struct ChunkUpdated(pub IVec3, pub SectionFlags);
+
+    /// Raised whenever a chunk is loaded from cache.
+    #[derive(Debug, Default)]
+    pub struct ChunkLoaded(pub IVec3);
+
+    /// Raised whenever a chunk which doesn't exists on cache is generated.
+    #[derive(Debug, Default)]
+    pub struct ChunkGenerated(pub IVec3);
 
+    /// Raised whenever a chunk is unloaded.
     #[derive(Debug, Default)]
-    pub struct ChunkUpdated(pub IVec3);
+    pub struct ChunkUnloaded(pub IVec3);
+
+    /// Voxel local, old kind and new kind.
+    pub type VoxelChange = (IVec3, voxel::Kind, voxel::Kind);
+
+    /// Raised whenever voxels of a chunk have their kind changed by an update command. Voxels which
+    /// already had the desired kind aren't listed.
+    #[derive(Debug, Default)]
+    pub struct VoxelsChanged {
+        pub chunk: IVec3,
+        pub changes: Vec<VoxelChange>,
+    }
+
+    /// Raised whenever a level of detail chunk requested on [`super::LodCommandBuffer`] is
+    /// generated.
+    #[derive(Debug, Default)]
+    pub struct ChunkLodGenerated {
+        pub local: IVec3,
+        pub level: u8,
+        pub vertices: Vec<VoxelVertex>,
+    }
 
     pub(super) fn register(app: &mut App) {
-        app.add_event::<ChunkUpdated>();
+        app.add_event::<ChunkUpdated>()
+            .add_event::<ChunkLoaded>()
+            .add_event::<ChunkGenerated>()
+            .add_event::<ChunkUnloaded>()
+            .add_event::<VoxelsChanged>()
+            .add_event::<ChunkLodGenerated>();
     }
 }
 
 fn init_cache() {
+    // Tests use a temporary cache, which is created on demand. See `task::cache_file`
+    if cfg!(test) {
+        return;
+    }
+
     if !std::path::Path::new(CACHE_PATH).exists() {
         std::fs::create_dir_all(CACHE_PATH).unwrap();
     }
 }
 
+/// Creates the cache and replaces [`GeneratorConfig`] by the config used to generate the chunks
+/// already on cache, if any, so new chunks match them. Otherwise the current config is saved.
+fn init_generator(mut config: ResMut<GeneratorConfig>) {
+    init_cache();
+
+    // Tests always use the given config, since all tests of a process share the same cache
+    if cfg!(test) {
+        return;
+    }
+
+    match generator::sync(&config) {
+        Ok(saved) if saved != *config => {
+            warn!(
+                "Using config of cached chunks instead of {:?}. Clear the cache to use it",
+                *config
+            );
+            *config = saved;
+        }
+        Ok(_) => (),
+        Err(err) => error!("Failed to sync generator config with cache: {}", err),
+    }
+}
+
 /// Hold chunk commands to be processed in batch.
-/// Internally uses a double buffered list of commands to keep track of what is running and what is
-/// pending.
+/// Internally keeps track of pending commands and commands of each running batch.
+///
+/// Voxel edits are kept on their own queue, which has priority over loads and unloads, so edits
+/// doesn't wait behind terrain streaming. Edits are queued in groups, which are always dispatched
+/// on the same batch, so a group is never visible half applied. Still, commands of the same chunk
+/// are always dispatched in the order they were queued, on both queues.
+///
+/// Chunks can also be held by many holders, like remote clients, using
+/// [`GenesisCommandBuffer::acquire`]. Those chunks are kept loaded until all holders release them.
 ///
 /// This command buffer handles duplicated commands. See [`optimize_commands`] for more.
 #[derive(Default)]
 pub struct GenesisCommandBuffer {
-    pending: Vec<ChunkCmd>,
-    running: Vec<ChunkCmd>,
+    /// Loads and unloads, with the sequence number of each command.
+    pending: Vec<(u64, ChunkCmd)>,
+    /// Edit groups, with the sequence number of each group.
+    edits: Vec<(u64, Vec<ChunkCmd>)>,
+    /// Sequence number of the last queued command, used to keep the order of commands of the same
+    /// chunk across both queues.
+    seq: u64,
+    running: HashMap<BatchId, Vec<ChunkCmd>>,
+    /// Chunks loaded by [`GenesisCommandBuffer::load`] which weren't unloaded yet.
+    requested: HashSet<IVec3>,
+    /// How many holders each acquired chunk has.
+    holders: HashMap<IVec3, u32>,
 }
 
 impl GenesisCommandBuffer {
-    /// Swap the running and pending buffers
+    /// Moves pending edit groups to a new running batch. Groups which any chunk of the neighborhood
+    /// of any edit is locked aren't moved. See [`WorldRes`] for more info.
+    ///
+    /// Groups with edits of chunks which still have a load or unload queued before them aren't
+    /// moved either, since edits must be applied after those. See
+    /// [`GenesisCommandBuffer::edit_barriers`] for more.
+    ///
+    /// Any group touching a chunk of a group kept is also kept, to preserve the order of edits.
     ///
-    /// Returns a clone of the running buffer
-    fn swap_and_clone(&mut self) -> Vec<ChunkCmd> {
-        // Since the running buffer is always cleared when the batch is finished, this swap has no
-        // side-effects
-        std::mem::swap(&mut self.running, &mut self.pending);
-        self.running.clone()
+    /// Returns a clone of the new running batch
+    fn take_edit_batch(&mut self, id: BatchId, locked: &HashSet<IVec3>) -> Vec<ChunkCmd> {
+        let barriers = self.edit_barriers();
+        let mut deferred = HashSet::default();
+        let mut batch = vec![];
+
+        for (seq, group) in std::mem::take(&mut self.edits) {
+            let defer = group.iter().any(|cmd| {
+                let local = cmd.local();
+
+                deferred.contains(&local)
+                    || barriers
+                        .get(&local)
+                        .map_or(false, |&barrier| barrier <= seq)
+                    || neighborhood(local).any(|l| locked.contains(&l))
+            });
+
+            if defer {
+                deferred.extend(group.iter().map(ChunkCmd::local));
+                self.edits.push((seq, group));
+            } else {
+                batch.extend(group);
+            }
+        }
+
+        self.start(id, batch)
+    }
+
+    /// Moves pending loads and unloads to a new running batch. Commands which any chunk of their
+    /// neighborhood is locked or is needed by a pending edit aren't moved. Commands queued after an
+    /// edit of the same chunk which is still waiting aren't moved either.
+    ///
+    /// Returns a clone of the new running batch
+    fn take_batch(&mut self, id: BatchId, locked: &HashSet<IVec3>) -> Vec<ChunkCmd> {
+        // Pending edits are waiting for locked chunks, so reserve their chunks to avoid starvation.
+        // Groups waiting for an earlier load or unload doesn't reserve anything, or that command
+        // would never run
+        let barriers = self.edit_barriers();
+        let mut reserved = locked.clone();
+        reserved.extend(
+            self.edits
+                .iter()
+                .filter(|(seq, group)| {
+                    group.iter().all(|cmd| {
+                        barriers
+                            .get(&cmd.local())
+                            .map_or(true, |&barrier| barrier > *seq)
+                    })
+                })
+                .flat_map(|(_, group)| group)
+                .flat_map(|cmd| neighborhood(cmd.local())),
+        );
+
+        let batch = take_commands(&mut self.pending, &reserved, &barriers);
+        self.start(id, batch)
+    }
+
+    /// Finds edit groups which must wait for a load or unload of any of its chunks queued before
+    /// them. Groups touching a chunk of a waiting group queued before them must wait too.
+    ///
+    /// **Returns** the sequence number of the first waiting group of each chunk. Commands of those
+    /// chunks queued after it must wait too.
+    fn edit_barriers(&self) -> HashMap<IVec3, u64> {
+        let mut streaming = HashMap::<IVec3, u64>::default();
+
+        for (seq, cmd) in &self.pending {
+            streaming.entry(cmd.local()).or_insert(*seq);
+        }
+
+        let mut barriers = HashMap::default();
+
+        for (seq, group) in &self.edits {
+            let waiting = group.iter().any(|cmd| {
+                let local = cmd.local();
+
+                barriers.contains_key(&local)
+                    || streaming.get(&local).map_or(false, |&first| first < *seq)
+            });
+
+            if waiting {
+                for cmd in group {
+                    barriers.entry(cmd.local()).or_insert(*seq);
+                }
+            }
+        }
+
+        barriers
+    }
+
+    /// Queues the given load or unload command.
+    fn push(&mut self, cmd: ChunkCmd) {
+        self.seq += 1;
+        self.pending.push((self.seq, cmd));
+    }
+
+    /// Queues the given edit group.
+    fn push_edits(&mut self, group: Vec<ChunkCmd>) {
+        self.seq += 1;
+        self.edits.push((self.seq, group));
+    }
+
+    fn start(&mut self, id: BatchId, batch: Vec<ChunkCmd>) -> Vec<ChunkCmd> {
+        if !batch.is_empty() {
+            self.running.insert(id, batch.clone());
+        }
+
+        batch
     }
 
     fn has_pending_cmds(&self) -> bool {
-        !self.pending.is_empty()
+        !self.pending.is_empty() || !self.edits.is_empty()
     }
 
-    /// Clears the running buffer
-    fn finished(&mut self) {
-        self.running.clear()
+    /// Removes the given batch from running batches
+    fn finished(&mut self, id: BatchId) {
+        self.running.remove(&id);
     }
 
     /// Adds a load command to the batch
     pub fn load(&mut self, local: IVec3) {
-        self.pending.push(ChunkCmd::Load(local));
+        self.requested.insert(local);
+        self.push(ChunkCmd::Load(local));
     }
 
-    /// Adds an unload command to the batch
+    /// Removes all pending load commands, which weren't dispatched yet. Loads of acquired chunks
+    /// are kept.
+    ///
+    /// This is useful to cancel stale loads or to request loads again using a new priority order.
+    pub fn cancel_pending_loads(&mut self) {
+        let Self {
+            pending,
+            requested,
+            holders,
+            ..
+        } = self;
+
+        pending.retain(|(_, cmd)| match cmd {
+            ChunkCmd::Load(local) if !holders.contains_key(local) => {
+                requested.remove(local);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Adds an unload command to the batch, unless the chunk is acquired. Acquired chunks are
+    /// unloaded once released by all holders.
     pub fn unload(&mut self, local: IVec3) {
-        self.pending.push(ChunkCmd::Unload(local));
+        self.requested.remove(&local);
+
+        if !self.holders.contains_key(&local) {
+            self.push(ChunkCmd::Unload(local));
+        }
     }
 
-    /// Adds an update command to the batch
+    /// Adds a load command to the batch on behalf of a holder. Each call must be paired with a
+    /// [`GenesisCommandBuffer::release`] call, so the chunk is kept loaded while any holder needs
+    /// it.
+    pub fn acquire(&mut self, local: IVec3) {
+        *self.holders.entry(local).or_default() += 1;
+        self.push(ChunkCmd::Load(local));
+    }
+
+    /// Releases a chunk acquired by [`GenesisCommandBuffer::acquire`]. Once there are no holders
+    /// left, the chunk is unloaded, unless it was loaded by [`GenesisCommandBuffer::load`] too.
+    pub fn release(&mut self, local: IVec3) {
+        let count = match self.holders.get_mut(&local) {
+            Some(count) => count,
+            None => return,
+        };
+
+        *count -= 1;
+
+        if *count == 0 {
+            self.holders.remove(&local);
+
+            if !self.requested.contains(&local) {
+                self.push(ChunkCmd::Unload(local));
+            }
+        }
+    }
+
+    /// Adds an update command to the edits queue
     pub fn update(&mut self, local: IVec3, voxels: Vec<(IVec3, voxel::Kind)>) {
-        self.pending.push(ChunkCmd::Update(local, voxels));
+        self.push_edits(vec![ChunkCmd::Update(local, voxels)]);
+    }
+
+    /// Adds an update command for each chunk affected by the given [`WorldEdit`]. See
+    /// [`GenesisCommandBuffer::update_group`] for more.
+    pub fn apply(&mut self, edit: WorldEdit) {
+        self.update_group(edit.into_updates());
+    }
+
+    /// Adds an update command for each given chunk update. Those are queued as a single group, so
+    /// they are only dispatched when all their chunks can be locked.
+    pub fn update_group(&mut self, updates: Vec<ChunkUpdate>) {
+        let group = updates
+            .into_iter()
+            .map(|(local, voxels)| ChunkCmd::Update(local, voxels))
+            .collect::<Vec<_>>();
+
+        if !group.is_empty() {
+            self.push_edits(group);
+        }
     }
 
     fn count_chunk_cmd(vec: &[ChunkCmd]) -> (i32, i32, i32) {
@@ -147,12 +476,21 @@ impl GenesisCommandBuffer {
 
 impl std::fmt::Debug for GenesisCommandBuffer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (pending_load, pending_unload, pending_update) = Self::count_chunk_cmd(&self.pending);
-        let (running_load, running_unload, running_update) = Self::count_chunk_cmd(&self.running);
+        let pending = self
+            .pending
+            .iter()
+            .map(|(_, cmd)| cmd)
+            .chain(self.edits.iter().flat_map(|(_, group)| group))
+            .cloned()
+            .collect::<Vec<_>>();
+        let (pending_load, pending_unload, pending_update) = Self::count_chunk_cmd(&pending);
+        let running = self.running.values().flatten().cloned().collect::<Vec<_>>();
+        let (running_load, running_unload, running_update) = Self::count_chunk_cmd(&running);
 
         write!(
             f,
-            "Running (LD: {} UL: {} UP: {}) | Pending (LD: {} UL: {} UP: {})",
+            "Running {} (LD: {} UL: {} UP: {}) | Pending (LD: {} UL: {} UP: {})",
+            self.running.len(),
             running_load,
             running_unload,
             running_update,
@@ -170,21 +508,103 @@ enum ChunkCmd {
     Update(IVec3, Vec<(IVec3, voxel::Kind)>),
 }
 
+impl ChunkCmd {
+    fn local(&self) -> IVec3 {
+        match self {
+            ChunkCmd::Load(local) | ChunkCmd::Unload(local) | ChunkCmd::Update(local, _) => *local,
+        }
+    }
+}
+
+/// Moves commands out of the given list, keeping commands which any chunk of their neighborhood is
+/// locked. Commands queued after the given edit `barriers` of their chunk are kept too.
+///
+/// At most [`MAX_LOADS_PER_BATCH`] loads are moved. Any command of a chunk which had a previous
+/// command kept is also kept, to preserve the order of commands of each chunk.
+///
+/// **Returns** moved commands.
+fn take_commands(
+    commands: &mut Vec<(u64, ChunkCmd)>,
+    locked: &HashSet<IVec3>,
+    barriers: &HashMap<IVec3, u64>,
+) -> Vec<ChunkCmd> {
+    let mut loads = 0;
+    let mut deferred = HashSet::default();
+    let mut taken = vec![];
+
+    for (seq, cmd) in std::mem::take(commands) {
+        let local = cmd.local();
+
+        let defer = deferred.contains(&local)
+            || barriers.get(&local).map_or(false, |&barrier| barrier < seq)
+            || neighborhood(local).any(|l| locked.contains(&l))
+            || (matches!(cmd, ChunkCmd::Load(_)) && {
+                loads += 1;
+                loads > MAX_LOADS_PER_BATCH
+            });
+
+        if defer {
+            deferred.insert(local);
+            commands.push((seq, cmd));
+        } else {
+            taken.push(cmd);
+        }
+    }
+
+    taken
+}
+
+/// Chunks and their one chunk neighborhood, which a command may read or write.
+fn neighborhood(local: IVec3) -> impl Iterator<Item = IVec3> {
+    query::range_inclusive(local - IVec3::ONE, local + IVec3::ONE)
+}
+
+/// Holds the [`VoxWorld`] and which chunks are locked by running batches.
+///
+/// Each running batch owns only the chunks it may touch: the chunks of its commands and their one
+/// chunk neighborhood. Those chunks are moved out of the world when the batch is dispatched and
+/// are moved back when it's completed, so batches which doesn't overlap can run at the same time.
+/// While locked, those chunks are missing from the world.
 #[derive(Default, Debug)]
-struct WorldRes(Option<VoxWorld>);
+struct WorldRes {
+    world: VoxWorld,
+    locked: HashSet<IVec3>,
+}
 
 impl WorldRes {
-    pub fn take(&mut self) -> VoxWorld {
-        self.0
-            .take()
-            .expect("You can take world only when it's ready")
+    /// Locks the given region and moves all existing chunks on it to a new [`VoxWorld`].
+    fn lock(&mut self, region: &HashSet<IVec3>) -> VoxWorld {
+        let mut world = VoxWorld::default();
+
+        for &local in region {
+            assert!(
+                self.locked.insert(local),
+                "Chunk {} is already locked by another batch",
+                local
+            );
+
+            if let Some(chunk) = self.world.remove(local) {
+                world.add(local, chunk);
+            }
+        }
+
+        world
     }
 
-    pub fn set(&mut self, world: VoxWorld) {
-        assert!(
-            self.0.replace(world).is_none(),
-            "There can be only one world at a time"
-        );
+    /// Moves back all chunks of the given [`VoxWorld`] and unlocks the given region.
+    fn unlock(&mut self, region: &HashSet<IVec3>, world: VoxWorld) {
+        for (local, chunk) in world.extract() {
+            debug_assert!(
+                region.contains(&local),
+                "Batch returned chunk {} outside of it's region",
+                local
+            );
+            self.world.add(local, chunk);
+        }
+
+        for local in region {
+            self.locked.remove(local);
+        }
     }
 }
 
@@ -192,18 +612,28 @@ impl std::ops::Deref for WorldRes {
     type Target = VoxWorld;
 
     fn deref(&self) -> &Self::Target {
-        self.0
-            .as_ref()
-            .expect("You can't use WorldRes while there is a task running")
+        &self.world
     }
 }
 
-#[derive(Default, Deref, DerefMut)]
-struct RunningTask(pub Option<Task<TaskResult>>);
+type BatchId = u32;
 
-impl RunningTask {
-    fn is_running(&self) -> bool {
-        self.is_some()
+struct RunningTask {
+    id: BatchId,
+    region: HashSet<IVec3>,
+    task: Task<TaskResult>,
+}
+
+#[derive(Default)]
+struct RunningTasks {
+    next_id: BatchId,
+    tasks: Vec<RunningTask>,
+}
+
+impl RunningTasks {
+    fn next_id(&mut self) -> BatchId {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
     }
 }
 
@@ -212,6 +642,7 @@ struct ChunkResources<'w, 's> {
     kind: ResMut<'w, ChunkKindRes>,
     light: ResMut<'w, ChunkLightRes>,
     vertex: ResMut<'w, ChunkVertexRes>,
+    connectivity: ResMut<'w, ChunkConnectivityRes>,
 
     #[system_param(ignore)]
     _pd: PhantomData<&'s ()>,
@@ -223,81 +654,198 @@ impl<'w, 's> ChunkResources<'w, 's> {
             kinds,
             lights,
             vertices,
+            connectivity,
         } = chunk.clone();
 
         self.kind.insert(local, kinds);
         self.light.insert(local, lights);
         self.vertex.insert(local, vertices);
+        self.connectivity.insert(local, connectivity);
     }
 
     fn remove(&mut self, local: IVec3) {
         self.kind.remove(&local);
         self.light.remove(&local);
         self.vertex.remove(&local);
+        self.connectivity.remove(&local);
     }
 }
 
+/// Event writers of all events raised when batches are completed.
+#[derive(SystemParam)]
+struct GenesisEventWriters<'w, 's> {
+    updated: EventWriter<'w, 's, events::ChunkUpdated>,
+    loaded: EventWriter<'w, 's, events::ChunkLoaded>,
+    generated: EventWriter<'w, 's, events::ChunkGenerated>,
+    unloaded: EventWriter<'w, 's, events::ChunkUnloaded>,
+    changed: EventWriter<'w, 's, events::VoxelsChanged>,
+}
+
+/// Collects the results of all completed batches. Results are merged back in the order batches
+/// were dispatched, so events and resources are updated deterministically.
 fn collect_completed_task_results(
-    mut running_task: ResMut<RunningTask>,
+    mut running_tasks: ResMut<RunningTasks>,
     mut world_res: ResMut<WorldRes>,
     mut batch_res: ResMut<GenesisCommandBuffer>,
-    mut updated_writer: EventWriter<events::ChunkUpdated>,
+    mut writers: GenesisEventWriters,
     mut chunk_resources: ChunkResources,
 ) {
-    if let Some(ref mut task) = **running_task {
-        // Check if task has finished
-        if let Some(TaskResult {
+    let mut completed = vec![];
+
+    running_tasks.tasks.retain_mut(|running| {
+        if let Some(result) = future::block_on(future::poll_once(&mut running.task)) {
+            completed.push((running.id, std::mem::take(&mut running.region), result));
+            false
+        } else {
+            true
+        }
+    });
+
+    completed.sort_by_key(|(id, _, _)| *id);
+
+    for (
+        id,
+        region,
+        TaskResult {
             world,
-            loaded,
-            unloaded,
+            mut loaded,
+            mut generated,
+            mut unloaded,
             updated,
-        }) = future::block_on(future::poll_once(task))
-        {
-            unloaded
-                .into_iter()
-                .for_each(|local| chunk_resources.remove(local));
+            changed,
+            timings,
+        },
+    ) in completed
+    {
+        unloaded.sort_by_key(|local| local.to_array());
+        unloaded.into_iter().for_each(|local| {
+            chunk_resources.remove(local);
+            writers.unloaded.send(events::ChunkUnloaded(local));
+        });
+
+        let mut updated_list = HashMap::<IVec3, SectionFlags>::new();
+        updated_list.extend(
+            loaded
+                .iter()
+                .chain(&generated)
+                .map(|&l| (l, SectionFlags::all())),
+        );
 
-            let mut updated_list = HashSet::new();
-            updated_list.extend(loaded);
-            updated_list.extend(updated);
+        for (local, sections) in updated {
+            *updated_list.entry(local).or_default() |= sections;
+        }
 
-            debug!("Completed task. Updated chunks: {}", updated_list.len());
+        debug!(
+            "Completed batch {}. Updated chunks: {}. Timings: {:?}",
+            id,
+            updated_list.len(),
+            timings
+        );
 
-            updated_list.into_iter().for_each(|local| {
-                updated_writer.send(events::ChunkUpdated(local));
-                chunk_resources.set(local, world.get(local).unwrap());
-            });
+        let mut updated_list = updated_list.into_iter().collect::<Vec<_>>();
+        updated_list.sort_by_key(|(local, _)| local.to_array());
 
-            // Give back the VoxWorld to WorldRes
-            **running_task = None;
-            world_res.set(world);
-            batch_res.finished();
-        }
+        updated_list.into_iter().for_each(|(local, sections)| {
+            writers.updated.send(events::ChunkUpdated(local, sections));
+            chunk_resources.set(local, world.get(local).unwrap());
+        });
+
+        loaded.sort_by_key(|local| local.to_array());
+        writers
+            .loaded
+            .send_batch(loaded.into_iter().map(events::ChunkLoaded));
+
+        generated.sort_by_key(|local| local.to_array());
+        writers
+            .generated
+            .send_batch(generated.into_iter().map(events::ChunkGenerated));
+
+        writers.changed.send_batch(
+            changed
+                .into_iter()
+                .map(|(chunk, changes)| events::VoxelsChanged { chunk, changes }),
+        );
+
+        // Give back the chunks owned by this batch to WorldRes
+        world_res.unlock(&region, world);
+        batch_res.finished(id);
     }
 }
 
-fn dispatch_task(
-    mut running_task: ResMut<RunningTask>,
+/// Dispatches pending edits and as many batches as possible, up to [`MAX_RUNNING_TASKS`]. Each
+/// batch contains only commands which doesn't overlap any running batch.
+///
+/// Edits are dispatched first, on their own batch, and doesn't count on running tasks limit, so
+/// they are processed as soon as their chunks are available.
+fn dispatch_tasks(
+    config: Res<GeneratorConfig>,
+    mut running_tasks: ResMut<RunningTasks>,
     mut batch_res: ResMut<GenesisCommandBuffer>,
     mut world_res: ResMut<WorldRes>,
 ) {
-    if running_task.is_running() || !batch_res.has_pending_cmds() {
-        return;
+    let id = running_tasks.next_id();
+    let edits = batch_res.take_edit_batch(id, &world_res.locked);
+
+    if !edits.is_empty() {
+        spawn_batch(
+            id,
+            edits,
+            &mut running_tasks,
+            &mut batch_res,
+            &mut world_res,
+            (*config).clone(),
+        );
     }
 
-    let commands = batch_res.swap_and_clone();
-    let commands = optimize_commands(&world_res, commands);
+    while running_tasks.tasks.len() < MAX_RUNNING_TASKS && batch_res.has_pending_cmds() {
+        let id = running_tasks.next_id();
 
-    if !commands.is_empty() {
-        let world = world_res.take();
+        let commands = batch_res.take_batch(id, &world_res.locked);
 
-        **running_task =
-            Some(AsyncComputeTaskPool::get().spawn(task::process_batch(world, commands)));
-    } else {
-        batch_res.finished();
+        if commands.is_empty() {
+            // All pending commands are waiting for running batches
+            break;
+        }
+
+        spawn_batch(
+            id,
+            commands,
+            &mut running_tasks,
+            &mut batch_res,
+            &mut world_res,
+            (*config).clone(),
+        );
     }
 }
 
+/// Locks the chunks of the given batch and spawns a task to process it.
+fn spawn_batch(
+    id: BatchId,
+    commands: Vec<ChunkCmd>,
+    running_tasks: &mut RunningTasks,
+    batch_res: &mut GenesisCommandBuffer,
+    world_res: &mut WorldRes,
+    config: GeneratorConfig,
+) {
+    let commands = optimize_commands(world_res, commands);
+
+    if commands.is_empty() {
+        batch_res.finished(id);
+        return;
+    }
+
+    let region = commands
+        .iter()
+        .flat_map(|cmd| neighborhood(cmd.local()))
+        .collect::<HashSet<_>>();
+
+    let world = world_res.lock(&region);
+    let task =
+        AsyncComputeTaskPool::get().spawn(task::process_batch(world, commands, config, true));
+
+    running_tasks.tasks.push(RunningTask { id, region, task });
+}
+
 /// This functions optimize the command list removing duplicated commands or commands that nullifies
 /// each other.
 ///
@@ -431,6 +979,10 @@ fn optimize_commands(world: &VoxWorld, commands: Vec<ChunkCmd>) -> Vec<ChunkCmd>
 mod tests {
     use super::*;
 
+    fn pending(buffer: &GenesisCommandBuffer) -> Vec<ChunkCmd> {
+        buffer.pending.iter().map(|(_, cmd)| cmd.clone()).collect()
+    }
+
     #[test]
     fn optimize_commands_preserve_insertion_order() {
         let cmds = (0..100)
@@ -627,4 +1179,331 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn command_buffer_limit_loads_per_batch() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        for i in 0..MAX_LOADS_PER_BATCH as i32 + 2 {
+            buffer.load((i, 0, 0).into());
+        }
+        buffer.unload((-1, 0, 0).into());
+        buffer.unload((MAX_LOADS_PER_BATCH as i32, 0, 0).into());
+
+        let running = buffer.take_batch(1, &HashSet::default());
+
+        assert_eq!(running.len(), MAX_LOADS_PER_BATCH + 1);
+        assert_eq!(running[0], ChunkCmd::Load((0, 0, 0).into()));
+        assert_eq!(
+            running[MAX_LOADS_PER_BATCH],
+            ChunkCmd::Unload((-1, 0, 0).into())
+        );
+
+        assert_eq!(
+            pending(&buffer),
+            vec![
+                ChunkCmd::Load((MAX_LOADS_PER_BATCH as i32, 0, 0).into()),
+                ChunkCmd::Load((MAX_LOADS_PER_BATCH as i32 + 1, 0, 0).into()),
+                ChunkCmd::Unload((MAX_LOADS_PER_BATCH as i32, 0, 0).into()),
+            ],
+            "Commands after a deferred load of the same chunk should be deferred too"
+        );
+
+        buffer.finished(1);
+        buffer.cancel_pending_loads();
+
+        assert_eq!(
+            buffer.take_batch(2, &HashSet::default()),
+            vec![ChunkCmd::Unload((MAX_LOADS_PER_BATCH as i32, 0, 0).into())]
+        );
+    }
+
+    #[test]
+    fn command_buffer_skip_locked_chunks() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        buffer.load((0, 0, 0).into());
+        buffer.load((2, 0, 0).into());
+        buffer.unload((1, 0, 0).into());
+        buffer.load((5, 0, 0).into());
+
+        let locked = neighborhood((0, 0, 0).into()).collect::<HashSet<_>>();
+        let batch = buffer.take_batch(1, &locked);
+
+        assert_eq!(
+            batch,
+            vec![ChunkCmd::Load((5, 0, 0).into())],
+            "Chunks which neighborhood overlaps a locked chunk should be kept pending"
+        );
+        assert_eq!(buffer.pending.len(), 3);
+        assert_eq!(buffer.running.len(), 1);
+
+        assert!(buffer.take_batch(2, &locked).is_empty());
+        assert_eq!(
+            buffer.running.len(),
+            1,
+            "Empty batches shouldn't be running"
+        );
+    }
+
+    #[test]
+    fn world_res_lock() {
+        let mut world_res = WorldRes::default();
+        world_res.world.add((0, 0, 0).into(), Default::default());
+        world_res.world.add((1, 0, 0).into(), Default::default());
+        world_res.world.add((5, 0, 0).into(), Default::default());
+
+        let region = neighborhood((0, 0, 0).into()).collect::<HashSet<_>>();
+        let mut world = world_res.lock(&region);
+
+        assert!(world.exists((0, 0, 0).into()));
+        assert!(world.exists((1, 0, 0).into()));
+        assert!(!world.exists((5, 0, 0).into()));
+        assert!(!world_res.exists((0, 0, 0).into()));
+        assert!(world_res.exists((5, 0, 0).into()));
+
+        world.remove((1, 0, 0).into());
+        world.add((-1, 0, 0).into(), Default::default());
+
+        world_res.unlock(&region, world);
+
+        assert!(world_res.locked.is_empty());
+        assert!(world_res.exists((0, 0, 0).into()));
+        assert!(world_res.exists((-1, 0, 0).into()));
+        assert!(!world_res.exists((1, 0, 0).into()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn world_res_lock_twice() {
+        let mut world_res = WorldRes::default();
+
+        let region = neighborhood((0, 0, 0).into()).collect::<HashSet<_>>();
+        world_res.lock(&region);
+        world_res.lock(&neighborhood((1, 0, 0).into()).collect::<HashSet<_>>());
+    }
+
+    #[test]
+    fn command_buffer_acquire_release() {
+        let mut buffer = GenesisCommandBuffer::default();
+        let local = (0, 0, 0).into();
+
+        buffer.acquire(local);
+        buffer.acquire(local);
+        buffer.cancel_pending_loads();
+        assert_eq!(
+            pending(&buffer),
+            vec![ChunkCmd::Load(local), ChunkCmd::Load(local)],
+            "Loads of acquired chunks shouldn't be canceled"
+        );
+
+        buffer.pending.clear();
+        buffer.unload(local);
+        buffer.release(local);
+        assert!(
+            buffer.pending.is_empty(),
+            "Chunk should be kept while there are holders"
+        );
+
+        buffer.load(local);
+        buffer.release(local);
+        assert_eq!(
+            pending(&buffer),
+            vec![ChunkCmd::Load(local)],
+            "Chunk loaded by load should be kept when released"
+        );
+
+        buffer.unload(local);
+        assert_eq!(
+            pending(&buffer),
+            vec![ChunkCmd::Load(local), ChunkCmd::Unload(local)]
+        );
+
+        buffer.pending.clear();
+        buffer.acquire(local);
+        buffer.release(local);
+        buffer.release(local);
+        assert_eq!(
+            pending(&buffer),
+            vec![ChunkCmd::Load(local), ChunkCmd::Unload(local)],
+            "Chunk should be unloaded once released by all holders"
+        );
+    }
+
+    #[test]
+    fn command_buffer_edits_wait_pending_loads() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        buffer.load((0, 0, 0).into());
+        buffer.update((0, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]);
+        buffer.update((5, 0, 0).into(), vec![]);
+
+        assert_eq!(
+            buffer.take_edit_batch(1, &HashSet::default()),
+            vec![ChunkCmd::Update((5, 0, 0).into(), vec![])],
+            "Edits of chunks being loaded should wait for the load"
+        );
+        assert_eq!(
+            buffer.take_batch(2, &HashSet::default()),
+            vec![ChunkCmd::Load((0, 0, 0).into())],
+            "Edits waiting for a load shouldn't reserve its chunk"
+        );
+        assert_eq!(
+            buffer.take_edit_batch(3, &HashSet::default()),
+            vec![ChunkCmd::Update(
+                (0, 0, 0).into(),
+                vec![((0, 0, 0).into(), 1.into())]
+            )]
+        );
+    }
+
+    #[test]
+    fn command_buffer_keep_order_of_each_chunk() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        buffer.update((0, 0, 0).into(), vec![]);
+        buffer.unload((0, 0, 0).into());
+        buffer.update((0, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]);
+        buffer.load((0, 0, 0).into());
+
+        assert_eq!(
+            buffer.take_edit_batch(1, &HashSet::default()),
+            vec![ChunkCmd::Update((0, 0, 0).into(), vec![])],
+            "Edits queued after an unload of the same chunk should wait for it"
+        );
+        buffer.finished(1);
+
+        assert_eq!(
+            buffer.take_batch(2, &HashSet::default()),
+            vec![ChunkCmd::Unload((0, 0, 0).into())],
+            "Commands queued after a waiting edit of the same chunk should wait for it"
+        );
+        buffer.finished(2);
+
+        assert_eq!(
+            buffer.take_edit_batch(3, &HashSet::default()),
+            vec![ChunkCmd::Update(
+                (0, 0, 0).into(),
+                vec![((0, 0, 0).into(), 1.into())]
+            )]
+        );
+        buffer.finished(3);
+
+        assert_eq!(
+            buffer.take_batch(4, &HashSet::default()),
+            vec![ChunkCmd::Load((0, 0, 0).into())]
+        );
+    }
+
+    #[test]
+    fn command_buffer_apply_whole_group() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        buffer.update_group(vec![((0, 0, 0).into(), vec![]), ((5, 0, 0).into(), vec![])]);
+        buffer.update((5, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]);
+        buffer.update((9, 0, 0).into(), vec![]);
+
+        let locked = neighborhood((1, 0, 0).into()).collect::<HashSet<_>>();
+
+        assert_eq!(
+            buffer.take_edit_batch(1, &locked),
+            vec![ChunkCmd::Update((9, 0, 0).into(), vec![])],
+            "Groups with a locked chunk and later edits of their chunks should wait"
+        );
+        assert_eq!(
+            buffer.take_edit_batch(2, &HashSet::default()),
+            vec![
+                ChunkCmd::Update((0, 0, 0).into(), vec![]),
+                ChunkCmd::Update((5, 0, 0).into(), vec![]),
+                ChunkCmd::Update((5, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn edit_keeps_neighborhood_outside_locked_region() {
+        AsyncComputeTaskPool::init(Default::default);
+        bevy_tasks::IoTaskPool::init(Default::default);
+
+        let mut world_res = WorldRes::default();
+        let locals = (-2..=2).map(|x| IVec3::new(x, 0, 0)).collect::<Vec<_>>();
+
+        for &local in &locals {
+            let mut chunk = Chunk::default();
+            chunk.kinds.set_all(1.into());
+            world_res.world.add(local, chunk);
+        }
+
+        projekto_shaping::update_neighborhood(&mut world_res.world, &locals);
+
+        let edge = (projekto_core::chunk::X_END, 1, 0).into();
+        let region = neighborhood((0, 0, 0).into()).collect::<HashSet<_>>();
+        let world = world_res.lock(&region);
+
+        let result = future::block_on(task::process_batch(
+            world,
+            vec![ChunkCmd::Update((0, 0, 0).into(), vec![(edge, 0.into())])],
+            GeneratorConfig::default(),
+            false,
+        ));
+
+        world_res.unlock(&region, result.world);
+
+        let ring = world_res.get((1, 0, 0).into()).unwrap();
+        assert_eq!(
+            ring.kinds.neighborhood.get(voxel::Side::Left, edge),
+            Some(0.into()),
+            "Edited chunk neighbor should see the edit"
+        );
+        assert!(
+            ring.kinds.neighborhood.has_side(voxel::Side::Right)
+                && ring.lights.neighborhood.has_side(voxel::Side::Right),
+            "Sides outside the locked region should be kept"
+        );
+
+        let untouched = world_res.get((2, 0, 0).into()).unwrap();
+        assert_eq!(
+            untouched.kinds.neighborhood.get(voxel::Side::Left, edge),
+            Some(1.into()),
+            "Chunk two rings away should keep its neighborhood"
+        );
+    }
+
+    #[test]
+    fn command_buffer_edits_priority() {
+        let mut buffer = GenesisCommandBuffer::default();
+
+        buffer.load((0, 0, 0).into());
+        buffer.update((5, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]);
+        buffer.unload((6, 0, 0).into());
+        buffer.update((9, 0, 0).into(), vec![]);
+
+        let locked = neighborhood((9, 0, 0).into()).collect::<HashSet<_>>();
+
+        assert_eq!(
+            buffer.take_edit_batch(1, &locked),
+            vec![ChunkCmd::Update(
+                (5, 0, 0).into(),
+                vec![((0, 0, 0).into(), 1.into())]
+            )],
+            "Only edits should be taken"
+        );
+
+        assert_eq!(
+            buffer.take_batch(2, &locked),
+            vec![
+                ChunkCmd::Load((0, 0, 0).into()),
+                ChunkCmd::Unload((6, 0, 0).into())
+            ]
+        );
+
+        buffer.update((1, 0, 0).into(), vec![]);
+        buffer.unload((2, 0, 0).into());
+
+        assert!(
+            buffer.take_batch(3, &HashSet::default()).is_empty(),
+            "Chunks needed by pending edits should be reserved"
+        );
+        assert!(buffer.has_pending_cmds());
+    }
 }
@@ -0,0 +1,78 @@
+use bevy_ecs::{
+    prelude::EventWriter,
+    system::{Res, ResMut},
+};
+use bevy_log::trace;
+use bevy_math::IVec3;
+use bevy_tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use itertools::Itertools;
+use projekto_core::voxel::VoxelVertex;
+use projekto_shaping::{self as shaping, GeneratorConfig};
+
+use super::events::ChunkLodGenerated;
+
+/// Hold level of detail generation requests.
+///
+/// Level of detail chunks doesn't exists on [`projekto_core::VoxWorld`], since they are generated
+/// using a lightweight path, without any light propagation or neighborhood. Results are sent using
+/// [`ChunkLodGenerated`] event.
+#[derive(Default, Debug)]
+pub struct LodCommandBuffer(Vec<(IVec3, u8)>);
+
+impl LodCommandBuffer {
+    /// Request a chunk to be generated at the given level of detail.
+    pub fn generate(&mut self, local: IVec3, level: u8) {
+        debug_assert!(
+            level > 0,
+            "Level 0 is full detail and should be loaded instead"
+        );
+        self.0.push((local, level));
+    }
+}
+
+#[derive(Default)]
+pub(super) struct RunningLodTasks(Vec<(IVec3, u8, Task<Vec<VoxelVertex>>)>);
+
+/// Dispatches all requested level of detail chunks. Requests are kept until there is a
+/// [`GeneratorConfig`], like on clients which didn't receive it from server yet.
+pub(super) fn dispatch_lod_tasks(
+    config: Option<Res<GeneratorConfig>>,
+    mut buffer: ResMut<LodCommandBuffer>,
+    mut running: ResMut<RunningLodTasks>,
+) {
+    let config = match config {
+        Some(config) if !buffer.0.is_empty() => config,
+        _ => return,
+    };
+
+    trace!("Dispatching {} level of detail tasks", buffer.0.len());
+
+    for (local, level) in buffer.0.drain(..).unique() {
+        let config = (*config).clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let kinds = shaping::generate_chunk_kinds(local, &config);
+            shaping::generate_lod_vertices(&kinds, level)
+        });
+
+        running.0.push((local, level, task));
+    }
+}
+
+pub(super) fn collect_lod_tasks(
+    mut running: ResMut<RunningLodTasks>,
+    mut writer: EventWriter<ChunkLodGenerated>,
+) {
+    running.0.retain_mut(|(local, level, task)| {
+        if let Some(vertices) = future::block_on(future::poll_once(task)) {
+            writer.send(ChunkLodGenerated {
+                local: *local,
+                level: *level,
+                vertices,
+            });
+            false
+        } else {
+            true
+        }
+    });
+}
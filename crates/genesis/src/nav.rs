@@ -0,0 +1,97 @@
+use bevy_ecs::{prelude::EventReader, system::ResMut};
+use projekto_core::pathfinding::NavGraph;
+
+use super::events;
+
+/// Keeps [`NavGraph`] in sync with loaded chunks. Chunks which were loaded, unloaded or had voxels
+/// changed are invalidated, so their abstract graph is rebuilt next time a path needs it.
+pub(super) fn invalidate_nav_graph(
+    mut graph: ResMut<NavGraph>,
+    mut changed_reader: EventReader<events::VoxelsChanged>,
+    mut loaded_reader: EventReader<events::ChunkLoaded>,
+    mut generated_reader: EventReader<events::ChunkGenerated>,
+    mut unloaded_reader: EventReader<events::ChunkUnloaded>,
+) {
+    for events::VoxelsChanged { chunk, .. } in changed_reader.iter() {
+        graph.invalidate(*chunk);
+    }
+
+    for events::ChunkLoaded(local) in loaded_reader.iter() {
+        graph.invalidate(*local);
+    }
+
+    for events::ChunkGenerated(local) in generated_reader.iter() {
+        graph.invalidate(*local);
+    }
+
+    for events::ChunkUnloaded(local) in unloaded_reader.iter() {
+        graph.invalidate(*local);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::{App, CoreStage};
+    use bevy_ecs::event::Events;
+    use bevy_math::IVec3;
+    use projekto_core::{
+        chunk::{self, ChunkKind},
+        voxel,
+    };
+
+    use super::*;
+    use crate::ChunkKindRes;
+
+    fn kinds() -> ChunkKindRes {
+        let mut kind = ChunkKind::default();
+        for x in 0..chunk::X_AXIS_SIZE as i32 {
+            for z in 0..chunk::Z_AXIS_SIZE as i32 {
+                kind.set((x, 0, z).into(), 1.into());
+            }
+        }
+
+        let mut kinds = ChunkKindRes::default();
+        kinds.insert((0, 0, 0).into(), kind.clone());
+        kinds.insert((1, 0, 0).into(), kind);
+        kinds
+    }
+
+    #[test]
+    fn invalidate_nav_graph() {
+        let mut kinds = kinds();
+        let mut graph = NavGraph::default();
+        let (start, goal) = (IVec3::new(1, 1, 1), IVec3::new(30, 1, 1));
+
+        assert!(graph.find_path(&kinds, start, goal).is_some());
+        assert!(graph.is_built((1, 0, 0).into()));
+
+        let mut app = App::new();
+        events::register(&mut app);
+        app.insert_resource(graph)
+            .add_system_to_stage(CoreStage::PreUpdate, super::invalidate_nav_graph);
+
+        // Wall all over the border between both chunks
+        let changes = (0..chunk::Z_AXIS_SIZE as i32)
+            .flat_map(|z| [IVec3::new(0, 1, z), IVec3::new(0, 2, z)])
+            .map(|voxel| (voxel, voxel::Kind::none(), 1.into()))
+            .collect::<Vec<_>>();
+
+        let chunk_kind = kinds.get_mut(&IVec3::X).unwrap();
+        for &(voxel, _, new) in &changes {
+            chunk_kind.set(voxel, new);
+        }
+
+        app.world
+            .resource_mut::<Events<events::VoxelsChanged>>()
+            .send(events::VoxelsChanged {
+                chunk: IVec3::X,
+                changes,
+            });
+        app.update();
+
+        let mut graph = app.world.resource_mut::<NavGraph>();
+        assert!(!graph.is_built(IVec3::ZERO));
+        assert!(!graph.is_built(IVec3::X));
+        assert_eq!(graph.find_path(&kinds, start, goal), None);
+    }
+}
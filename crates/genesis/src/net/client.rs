@@ -0,0 +1,263 @@
+use std::net::{SocketAddr, TcpStream};
+
+use bevy_app::{App, CoreStage, Plugin};
+use bevy_ecs::{
+    schedule::ParallelSystemDescriptorCoercion,
+    system::{Commands, ResMut},
+};
+use bevy_log::{error, warn};
+use bevy_math::IVec3;
+use bevy_utils::HashSet;
+use projekto_core::chunk;
+
+use crate::{
+    events, task, ChunkCmd, ChunkResources, GenesisCommandBuffer, GenesisEventWriters, GenesisLabel,
+};
+
+use super::protocol::{ChunkDelta, ClientMessage, Connection, ServerMessage, PROTOCOL_VERSION};
+
+/// Replaces [`crate::GenesisPlugin`] by a remote world, owned by a server running
+/// [`super::GenesisServerPlugin`].
+///
+/// Commands sent to [`GenesisCommandBuffer`] are forwarded to the server, which has authority over
+/// all chunks. Chunk resources and events are updated the same way as a local genesis, as soon as
+/// the server replicates the results back. Level of detail chunks are still generated locally,
+/// using the [`GeneratorConfig`](projekto_shaping::GeneratorConfig) received from the server.
+pub struct GenesisClientPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for GenesisClientPlugin {
+    fn build(&self, app: &mut App) {
+        let mut connection = TcpStream::connect(self.addr)
+            .map_err(|err| err.to_string())
+            .and_then(Connection::new)
+            .unwrap_or_else(|err| panic!("Failed to connect to {}: {}", self.addr, err));
+
+        connection.send(&ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+        });
+
+        app.insert_resource(GenesisClient {
+            connection,
+            requested: HashSet::default(),
+        })
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            receive_server_messages.label(GenesisLabel::Collect),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            send_client_commands.label(GenesisLabel::Dispatch),
+        );
+
+        crate::register_common(app);
+    }
+}
+
+/// Holds the connection to the server and which chunks were requested to it.
+pub struct GenesisClient {
+    connection: Connection,
+    requested: HashSet<IVec3>,
+}
+
+impl GenesisClient {
+    /// **Returns** `false` if the connection to the server was lost.
+    pub fn is_connected(&self) -> bool {
+        !self.connection.is_closed()
+    }
+}
+
+fn receive_server_messages(
+    mut client: ResMut<GenesisClient>,
+    mut writers: GenesisEventWriters,
+    mut chunk_resources: ChunkResources,
+    mut commands: Commands,
+) {
+    if !client.is_connected() {
+        return;
+    }
+
+    let messages = match client.connection.receive::<ServerMessage>() {
+        Ok(messages) => messages,
+        Err(err) => {
+            error!("Lost connection to server: {}", err);
+            return;
+        }
+    };
+
+    for message in messages {
+        match message {
+            ServerMessage::Welcome {
+                generator,
+                heightmap,
+            } => match generator.decode(heightmap.as_deref()) {
+                Ok(config) => commands.insert_resource(config),
+                Err(err) => error!("Failed to decode server generator config: {}", err),
+            },
+            ServerMessage::ChunkSnapshot {
+                local,
+                sections,
+                chunk,
+            } => {
+                // Chunk was unloaded while the snapshot was on the way
+                if !client.requested.contains(&local) {
+                    continue;
+                }
+
+                match task::decode_chunk(&chunk) {
+                    Ok(chunk) => {
+                        let loaded = !chunk_resources.kind.exists(local);
+
+                        chunk_resources.set(local, &chunk);
+                        writers.updated.send(events::ChunkUpdated(local, sections));
+
+                        if loaded {
+                            writers.loaded.send(events::ChunkLoaded(local));
+                        }
+                    }
+                    Err(err) => error!("Failed to decode chunk {} snapshot: {}", local, err),
+                }
+            }
+            ServerMessage::ChunkDelta {
+                local,
+                sections,
+                delta,
+            } => {
+                if !client.requested.contains(&local) || !chunk_resources.kind.exists(local) {
+                    continue;
+                }
+
+                match ChunkDelta::decode(&delta)
+                    .and_then(|delta| apply_delta(&mut chunk_resources, local, delta))
+                {
+                    Ok(()) => writers.updated.send(events::ChunkUpdated(local, sections)),
+                    Err(err) => error!("Failed to apply chunk {} delta: {}", local, err),
+                }
+            }
+            ServerMessage::VoxelsChanged { chunk, changes } => {
+                if let Some(kinds) = chunk_resources.kind.get_mut(&chunk) {
+                    for &(voxel, _, new) in &changes {
+                        kinds.set(voxel, new);
+                    }
+
+                    writers
+                        .changed
+                        .send(events::VoxelsChanged { chunk, changes });
+                }
+            }
+            ServerMessage::ChunkUnloaded(local) => {
+                if client.requested.remove(&local) {
+                    chunk_resources.remove(local);
+                    writers.unloaded.send(events::ChunkUnloaded(local));
+                }
+            }
+        }
+    }
+
+    if client.connection.is_closed() {
+        error!("Server closed the connection");
+    }
+}
+
+/// Applies the given [`ChunkDelta`] on an existing chunk. Voxels kinds are applied by
+/// [`ServerMessage::VoxelsChanged`], which is received before.
+///
+/// **Returns** an error and doesn't apply anything if the delta has sections out of chunk bounds.
+fn apply_delta(
+    chunk_resources: &mut ChunkResources,
+    local: IVec3,
+    delta: ChunkDelta,
+) -> Result<(), String> {
+    if let Some((section, _, _)) = delta
+        .sections
+        .iter()
+        .find(|(section, _, _)| *section >= chunk::SECTION_COUNT)
+    {
+        return Err(format!("Section {section} is out of bounds"));
+    }
+
+    if let Some(kinds) = chunk_resources.kind.get_mut(&local) {
+        kinds.neighborhood = delta.kinds_neighborhood;
+    }
+
+    chunk_resources.light.insert(local, delta.lights);
+
+    if let (Some(vertices), Some(connectivity)) = (
+        chunk_resources.vertex.get_mut(&local),
+        chunk_resources.connectivity.get_mut(&local),
+    ) {
+        for (section, section_vertices, section_connectivity) in delta.sections {
+            vertices[section] = section_vertices;
+            connectivity[section] = section_connectivity;
+        }
+    }
+
+    Ok(())
+}
+
+/// Forwards all pending commands to the server, in the order they were queued, so the server sees
+/// commands of each chunk in the same order. Each edit group is sent as a single message, so the
+/// server applies it as a whole.
+///
+/// Unloads are applied locally right away, since the server doesn't confirms them.
+fn send_client_commands(
+    mut client: ResMut<GenesisClient>,
+    mut buffer: ResMut<GenesisCommandBuffer>,
+    mut writers: GenesisEventWriters,
+    mut chunk_resources: ChunkResources,
+) {
+    let mut commands = std::mem::take(&mut buffer.pending)
+        .into_iter()
+        .map(|(seq, cmd)| (seq, vec![cmd]))
+        .chain(std::mem::take(&mut buffer.edits))
+        .collect::<Vec<_>>();
+    commands.sort_by_key(|(seq, _)| *seq);
+
+    let GenesisClient {
+        connection,
+        requested,
+    } = &mut *client;
+
+    for (_, group) in commands {
+        let mut updates = vec![];
+
+        for cmd in group {
+            match cmd {
+                ChunkCmd::Load(local) => {
+                    if requested.insert(local) {
+                        connection.send(&ClientMessage::Load(local));
+                    }
+                }
+                ChunkCmd::Unload(local) => {
+                    if requested.remove(&local) {
+                        connection.send(&ClientMessage::Unload(local));
+
+                        if chunk_resources.kind.exists(local) {
+                            chunk_resources.remove(local);
+                            writers.unloaded.send(events::ChunkUnloaded(local));
+                        }
+                    }
+                }
+                ChunkCmd::Update(local, voxels) => updates.push((local, voxels)),
+            }
+        }
+
+        if updates.is_empty() {
+            continue;
+        }
+
+        if let Some((local, _)) = updates
+            .iter()
+            .find(|(local, _)| !chunk_resources.kind.exists(*local))
+        {
+            warn!("Ignoring edit of chunk {} which isn't loaded", local);
+        } else {
+            connection.send(&ClientMessage::UpdateGroup(updates));
+        }
+    }
+
+    if let Err(err) = connection.flush() {
+        error!("Lost connection to server: {}", err);
+    }
+}
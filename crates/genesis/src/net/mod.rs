@@ -0,0 +1,129 @@
+//! Replicates genesis over TCP, so a single server owns the world and many clients can see and
+//! edit it.
+//!
+//! Each message is a bincode payload prefixed by its size. Clients first receive the config used by
+//! the server to generate chunks, so level of detail chunks generated locally match the server
+//! world. Chunks are sent as snapshots, encoded the same way as cache files, when a client loads
+//! them. After that, voxel changes and updated chunk sections are sent as deltas.
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::{GenesisClient, GenesisClientPlugin};
+pub use server::{GenesisServer, GenesisServerPlugin};
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_math::IVec3;
+    use bevy_tasks::{AsyncComputeTaskPool, IoTaskPool};
+    use projekto_core::voxel::Kind;
+    use projekto_shaping::GeneratorConfig;
+
+    use crate::{ChunkKindRes, ChunkVertexRes, GenesisCommandBuffer, GenesisPlugin};
+
+    use super::*;
+
+    fn create_apps() -> (App, App) {
+        AsyncComputeTaskPool::init(Default::default);
+        IoTaskPool::init(Default::default);
+
+        let mut server = App::new();
+        server
+            .add_plugin(GenesisPlugin)
+            .add_plugin(GenesisServerPlugin {
+                addr: "127.0.0.1:0".parse().unwrap(),
+            });
+
+        let addr = server.world.resource::<GenesisServer>().local_addr();
+
+        let mut client = App::new();
+        client.add_plugin(GenesisClientPlugin { addr });
+
+        (server, client)
+    }
+
+    /// Updates both apps until the given condition is met or panics after some time.
+    fn update_until(server: &mut App, client: &mut App, condition: impl Fn(&App, &App) -> bool) {
+        for _ in 0..5000 {
+            server.update();
+            client.update();
+
+            if condition(server, client) {
+                return;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        panic!("Condition wasn't met in time");
+    }
+
+    #[test]
+    fn replicate_over_loopback() {
+        let (mut server, mut client) = create_apps();
+        let local = IVec3::new(1001, 0, -1001);
+
+        client
+            .world
+            .resource_mut::<GenesisCommandBuffer>()
+            .load(local);
+
+        update_until(&mut server, &mut client, |_, client| {
+            client.world.resource::<ChunkKindRes>().exists(local)
+        });
+
+        assert_eq!(server.world.resource::<GenesisServer>().client_count(), 1);
+        assert_eq!(
+            client.world.resource::<ChunkKindRes>().get(local),
+            server.world.resource::<ChunkKindRes>().get(local),
+            "Client should receive the chunk generated by server"
+        );
+        assert!(client.world.resource::<ChunkVertexRes>().exists(local));
+        assert_eq!(
+            client.world.get_resource::<GeneratorConfig>(),
+            server.world.get_resource::<GeneratorConfig>(),
+            "Client should receive the generator config of server"
+        );
+
+        // Client edits are applied by server and replicated back as deltas. The voxel is toggled,
+        // so the edit always changes it
+        let voxel = IVec3::new(3, 255, 4);
+        let kind_of = move |app: &App| {
+            app.world
+                .resource::<ChunkKindRes>()
+                .get(local)
+                .map(|kinds| kinds.get(voxel))
+        };
+        let new_kind = if kind_of(&client).unwrap().is_none() {
+            1.into()
+        } else {
+            Kind::none()
+        };
+
+        client
+            .world
+            .resource_mut::<GenesisCommandBuffer>()
+            .update(local, vec![(voxel, new_kind)]);
+
+        update_until(&mut server, &mut client, |server, client| {
+            kind_of(server) == Some(new_kind)
+                && kind_of(client) == Some(new_kind)
+                && client.world.resource::<ChunkVertexRes>().get(local)
+                    == server.world.resource::<ChunkVertexRes>().get(local)
+        });
+
+        // Chunks loaded on behalf of a client are unloaded once it doesn't need them anymore
+        client
+            .world
+            .resource_mut::<GenesisCommandBuffer>()
+            .unload(local);
+
+        update_until(&mut server, &mut client, |server, _| {
+            !server.world.resource::<ChunkKindRes>().exists(local)
+        });
+
+        assert!(!client.world.resource::<ChunkKindRes>().exists(local));
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use bevy_log::warn;
+use bevy_math::IVec3;
+use projekto_core::{
+    chunk::{ChunkLight, ChunkNeighborhood, FacesConnectivity, SectionFlags},
+    voxel,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{events::VoxelChange, generator::GeneratorSettings, ChunkUpdate};
+
+/// Version of messages layout. Clients with a different version are disconnected.
+pub(super) const PROTOCOL_VERSION: u32 = 3;
+
+/// Max size of a single message. Bigger messages are treated as a corrupted stream.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Max size of messages queued and not sent yet. Peers which can't keep up are disconnected.
+const MAX_OUTGOING_SIZE: usize = 2 * MAX_MESSAGE_SIZE;
+
+/// Size of the length prefix of each message.
+const HEADER_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Messages sent from client to server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum ClientMessage {
+    /// First message of each connection.
+    Hello {
+        version: u32,
+    },
+    Load(IVec3),
+    Unload(IVec3),
+    /// Voxels edits of many chunks, which are applied all at once.
+    UpdateGroup(Vec<ChunkUpdate>),
+}
+
+/// Messages sent from server to client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum ServerMessage {
+    /// First message sent to each client once its hello is accepted. Holds the config used by the
+    /// server to generate chunks, so clients generate level of detail chunks the same way.
+    Welcome {
+        generator: GeneratorSettings,
+        /// Heightmap encoded as PNG. See [`GeneratorSettings`] for more.
+        heightmap: Option<Vec<u8>>,
+    },
+    /// Whole chunk encoded the same way as cache files. `sections` holds which sections had
+    /// vertices changed since the last snapshot.
+    ChunkSnapshot {
+        local: IVec3,
+        sections: SectionFlags,
+        chunk: Vec<u8>,
+    },
+    /// Encoded [`ChunkDelta`] of a chunk which the client already has. `sections` holds which
+    /// sections had vertices changed.
+    ChunkDelta {
+        local: IVec3,
+        sections: SectionFlags,
+        delta: Vec<u8>,
+    },
+    VoxelsChanged {
+        chunk: IVec3,
+        changes: Vec<VoxelChange>,
+    },
+    ChunkUnloaded(IVec3),
+}
+
+/// Chunk data which changes whenever a chunk is updated, except voxels kinds, which are sent by
+/// [`ServerMessage::VoxelsChanged`]. Only vertices and connectivity of changed sections are sent.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct ChunkDelta {
+    pub kinds_neighborhood: ChunkNeighborhood<voxel::Kind>,
+    pub lights: ChunkLight,
+    pub sections: Vec<(usize, Vec<voxel::VoxelVertex>, FacesConnectivity)>,
+}
+
+impl ChunkDelta {
+    /// Encodes this delta as lz4 compressed bincode, since lights are mostly the same value.
+    ///
+    /// **Returns** the encoded delta or a message describing why it failed.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let bincode =
+            bincode::serialize(self).map_err(|err| format!("Failed to serialize: {err}"))?;
+
+        Ok(lz4_flex::compress_prepend_size(&bincode))
+    }
+
+    /// Decodes a delta encoded by [`ChunkDelta::encode`].
+    ///
+    /// **Returns** the decoded delta or a message describing why it failed.
+    pub fn decode(encoded: &[u8]) -> Result<Self, String> {
+        let decompressed = lz4_flex::decompress_size_prepended(encoded)
+            .map_err(|err| format!("Failed to decompress: {err}"))?;
+
+        bincode::deserialize(&decompressed).map_err(|err| format!("Failed to parse: {err}"))
+    }
+}
+
+/// Non-blocking TCP connection which sends and receives length prefixed bincode messages.
+///
+/// Sent messages are queued until [`Connection::flush`] is called, up to [`MAX_OUTGOING_SIZE`].
+/// Once any error happens, the connection is closed and all further sends are ignored.
+pub(super) struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Self, String> {
+        stream
+            .set_nonblocking(true)
+            .and_then(|_| stream.set_nodelay(true))
+            .map_err(|err| format!("Failed to setup connection: {err}"))?;
+
+        Ok(Self {
+            stream,
+            incoming: vec![],
+            outgoing: vec![],
+            closed: false,
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    /// Queues the given message to be sent on next [`Connection::flush`]. The connection is closed
+    /// if the message can't be serialized or if too many messages are queued already, since the
+    /// remote side isn't keeping up.
+    pub fn send<T: Serialize>(&mut self, message: &T) {
+        if self.closed {
+            return;
+        }
+
+        match bincode::serialize(message) {
+            Ok(bytes) if self.outgoing.len() + HEADER_SIZE + bytes.len() > MAX_OUTGOING_SIZE => {
+                warn!(
+                    "Closing connection: {} bytes are waiting to be sent",
+                    self.outgoing.len()
+                );
+                self.close();
+            }
+            Ok(bytes) => {
+                self.outgoing
+                    .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                self.outgoing.extend(bytes);
+            }
+            Err(err) => {
+                warn!("Failed to serialize message: {}", err);
+                self.close();
+            }
+        }
+    }
+
+    /// Writes as much queued data as possible, without blocking.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let mut written = 0;
+
+        while !self.closed && written < self.outgoing.len() {
+            match self.stream.write(&self.outgoing[written..]) {
+                Ok(0) => return self.fail("Connection closed".to_string()),
+                Ok(n) => written += n,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return self.fail(format!("Failed to write: {err}")),
+            }
+        }
+
+        self.outgoing.drain(..written);
+
+        Ok(())
+    }
+
+    /// Reads all available data, without blocking.
+    ///
+    /// **Returns** all messages fully received or a message describing why it failed. When the
+    /// remote side closes the connection, messages received before are still returned.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Result<Vec<T>, String> {
+        let mut buffer = [0; 64 * 1024];
+
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return self.fail(format!("Failed to read: {err}")),
+            }
+        }
+
+        let mut messages = vec![];
+        let mut consumed = 0;
+
+        while self.incoming.len() - consumed >= HEADER_SIZE {
+            let header = &self.incoming[consumed..consumed + HEADER_SIZE];
+            let size = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+
+            if size > MAX_MESSAGE_SIZE {
+                return self.fail(format!("Message too big: {size} bytes"));
+            }
+
+            let begin = consumed + HEADER_SIZE;
+            if self.incoming.len() < begin + size {
+                break;
+            }
+
+            match bincode::deserialize(&self.incoming[begin..begin + size]) {
+                Ok(message) => messages.push(message),
+                Err(err) => return self.fail(format!("Failed to parse: {err}")),
+            }
+
+            consumed = begin + size;
+        }
+
+        self.incoming.drain(..consumed);
+
+        Ok(messages)
+    }
+
+    fn fail<T>(&mut self, err: String) -> Result<T, String> {
+        self.close();
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn connection_pair() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (
+            Connection::new(client).unwrap(),
+            Connection::new(server).unwrap(),
+        )
+    }
+
+    /// Flushes `sender` and receives on `receiver` until `count` messages are received.
+    fn receive_all<T: DeserializeOwned>(
+        sender: &mut Connection,
+        receiver: &mut Connection,
+        count: usize,
+    ) -> Vec<T> {
+        let mut messages = vec![];
+
+        for _ in 0..1000 {
+            sender.flush().unwrap();
+            messages.extend(receiver.receive::<T>().unwrap());
+
+            if messages.len() >= count || receiver.is_closed() {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        messages
+    }
+
+    #[test]
+    fn send_receive() {
+        let (mut client, mut server) = connection_pair();
+
+        let sent = vec![
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            ClientMessage::Load((1, -2, 3).into()),
+            ClientMessage::UpdateGroup(vec![
+                ((0, 0, 0).into(), vec![((1, 2, 3).into(), 4.into())]),
+                ((1, 0, 0).into(), vec![((0, 2, 3).into(), 5.into())]),
+            ]),
+        ];
+
+        sent.iter().for_each(|msg| client.send(msg));
+
+        assert_eq!(
+            receive_all::<ClientMessage>(&mut client, &mut server, sent.len()),
+            sent
+        );
+
+        let chunk = vec![7; 200 * 1024];
+        server.send(&ServerMessage::ChunkSnapshot {
+            local: (0, 0, 0).into(),
+            sections: SectionFlags::all(),
+            chunk: chunk.clone(),
+        });
+
+        assert_eq!(
+            receive_all::<ServerMessage>(&mut server, &mut client, 1),
+            vec![ServerMessage::ChunkSnapshot {
+                local: (0, 0, 0).into(),
+                sections: SectionFlags::all(),
+                chunk,
+            }],
+            "Messages bigger than read buffer should be received whole"
+        );
+    }
+
+    #[test]
+    fn send_too_slow() {
+        let (mut client, _server) = connection_pair();
+
+        let message = ServerMessage::ChunkSnapshot {
+            local: (0, 0, 0).into(),
+            sections: SectionFlags::all(),
+            chunk: vec![0; MAX_MESSAGE_SIZE / 2],
+        };
+
+        // Nothing is flushed, like a peer which never reads
+        for _ in 0..MAX_OUTGOING_SIZE / (MAX_MESSAGE_SIZE / 2) {
+            client.send(&message);
+        }
+
+        assert!(
+            client.is_closed(),
+            "Connection should be closed instead of growing forever"
+        );
+    }
+
+    #[test]
+    fn chunk_delta_encode() {
+        let mut delta = ChunkDelta::default();
+        delta.lights.set(
+            (1, 2, 3).into(),
+            voxel::Light::natural(voxel::Light::MAX_NATURAL_INTENSITY),
+        );
+        delta
+            .sections
+            .push((2, vec![Default::default()], Default::default()));
+
+        let decoded = ChunkDelta::decode(&delta.encode().unwrap()).unwrap();
+
+        assert_eq!(decoded.lights, delta.lights);
+        assert_eq!(decoded.sections, delta.sections);
+        assert!(ChunkDelta::decode(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn receive_closed() {
+        let (mut client, mut server) = connection_pair();
+
+        client.send(&ClientMessage::Unload(IVec3::ONE));
+        client.flush().unwrap();
+        client.close();
+
+        assert_eq!(
+            receive_all::<ClientMessage>(&mut client, &mut server, 2),
+            vec![ClientMessage::Unload(IVec3::ONE)],
+            "Messages sent before closing should be received"
+        );
+        assert!(server.is_closed());
+    }
+
+    #[test]
+    fn receive_too_big() {
+        let (mut client, mut server) = connection_pair();
+
+        client
+            .stream
+            .write_all(&(MAX_MESSAGE_SIZE as u32 + 1).to_le_bytes())
+            .unwrap();
+
+        let mut result = Ok(vec![]);
+        for _ in 0..1000 {
+            result = server.receive::<ClientMessage>();
+            if result.is_err() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert!(result.is_err());
+        assert!(server.is_closed());
+    }
+}
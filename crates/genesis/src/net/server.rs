@@ -0,0 +1,409 @@
+use std::{
+    io::ErrorKind,
+    marker::PhantomData,
+    net::{SocketAddr, TcpListener},
+};
+
+use bevy_app::{App, CoreStage, Plugin};
+use bevy_ecs::{
+    prelude::EventReader,
+    schedule::ParallelSystemDescriptorCoercion,
+    system::{Res, ResMut, SystemParam},
+};
+use bevy_log::{info, warn};
+use bevy_math::IVec3;
+use bevy_utils::HashSet;
+use projekto_core::{
+    chunk::{self, Chunk, SectionFlags},
+    voxel::{self, KindsDescs},
+};
+use projekto_shaping::GeneratorConfig;
+
+use crate::{
+    events, generator::GeneratorSettings, task, ChunkConnectivityRes, ChunkKindRes, ChunkLightRes,
+    ChunkVertexRes, GenesisCommandBuffer, GenesisLabel,
+};
+
+use super::protocol::{ChunkDelta, ClientMessage, Connection, ServerMessage, PROTOCOL_VERSION};
+
+/// Replicates genesis to remote clients connected using [`super::GenesisClientPlugin`].
+///
+/// This plugin must be added together with [`crate::GenesisPlugin`], which remains the only owner
+/// of the world. Clients requests loads, unloads and voxel edits, which are validated and forwarded
+/// to [`GenesisCommandBuffer`]. Each client receives the server [`GeneratorConfig`] once connected,
+/// a snapshot of each chunk it loads, deltas whenever those chunks are updated and a notification
+/// whenever the server unloads them.
+///
+/// Chunks requested by clients are acquired on [`GenesisCommandBuffer`], so they are kept loaded
+/// until no client needs them, without unloading chunks needed by the server itself.
+pub struct GenesisServerPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for GenesisServerPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = TcpListener::bind(self.addr)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .unwrap_or_else(|err| panic!("Failed to listen on {}: {}", self.addr, err));
+
+        info!("Genesis server listening on {}", self.addr);
+
+        app.insert_resource(GenesisServer {
+            listener,
+            next_id: 0,
+            clients: vec![],
+        })
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            receive_client_messages.after(GenesisLabel::Collect),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            replicate_changes.after(GenesisLabel::Collect),
+        );
+    }
+}
+
+type ClientId = u32;
+
+struct RemoteClient {
+    id: ClientId,
+    connection: Connection,
+    greeted: bool,
+    /// Chunks requested by this client.
+    interest: HashSet<IVec3>,
+    /// Chunks which snapshot was sent to this client, so only deltas are sent.
+    loaded: HashSet<IVec3>,
+}
+
+/// Holds connected clients and which chunks each of them has loaded.
+pub struct GenesisServer {
+    listener: TcpListener,
+    next_id: ClientId,
+    clients: Vec<RemoteClient>,
+}
+
+impl GenesisServer {
+    /// **Returns** the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// **Returns** the number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => match Connection::new(stream) {
+                    Ok(connection) => {
+                        self.next_id = self.next_id.wrapping_add(1);
+                        info!("Client {} connected from {}", self.next_id, addr);
+
+                        self.clients.push(RemoteClient {
+                            id: self.next_id,
+                            connection,
+                            greeted: false,
+                            interest: HashSet::default(),
+                            loaded: HashSet::default(),
+                        });
+                    }
+                    Err(err) => warn!("Failed to accept client {}: {}", addr, err),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Failed to accept client: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Read-only view of chunk resources, used to build chunk snapshots.
+#[derive(SystemParam)]
+struct ChunkSnapshots<'w, 's> {
+    kind: Res<'w, ChunkKindRes>,
+    light: Res<'w, ChunkLightRes>,
+    vertex: Res<'w, ChunkVertexRes>,
+    connectivity: Res<'w, ChunkConnectivityRes>,
+
+    #[system_param(ignore)]
+    _pd: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> ChunkSnapshots<'w, 's> {
+    /// **Returns** the encoded chunk or `None` if it doesn't exists.
+    fn encode(&self, local: IVec3) -> Option<Vec<u8>> {
+        let chunk = Chunk {
+            kinds: self.kind.get(local)?.clone(),
+            lights: self.light.get(local)?.clone(),
+            vertices: self.vertex.get(local)?.clone(),
+            connectivity: self.connectivity.get(local)?.clone(),
+        };
+
+        task::encode_chunk(&chunk)
+            .map_err(|err| warn!("Failed to encode chunk {}: {}", local, err))
+            .ok()
+    }
+
+    /// **Returns** the encoded [`ChunkDelta`] of the given sections or `None` if the chunk doesn't
+    /// exists.
+    fn encode_delta(&self, local: IVec3, sections: SectionFlags) -> Option<Vec<u8>> {
+        let vertices = self.vertex.get(local)?;
+        let connectivity = self.connectivity.get(local)?;
+
+        let delta = ChunkDelta {
+            kinds_neighborhood: self.kind.get(local)?.neighborhood.clone(),
+            lights: self.light.get(local)?.clone(),
+            sections: sections
+                .iter()
+                .map(|section| (section, vertices[section].clone(), connectivity[section]))
+                .collect(),
+        };
+
+        delta
+            .encode()
+            .map_err(|err| warn!("Failed to encode chunk {} delta: {}", local, err))
+            .ok()
+    }
+}
+
+fn receive_client_messages(
+    mut server: ResMut<GenesisServer>,
+    mut buffer: ResMut<GenesisCommandBuffer>,
+    config: Res<GeneratorConfig>,
+    snapshots: ChunkSnapshots,
+) {
+    server.accept_clients();
+
+    let GenesisServer { clients, .. } = &mut *server;
+
+    for client in clients.iter_mut() {
+        let messages = match client.connection.receive::<ClientMessage>() {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("Dropping client {}: {}", client.id, err);
+                vec![]
+            }
+        };
+
+        for message in messages {
+            match message {
+                ClientMessage::Hello { version } if version == PROTOCOL_VERSION => {
+                    match GeneratorSettings::encode(&config) {
+                        Ok((generator, heightmap)) => {
+                            client.greeted = true;
+                            client.connection.send(&ServerMessage::Welcome {
+                                generator,
+                                heightmap,
+                            });
+                        }
+                        Err(err) => {
+                            warn!(
+                                "Dropping client {}: failed to encode generator config: {}",
+                                client.id, err
+                            );
+                            client.connection.close();
+                            break;
+                        }
+                    }
+                }
+                ClientMessage::Hello { version } => {
+                    warn!(
+                        "Dropping client {}: protocol version {} isn't supported",
+                        client.id, version
+                    );
+                    client.connection.close();
+                    break;
+                }
+                _ if !client.greeted => {
+                    warn!("Dropping client {}: expected hello message", client.id);
+                    client.connection.close();
+                    break;
+                }
+                ClientMessage::Load(local) => {
+                    if !client.interest.insert(local) {
+                        continue;
+                    }
+
+                    // When the chunk isn't loaded yet, the snapshot is sent once it's loaded. See
+                    // `replicate_changes`
+                    buffer.acquire(local);
+
+                    if let Some(chunk) = snapshots.encode(local) {
+                        client.connection.send(&ServerMessage::ChunkSnapshot {
+                            local,
+                            sections: SectionFlags::all(),
+                            chunk,
+                        });
+                        client.loaded.insert(local);
+                    }
+                }
+                ClientMessage::Unload(local) => {
+                    if client.interest.remove(&local) {
+                        client.loaded.remove(&local);
+                        buffer.release(local);
+                    }
+                }
+                ClientMessage::UpdateGroup(updates) => {
+                    match updates.iter().try_for_each(|(local, voxels)| {
+                        validate_update(&client.interest, *local, voxels)
+                    }) {
+                        Ok(()) => buffer.update_group(updates),
+                        Err(err) => warn!("Ignoring edit of client {}: {}", client.id, err),
+                    }
+                }
+            }
+        }
+    }
+
+    clients.retain_mut(|client| {
+        if client.connection.is_closed() {
+            info!("Client {} disconnected", client.id);
+            client
+                .interest
+                .drain()
+                .for_each(|local| buffer.release(local));
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Checks if the given voxels edit of a chunk can be applied on behalf of a client, which loaded
+/// the given `interest` chunks.
+fn validate_update(
+    interest: &HashSet<IVec3>,
+    local: IVec3,
+    voxels: &[(IVec3, voxel::Kind)],
+) -> Result<(), String> {
+    if !interest.contains(&local) {
+        return Err(format!("Chunk {local} isn't loaded"));
+    }
+
+    if let Some((voxel, _)) = voxels
+        .iter()
+        .find(|(voxel, _)| !chunk::is_within_bounds(*voxel))
+    {
+        return Err(format!("Voxel {voxel} of chunk {local} is out of bounds"));
+    }
+
+    if let Some((_, kind)) = voxels
+        .iter()
+        .find(|(_, kind)| !KindsDescs::get().contains(*kind))
+    {
+        return Err(format!("Kind {kind:?} of chunk {local} doesn't exists"));
+    }
+
+    Ok(())
+}
+
+/// Sends chunks changes to all clients which has them loaded. Clients which already have the
+/// snapshot of an updated chunk receive only its delta.
+fn replicate_changes(
+    mut server: ResMut<GenesisServer>,
+    mut buffer: ResMut<GenesisCommandBuffer>,
+    mut unloaded_reader: EventReader<events::ChunkUnloaded>,
+    mut changed_reader: EventReader<events::VoxelsChanged>,
+    mut updated_reader: EventReader<events::ChunkUpdated>,
+    snapshots: ChunkSnapshots,
+) {
+    let GenesisServer { clients, .. } = &mut *server;
+
+    // Unloads are handled first, so snapshots of chunks unloaded on the same frame aren't sent.
+    for events::ChunkUnloaded(local) in unloaded_reader.iter() {
+        for client in clients.iter_mut() {
+            if client.interest.remove(local) {
+                client.loaded.remove(local);
+                client
+                    .connection
+                    .send(&ServerMessage::ChunkUnloaded(*local));
+                buffer.release(*local);
+            }
+        }
+    }
+
+    for events::VoxelsChanged { chunk, changes } in changed_reader.iter() {
+        let message = ServerMessage::VoxelsChanged {
+            chunk: *chunk,
+            changes: changes.clone(),
+        };
+
+        for client in clients.iter_mut() {
+            if client.loaded.contains(chunk) {
+                client.connection.send(&message);
+            }
+        }
+    }
+
+    for events::ChunkUpdated(local, sections) in updated_reader.iter() {
+        let (holders, others): (Vec<_>, Vec<_>) = clients
+            .iter_mut()
+            .filter(|client| client.interest.contains(local))
+            .partition(|client| client.loaded.contains(local));
+
+        if !holders.is_empty() {
+            if let Some(delta) = snapshots.encode_delta(*local, *sections) {
+                let message = ServerMessage::ChunkDelta {
+                    local: *local,
+                    sections: *sections,
+                    delta,
+                };
+
+                for client in holders {
+                    client.connection.send(&message);
+                }
+            }
+        }
+
+        if !others.is_empty() {
+            if let Some(chunk) = snapshots.encode(*local) {
+                let message = ServerMessage::ChunkSnapshot {
+                    local: *local,
+                    sections: *sections,
+                    chunk,
+                };
+
+                for client in others {
+                    client.connection.send(&message);
+                    client.loaded.insert(*local);
+                }
+            }
+        }
+    }
+
+    for client in clients.iter_mut() {
+        if let Err(err) = client.connection.flush() {
+            warn!("Dropping client {}: {}", client.id, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_update() {
+        let local = IVec3::new(1, 0, -1);
+        let interest = [local].into_iter().collect::<HashSet<_>>();
+
+        assert!(super::validate_update(&interest, local, &[(IVec3::ONE, 1.into())]).is_ok());
+        assert!(
+            super::validate_update(&interest, IVec3::ZERO, &[(IVec3::ONE, 1.into())]).is_err(),
+            "Chunks not loaded by client can't be edited"
+        );
+        assert!(
+            super::validate_update(&interest, local, &[(IVec3::new(0, -1, 0), 1.into())]).is_err(),
+            "Voxels out of bounds can't be edited"
+        );
+        assert!(
+            super::validate_update(&interest, local, &[(IVec3::ONE, u16::MAX.into())]).is_err(),
+            "Unknown kinds can't be placed"
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use bevy_math::{IVec3, Vec3};
 use bevy_utils::hashbrown::HashMap;
 use projekto_core::{
-    chunk::{self, ChunkKind, ChunkLight, ChunkStorage, ChunkStorageType},
+    chunk::{
+        self, ChunkConnectivity, ChunkKind, ChunkLight, ChunkStorage, ChunkStorageType, ChunkVertex,
+    },
+    query::KindLookup,
     voxel,
 };
 
@@ -60,11 +63,20 @@ where
 /// [`ChunkWorldRes`] holding [`ChunkKind`]
 pub type ChunkKindRes = ChunkWorldRes<ChunkKind>;
 
+impl KindLookup for ChunkKindRes {
+    fn kind_at(&self, world: IVec3) -> Option<voxel::Kind> {
+        self.get_at_world(world.as_vec3())
+    }
+}
+
 /// [`ChunkWorldRes`] holding [`ChunkLight`]
 pub type ChunkLightRes = ChunkWorldRes<ChunkLight>;
 
-/// [`ChunkWorldRes`] holding a vector of [`voxel::VoxelVertex`]
-pub type ChunkVertexRes = ChunkWorldRes<Vec<voxel::VoxelVertex>>;
+/// [`ChunkWorldRes`] holding a vector of [`voxel::VoxelVertex`] for each chunk section
+pub type ChunkVertexRes = ChunkWorldRes<ChunkVertex>;
+
+/// [`ChunkWorldRes`] holding [`chunk::FacesConnectivity`] for each chunk section
+pub type ChunkConnectivityRes = ChunkWorldRes<ChunkConnectivity>;
 
 /// Those are implements which should be used only by genesis module
 pub(super) mod impls {
@@ -1,34 +1,86 @@
 use std::{
     io::{Read, Write},
+    ops::AddAssign,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use bevy_log::{trace, warn};
 use bevy_math::IVec3;
 use bevy_tasks::{IoTaskPool, Task};
-use bevy_utils::HashSet;
+use bevy_utils::{HashMap, HashSet};
 
 use itertools::Itertools;
-use projekto_core::{chunk::Chunk, voxel, VoxWorld};
-use projekto_shaping as shaping;
+use projekto_core::{
+    chunk::{Chunk, ChunkKind, SectionFlags},
+    voxel, VoxWorld,
+};
+use projekto_shaping::{self as shaping, GeneratorConfig};
 
-use super::ChunkCmd;
+use super::{events::VoxelChange, ChunkCmd};
 
 pub(super) struct TaskResult {
     pub world: VoxWorld,
+    /// Chunks loaded from cache.
     pub loaded: Vec<IVec3>,
+    /// Chunks which doesn't exists on cache and were generated.
+    pub generated: Vec<IVec3>,
     pub unloaded: Vec<IVec3>,
-    pub updated: Vec<IVec3>,
+    pub updated: Vec<(IVec3, SectionFlags)>,
+    /// Voxels which had their kind changed by update commands.
+    pub changed: Vec<(IVec3, Vec<VoxelChange>)>,
+    pub timings: StageTimings,
+}
+
+/// Time spent on each stage of a batch processing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StageTimings {
+    /// Generation of new chunks kinds.
+    pub generate: Duration,
+    /// Waiting for chunks being loaded from cache, which runs alongside generation.
+    pub load: Duration,
+    /// Light propagation and updates, both inside chunks and across neighborhood.
+    pub light: Duration,
+    /// Vertices and faces connectivity computation.
+    pub vertices: Duration,
+    /// Saving chunks on cache.
+    pub save: Duration,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> Duration {
+        self.generate + self.load + self.light + self.vertices + self.save
+    }
 }
 
-/// Process a batch a list of [`ChunkCmd`]. This function takes ownership of [`VoxWorld`] since it
-/// needs to do modification on world.
+impl AddAssign for StageTimings {
+    fn add_assign(&mut self, rhs: Self) {
+        self.generate += rhs.generate;
+        self.load += rhs.load;
+        self.light += rhs.light;
+        self.vertices += rhs.vertices;
+        self.save += rhs.save;
+    }
+}
+
+/// Process a batch a list of [`ChunkCmd`]. This function takes ownership of a [`VoxWorld`] holding
+/// only the chunks locked by this batch, since it needs to do modification on them.
 ///
 /// This function triggers [`recompute_chunks`] whenever a new chunk is generated or is updated.
 ///
+/// New chunks are generated using the given [`GeneratorConfig`]. Updated chunks are saved on cache
+/// only when `save` is set, so tools can read the world without changing the cache.
+///
 /// ***Returns*** the [`VoxWorld`] ownership and a list of updated chunks.
-pub(super) async fn process_batch(mut world: VoxWorld, commands: Vec<ChunkCmd>) -> TaskResult {
+pub(super) async fn process_batch(
+    mut world: VoxWorld,
+    commands: Vec<ChunkCmd>,
+    config: GeneratorConfig,
+    save: bool,
+) -> TaskResult {
+    let mut timings = StageTimings::default();
+
     let SplitResult {
         load,
         unload,
@@ -50,7 +102,7 @@ pub(super) async fn process_batch(mut world: VoxWorld, commands: Vec<ChunkCmd>)
         load_task,
     } = load_chunks(&load);
 
-    let new_chunks = generate_chunks(not_found)
+    let generated = generate_chunks(not_found, &config, &mut timings)
         .await
         .into_iter()
         .map(|(local, chunk)| {
@@ -59,57 +111,256 @@ pub(super) async fn process_batch(mut world: VoxWorld, commands: Vec<ChunkCmd>)
         })
         .collect_vec();
 
+    let mut loaded = vec![];
+    let mut stale = vec![];
+    let now = Instant::now();
+
     if let Some(tasks) = load_task {
         for task in tasks {
-            task.await
-                .into_iter()
-                .for_each(|(local, chunk)| world.add(local, chunk));
+            for (local, chunk) in task.await {
+                match chunk {
+                    Ok(chunk) => {
+                        world.add(local, chunk);
+                        loaded.push(local);
+                    }
+                    Err(err) => {
+                        warn!("{}. Chunk {} will be generated again", err, local);
+                        stale.push(local);
+                    }
+                }
+            }
         }
     }
 
+    timings.load = now.elapsed();
+
+    // Chunks which failed to load are cache misses too. Those are generated after the others, so
+    // both those and chunks generated earlier next to them must have their neighborhood refreshed
+    let regenerated = generate_chunks(stale, &config, &mut timings)
+        .await
+        .into_iter()
+        .map(|(local, chunk)| {
+            world.add(local, chunk);
+            local
+        })
+        .collect_vec();
+
+    let refresh = generated
+        .iter()
+        .copied()
+        .filter(|&local| {
+            voxel::SIDES
+                .iter()
+                .any(|side| regenerated.contains(&(local + side.dir())))
+        })
+        .chain(regenerated.iter().copied())
+        .collect_vec();
+
+    let generated = generated.into_iter().chain(regenerated).collect_vec();
+
+    let (world, updated, changed) =
+        finish_chunks(world, &generated, &refresh, &update, save, &mut timings).await;
+
+    TaskResult {
+        world,
+        loaded,
+        generated,
+        unloaded: unload,
+        updated,
+        changed,
+        timings,
+    }
+}
+
+/// Adds the given chunks kinds to the [`VoxWorld`], replacing existing chunks, and computes their
+/// light and vertices, the same way generated chunks are. Chunks are saved on cache as generated.
+///
+/// Neighbors of imported chunks which aren't on the given world are loaded from cache, when they
+/// exist, so they are refreshed and saved again too, like chunks imported earlier.
+///
+/// ***Returns*** the [`VoxWorld`] ownership and a list of updated chunks.
+pub(super) async fn import_chunks(
+    mut world: VoxWorld,
+    chunks: Vec<(IVec3, ChunkKind)>,
+) -> TaskResult {
+    let mut timings = StageTimings::default();
+
+    let now = Instant::now();
+    let chunks = chunks
+        .into_iter()
+        .map(|(local, kinds)| (local, shaping::new_chunk(kinds)))
+        .collect_vec();
+    let chunks = shaping::build_chunk_internals(chunks).await;
+    timings.light = now.elapsed();
+
+    let generated = chunks
+        .into_iter()
+        .map(|(local, chunk)| {
+            world.remove(local);
+            world.add(local, chunk);
+            local
+        })
+        .collect_vec();
+
+    let now = Instant::now();
+    load_cached_neighbors(&mut world, &generated).await;
+    timings.load = now.elapsed();
+
+    let (world, updated, changed) =
+        finish_chunks(world, &generated, &[], &[], true, &mut timings).await;
+
+    TaskResult {
+        world,
+        loaded: vec![],
+        generated,
+        unloaded: vec![],
+        updated,
+        changed,
+        timings,
+    }
+}
+
+/// Loads from cache the neighbors of the given chunks which aren't on the given world, so those are
+/// refreshed and saved again together with the given chunks. Neighbors missing from cache are
+/// skipped, so nothing is generated around the given chunks.
+pub(super) async fn load_cached_neighbors(world: &mut VoxWorld, locals: &[IVec3]) {
+    let missing = locals
+        .iter()
+        .flat_map(|local| voxel::SIDES.iter().map(move |s| s.dir() + *local))
+        .filter(|local| !world.exists(*local) && !locals.contains(local))
+        .unique()
+        .collect_vec();
+
+    if let Some(tasks) = load_chunks(&missing).load_task {
+        for task in tasks {
+            for (local, chunk) in task.await {
+                match chunk {
+                    Ok(chunk) => world.add(local, chunk),
+                    Err(err) => warn!("{}. Chunk {} won't be refreshed", err, local),
+                }
+            }
+        }
+    }
+}
+
+/// Refreshes chunks surrounding the given new chunks and the given `refresh` chunks, applies the
+/// given updates, computes vertices of all affected chunks and, if `save` is set, saves them on
+/// cache.
+///
+/// ***Returns*** the [`VoxWorld`] ownership, the updated sections of each chunk and the voxels
+/// changed by updates.
+async fn finish_chunks(
+    mut world: VoxWorld,
+    generated: &[IVec3],
+    refresh: &[IVec3],
+    update: &[(IVec3, Vec<(IVec3, voxel::Kind)>)],
+    save: bool,
+    timings: &mut StageTimings,
+) -> (
+    VoxWorld,
+    Vec<(IVec3, SectionFlags)>,
+    Vec<(IVec3, Vec<VoxelChange>)>,
+) {
     // Get all chunks surrounding newly created chunks, so they can be refreshed
-    let dirty = new_chunks
+    let dirty = generated
         .iter()
         .flat_map(|local| voxel::SIDES.iter().map(move |s| s.dir() + *local))
-        .filter(|local| !new_chunks.contains(local))
+        .filter(|local| !generated.contains(local))
         .filter(|local| world.exists(*local))
+        .chain(refresh.iter().copied())
         .unique()
         .collect_vec();
 
     trace!("Generation completed! {} chunks dirty.", dirty.len());
 
+    let now = Instant::now();
+
+    // Chunks affected by neighborhood changes needs to regenerate all sections, since kind
+    // neighborhood doesn't flag any section as dirty
     let mut gen_vertices_list = if dirty.is_empty() {
         vec![]
     } else {
         shaping::update_neighborhood(&mut world, &dirty)
-    };
+    }
+    .into_iter()
+    .chain(generated.iter().copied())
+    .map(|local| (local, SectionFlags::all()))
+    .collect::<HashMap<_, _>>();
 
-    gen_vertices_list.extend(shaping::update_chunks(&mut world, &update));
-    gen_vertices_list.extend(new_chunks);
+    let changed = voxel_changes(&world, update);
+
+    for local in shaping::update_chunks(&mut world, update) {
+        *gen_vertices_list.entry(local).or_default() |= shaping::dirty_sections(&world, local);
+    }
+
+    timings.light += now.elapsed();
 
     // Compute chunk vertices
-    let locals = gen_vertices_list.into_iter().unique().collect_vec();
-    shaping::generate_chunk_vertices(&world, &locals)
+    let now = Instant::now();
+    let updated = gen_vertices_list.into_iter().collect_vec();
+    shaping::generate_chunk_vertices(&world, &updated)
         .into_iter()
-        .for_each(|(local, vertices)| {
-            world
+        .for_each(|(local, sections)| {
+            let chunk = world
                 .get_mut(local)
-                .expect("Chunk should exists on vertex generation")
-                .vertices = vertices
+                .expect("Chunk should exists on vertex generation");
+
+            for (section, vertices, connectivity) in sections {
+                chunk.vertices[section] = vertices;
+                chunk.connectivity[section] = connectivity;
+            }
         });
 
-    let world = if !locals.is_empty() {
+    let locals = updated.iter().map(|(local, _)| *local).collect_vec();
+    shaping::clear_dirty_sections(&mut world, &locals);
+
+    timings.vertices = now.elapsed();
+
+    let now = Instant::now();
+    let world = if save && !locals.is_empty() {
         save_chunks(world, &locals).await
     } else {
         world
     };
+    timings.save = now.elapsed();
 
-    TaskResult {
-        world,
-        loaded: load,
-        unloaded: unload,
-        updated: locals.into_iter().collect(),
-    }
+    (world, updated, changed)
+}
+
+/// Computes which voxels will have their kind changed by the given update list. Voxels which
+/// already have the given kind are skipped and voxels updated more than once are merged.
+///
+/// ***Returns*** a list of changed voxels, with their old and new kinds, for each chunk.
+fn voxel_changes(
+    world: &VoxWorld,
+    update: &[(IVec3, Vec<(IVec3, voxel::Kind)>)],
+) -> Vec<(IVec3, Vec<VoxelChange>)> {
+    update
+        .iter()
+        .filter_map(|(local, voxels)| {
+            let chunk = world.get(*local)?;
+
+            let mut index = HashMap::<IVec3, usize>::default();
+            let mut changes: Vec<VoxelChange> = vec![];
+
+            for &(voxel, kind) in voxels {
+                if let Some(&i) = index.get(&voxel) {
+                    changes[i].2 = kind;
+                } else {
+                    index.insert(voxel, changes.len());
+                    changes.push((voxel, chunk.kinds.get(voxel), kind));
+                }
+            }
+
+            changes.retain(|(_, old, new)| old != new);
+
+            if changes.is_empty() {
+                None
+            } else {
+                Some((*local, changes))
+            }
+        })
+        .collect()
 }
 
 /// Generate new chunks on given locals.
@@ -118,19 +369,29 @@ pub(super) async fn process_batch(mut world: VoxWorld, commands: Vec<ChunkCmd>)
 /// chunks.
 ///
 /// ***Returns*** a list of newly created chunks and their locals
-async fn generate_chunks(locals: Vec<IVec3>) -> Vec<(IVec3, Chunk)> {
+async fn generate_chunks(
+    locals: Vec<IVec3>,
+    config: &GeneratorConfig,
+    timings: &mut StageTimings,
+) -> Vec<(IVec3, Chunk)> {
     if locals.is_empty() {
         return vec![];
     }
 
     trace!("Generating {} chunks.", locals.len());
 
+    let now = Instant::now();
     let new_chunks = locals
         .iter()
-        .map(|&local| (local, shaping::generate_chunk(local)))
+        .map(|&local| (local, shaping::generate_chunk(local, config)))
         .collect_vec();
+    timings.generate += now.elapsed();
+
+    let now = Instant::now();
+    let chunks = shaping::build_chunk_internals(new_chunks).await;
+    timings.light += now.elapsed();
 
-    shaping::build_chunk_internals(new_chunks).await
+    chunks
 }
 
 /// Remove from [`VoxWorld`] all chunks on the given list.
@@ -150,14 +411,15 @@ fn unload_chunks(world: &mut VoxWorld, locals: &[IVec3]) -> HashSet<IVec3> {
     dirty_chunks
 }
 
-type LoadTask = Task<Vec<(IVec3, Chunk)>>;
+type LoadTask = Task<Vec<(IVec3, Result<Chunk, String>)>>;
 
 struct LoadChunksResult {
     not_found: Vec<IVec3>,
     load_task: Option<Vec<LoadTask>>,
 }
 
-/// Spawn a task on [`IoTaskPool`] which will load all existing chunks.
+/// Spawn a task on [`IoTaskPool`] which will load all existing chunks. Chunks which fails to load
+/// are returned as errors by the task.
 ///
 /// Chunks that doesn't exists on cache (cache miss) will be returned.
 ///
@@ -253,10 +515,8 @@ fn save_chunk(path: &Path, chunk: &Chunk) {
         .open(path)
         .unwrap_or_else(|_| panic!("Unable to write to file {}", path.display()));
 
-    let bincode = bincode::serialize(chunk)
-        .unwrap_or_else(|_| panic!("Failed to serialize cache {}", path.display()));
-
-    let compressed = lz4_flex::compress_prepend_size(&bincode);
+    let compressed = encode_chunk(chunk)
+        .unwrap_or_else(|err| panic!("Failed to serialize cache {}. {}", path.display(), err));
 
     file.write_all(&compressed).unwrap_or_else(|_| {
         panic!(
@@ -266,36 +526,88 @@ fn save_chunk(path: &Path, chunk: &Chunk) {
     });
 }
 
-fn load_chunk(path: &Path) -> Chunk {
+/// Loads a [`Chunk`] from the cache file at [`Path`].
+///
+/// **Returns** the loaded chunk or a message describing why it failed, like when the cache file was
+/// written using another [`super::CACHE_VERSION`].
+fn load_chunk(path: &Path) -> Result<Chunk, String> {
     let mut file = std::fs::OpenOptions::new()
         .read(true)
         .open(path)
-        .unwrap_or_else(|_| panic!("Unable to open file {}", path.display()));
+        .map_err(|err| format!("Unable to open file {}: {err}", path.display()))?;
 
     let mut compressed = Vec::new();
     file.read_to_end(&mut compressed)
-        .unwrap_or_else(|_| panic!("Failed to read file {}", path.display()));
+        .map_err(|err| format!("Failed to read file {}: {err}", path.display()))?;
+
+    decode_chunk(&compressed)
+        .map_err(|err| format!("Failed to load cache {}: {err}", path.display()))
+}
+
+/// Size of the cache version written before chunk data.
+const VERSION_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Encodes a chunk the same way it's stored on cache files, which is the
+/// [`super::CACHE_VERSION`] followed by a lz4 compressed bincode [`Chunk`].
+///
+/// **Returns** the encoded chunk or a message describing why it failed.
+pub(super) fn encode_chunk(chunk: &Chunk) -> Result<Vec<u8>, String> {
+    let bincode = bincode::serialize(chunk).map_err(|err| format!("Failed to serialize: {err}"))?;
+
+    let mut encoded = super::CACHE_VERSION.to_le_bytes().to_vec();
+    encoded.extend(lz4_flex::compress_prepend_size(&bincode));
+
+    Ok(encoded)
+}
+
+/// Decodes the content of a chunk cache file, which is the [`super::CACHE_VERSION`] followed by a
+/// lz4 compressed bincode [`Chunk`].
+///
+/// **Returns** the decoded chunk or a message describing why it failed.
+pub fn decode_chunk(encoded: &[u8]) -> Result<Chunk, String> {
+    if encoded.len() < VERSION_SIZE {
+        return Err("Failed to read cache version: data is too short".to_string());
+    }
 
-    let decompressed = lz4_flex::decompress_size_prepended(&compressed)
-        .unwrap_or_else(|_| panic!("Failed to decompress cache {}", path.display()));
+    let (version, compressed) = encoded.split_at(VERSION_SIZE);
+    let version = u32::from_le_bytes(version.try_into().unwrap());
 
-    bincode::deserialize(&decompressed)
-        .unwrap_or_else(|_| panic!("Failed to parse file {}", path.display()))
+    if version != super::CACHE_VERSION {
+        return Err(format!(
+            "Unsupported cache version {version}. Expected {}",
+            super::CACHE_VERSION
+        ));
+    }
+
+    let decompressed = lz4_flex::decompress_size_prepended(compressed)
+        .map_err(|err| format!("Failed to decompress: {err}"))?;
+
+    bincode::deserialize(&decompressed).map_err(|err| format!("Failed to parse: {err}"))
+}
+
+/// **Returns** the path of the cache file of the given chunk.
+pub fn cache_path(local: IVec3) -> PathBuf {
+    local_path(&local)
 }
 
 fn local_path(local: &IVec3) -> PathBuf {
+    cache_file(&format_local(local)).with_extension(super::CACHE_EXT)
+}
+
+/// **Returns** the path of the given file inside cache directory.
+pub(super) fn cache_file(name: &str) -> PathBuf {
+    // Each test process has its own temporary cache, so tests never touch the real cache
     #[cfg(test)]
     {
-        std::env::temp_dir()
-            .with_file_name(format_local(local))
-            .with_extension(super::CACHE_EXT)
+        let dir = std::env::temp_dir().join(format!("projekto-genesis-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir.join(name)
     }
 
     #[cfg(not(test))]
     {
-        PathBuf::from(super::CACHE_PATH)
-            .with_file_name(format_local(local))
-            .with_extension(super::CACHE_EXT)
+        PathBuf::from(super::CACHE_PATH).with_file_name(name)
     }
 }
 
@@ -391,7 +703,7 @@ mod tests {
         let task = tasks.unwrap().remove(0);
         let chunks = block_on(task);
         assert!(
-            chunks.iter().any(|(l, _)| *l == local),
+            chunks.iter().any(|(l, c)| *l == local && c.is_ok()),
             "Chunk should be added to world"
         );
 
@@ -419,6 +731,75 @@ mod tests {
         assert!(not_found.contains(&local));
     }
 
+    #[test]
+    fn import_chunks_refresh_cached_neighbors() {
+        IoTaskPool::init(Default::default);
+        bevy_tasks::AsyncComputeTaskPool::init(Default::default);
+
+        let local = (9611, 0, 9611).into();
+        let neighbor = (9612, 0, 9611).into();
+        create_chunk_on_disk(&super::local_path(&neighbor), &Chunk::default());
+
+        let mut kinds = ChunkKind::default();
+        kinds.set_all(1.into());
+
+        let result = block_on(super::import_chunks(
+            VoxWorld::default(),
+            vec![(local, kinds)],
+        ));
+
+        let _ = remove_file(super::local_path(&local));
+        let _ = remove_file(super::local_path(&neighbor));
+
+        let chunk = result
+            .world
+            .get(neighbor)
+            .expect("Cached neighbor should be loaded");
+        assert_eq!(
+            chunk.kinds.neighborhood.get(
+                voxel::Side::Left,
+                (projekto_core::chunk::X_END, 0, 0).into()
+            ),
+            Some(1.into()),
+            "Cached neighbor should be refreshed"
+        );
+        assert!(result.updated.iter().any(|(l, _)| *l == neighbor));
+        assert!(
+            !result.world.exists((9610, 0, 9611).into()),
+            "Neighbors missing from cache shouldn't be generated"
+        );
+    }
+
+    #[test]
+    fn load_cached_neighbors() {
+        IoTaskPool::init(Default::default);
+
+        let local = (9711, 0, 9711).into();
+        let neighbor = (9712, 0, 9711).into();
+        let requested = (9710, 0, 9711).into();
+        create_chunk_on_disk(&super::local_path(&neighbor), &Chunk::default());
+        create_chunk_on_disk(&super::local_path(&requested), &Chunk::default());
+
+        let mut world = VoxWorld::default();
+        block_on(super::load_cached_neighbors(
+            &mut world,
+            &[local, requested],
+        ));
+
+        let _ = remove_file(super::local_path(&neighbor));
+        let _ = remove_file(super::local_path(&requested));
+
+        assert!(world.exists(neighbor), "Cached neighbor should be loaded");
+        assert!(
+            !world.exists(requested),
+            "Given chunks shouldn't be loaded as neighbors"
+        );
+        assert!(
+            !world.exists((9711, 0, 9712).into()),
+            "Neighbors missing from cache shouldn't be generated"
+        );
+    }
+
     #[test]
     fn local_path_test() {
         let path = super::local_path(&(0, 0, 0).into())
@@ -464,9 +845,9 @@ mod tests {
             .open(&temp_file)
             .unwrap();
 
-        let mut compressed = Vec::new();
-        file.read_to_end(&mut compressed).unwrap();
-        let uncompressed = lz4_flex::decompress_size_prepended(&compressed).unwrap();
+        let mut encoded = Vec::new();
+        file.read_to_end(&mut encoded).unwrap();
+        let uncompressed = lz4_flex::decompress_size_prepended(&encoded[VERSION_SIZE..]).unwrap();
         let loaded_chunk = bincode::deserialize::<Chunk>(&uncompressed).unwrap();
         assert_eq!(chunk, loaded_chunk);
 
@@ -487,9 +868,8 @@ mod tests {
             .open(path)
             .unwrap();
 
-        let uncompressed = bincode::serialize(chunk).unwrap();
-        let compressed = lz4_flex::compress_prepend_size(&uncompressed);
-        file.write_all(&compressed).unwrap();
+        file.write_all(&super::encode_chunk(chunk).unwrap())
+            .unwrap();
     }
 
     #[test]
@@ -515,7 +895,7 @@ mod tests {
         let path = local_path(&local);
         create_chunk_on_disk(&path, &chunk);
 
-        let loaded_chunk = super::load_chunk(&path);
+        let loaded_chunk = super::load_chunk(&path).unwrap();
 
         assert_eq!(chunk, loaded_chunk);
 
@@ -536,10 +916,85 @@ mod tests {
 
         assert!(path.exists());
 
-        let loaded_cache = super::load_chunk(&path);
+        let loaded_cache = super::load_chunk(&path).unwrap();
 
         assert_eq!(chunk, loaded_cache);
 
         remove_file(path).unwrap();
     }
+
+    #[test]
+    fn voxel_changes() {
+        let mut world = VoxWorld::default();
+        let mut chunk = Chunk::default();
+        chunk.kinds.set((0, 0, 0).into(), 1.into());
+        world.add((0, 0, 0).into(), chunk);
+
+        let update = vec![
+            (
+                (0, 0, 0).into(),
+                vec![
+                    ((0, 0, 0).into(), 2.into()),
+                    ((1, 0, 0).into(), 0.into()),
+                    ((2, 0, 0).into(), 3.into()),
+                    ((0, 0, 0).into(), 4.into()),
+                ],
+            ),
+            ((1, 0, 0).into(), vec![((0, 0, 0).into(), 1.into())]),
+        ];
+
+        let changed = super::voxel_changes(&world, &update);
+
+        assert_eq!(
+            changed,
+            vec![(
+                (0, 0, 0).into(),
+                vec![
+                    ((0, 0, 0).into(), 1.into(), 4.into()),
+                    ((2, 0, 0).into(), 0.into(), 3.into()),
+                ]
+            )],
+            "Unchanged voxels and missing chunks should be skipped"
+        );
+    }
+
+    #[test]
+    fn decode_chunk() {
+        let mut chunk = Chunk::default();
+        chunk.kinds.set((1, 2, 3).into(), 4.into());
+
+        let encoded = super::encode_chunk(&chunk).unwrap();
+        let decoded = super::decode_chunk(&encoded).unwrap();
+
+        assert_eq!(decoded.kinds.get((1, 2, 3).into()), 4.into());
+
+        assert!(super::decode_chunk(&encoded[..encoded.len() / 2]).is_err());
+        assert!(super::decode_chunk(&[]).is_err());
+
+        let mut old_version = encoded;
+        old_version[..VERSION_SIZE]
+            .copy_from_slice(&(super::super::CACHE_VERSION + 1).to_le_bytes());
+        assert!(
+            super::decode_chunk(&old_version).is_err(),
+            "Caches of other versions should be rejected"
+        );
+    }
+
+    #[test]
+    fn load_stale_cache() {
+        let local = (-9997, 0, 9997).into();
+        let path = local_path(&local);
+
+        // Cache written without any version, like the ones before cache versioning
+        let unversioned =
+            lz4_flex::compress_prepend_size(&bincode::serialize(&Chunk::default()).unwrap());
+        std::fs::write(&path, unversioned).unwrap();
+
+        assert!(
+            super::load_chunk(&path).is_err(),
+            "Stale caches should fail to load instead of panicking"
+        );
+
+        remove_file(path).unwrap();
+    }
 }
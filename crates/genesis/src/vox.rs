@@ -0,0 +1,427 @@
+//! MagicaVoxel `.vox` models import and export.
+//!
+//! MagicaVoxel uses Z as up axis, so model `(x, y, z)` is placed at world `(x, z, size.y - 1 - y)`,
+//! which keeps the model from being mirrored.
+
+use std::path::Path;
+
+use bevy_math::IVec3;
+use bevy_utils::HashMap;
+use projekto_core::{
+    query,
+    voxel::{self, KindSidesDesc},
+};
+use serde::Deserialize;
+
+use super::WorldEdit;
+
+const VOX_MAGIC: &[u8; 4] = b"VOX ";
+const VOX_VERSION: u32 = 150;
+
+/// Max size of a model on each axis.
+pub const VOX_MAX_SIZE: i32 = 256;
+
+/// RGBA color of a palette entry.
+pub type VoxColor = [u8; 4];
+
+/// A single MagicaVoxel model. Only the first model of a file is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxModel {
+    /// Model size, in MagicaVoxel space.
+    pub size: IVec3,
+    /// Voxels position, in MagicaVoxel space, and their palette index.
+    pub voxels: Vec<(IVec3, u8)>,
+    /// Palette colors, indexed by voxel palette index. Index 0 is never used by voxels.
+    pub palette: Box<[VoxColor; 256]>,
+}
+
+/// Maps palette colors to kinds, loaded from a ron file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VoxMapping {
+    pub colors: Vec<VoxColorMapping>,
+    /// Kind used by colors which aren't mapped. Those voxels are skipped when `None`.
+    pub default: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoxColorMapping {
+    pub color: VoxColor,
+    pub kind: u16,
+}
+
+impl VoxMapping {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|err| format!("Failed to open {}: {err}", path.display()))?;
+
+        ron::de::from_reader(file)
+            .map_err(|err| format!("Failed to parse {}: {err}", path.display()))
+    }
+
+    /// **Returns** the kind of the given color or the default kind if it isn't mapped.
+    pub fn kind(&self, color: VoxColor) -> Option<voxel::Kind> {
+        self.colors
+            .iter()
+            .find(|mapping| mapping.color == color)
+            .map(|mapping| mapping.kind)
+            .or(self.default)
+            .map(voxel::Kind::from)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("Unexpected end of file".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+impl VoxModel {
+    /// Parses the content of a `.vox` file. Chunks other than `SIZE`, `XYZI` and `RGBA` are
+    /// skipped.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != VOX_MAGIC {
+            return Err("Not a vox file".to_string());
+        }
+        reader.u32()?;
+
+        if reader.take(4)? != b"MAIN" {
+            return Err("Missing MAIN chunk".to_string());
+        }
+        let content = reader.u32()? as usize;
+        reader.u32()?;
+        reader.take(content)?;
+
+        let mut size = None;
+        let mut voxels = None;
+        let mut palette = None;
+
+        while !reader.bytes.is_empty() {
+            let id = reader.take(4)?;
+            let content = reader.u32()? as usize;
+            let children = reader.u32()? as usize;
+            let mut chunk = Reader {
+                bytes: reader.take(content)?,
+            };
+            reader.take(children)?;
+
+            match id {
+                b"SIZE" if size.is_none() => {
+                    size = Some(IVec3::new(
+                        chunk.u32()? as i32,
+                        chunk.u32()? as i32,
+                        chunk.u32()? as i32,
+                    ));
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let count = chunk.u32()? as usize;
+                    let data = chunk.take(count * 4)?;
+
+                    voxels = Some(
+                        data.chunks_exact(4)
+                            .map(|v| (IVec3::new(v[0] as i32, v[1] as i32, v[2] as i32), v[3]))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                b"RGBA" => {
+                    let mut colors = Box::new([[0; 4]; 256]);
+                    for (i, color) in chunk.take(256 * 4)?.chunks_exact(4).take(255).enumerate() {
+                        colors[i + 1] = color.try_into().unwrap();
+                    }
+                    palette = Some(colors);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            size: size.ok_or("Missing SIZE chunk")?,
+            voxels: voxels.ok_or("Missing XYZI chunk")?,
+            palette: palette.ok_or("Missing RGBA chunk")?,
+        })
+    }
+
+    /// Encodes this model as the content of a `.vox` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+            out.extend(id);
+            out.extend((content.len() as u32).to_le_bytes());
+            out.extend(0u32.to_le_bytes());
+            out.extend(content);
+        }
+
+        let size = [self.size.x, self.size.y, self.size.z]
+            .iter()
+            .flat_map(|&axis| (axis as u32).to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut xyzi = (self.voxels.len() as u32).to_le_bytes().to_vec();
+        for &(position, index) in &self.voxels {
+            xyzi.extend([position.x as u8, position.y as u8, position.z as u8, index]);
+        }
+
+        let mut rgba = self.palette[1..].concat();
+        rgba.extend([0; 4]);
+
+        let mut children = vec![];
+        write_chunk(&mut children, b"SIZE", &size);
+        write_chunk(&mut children, b"XYZI", &xyzi);
+        write_chunk(&mut children, b"RGBA", &rgba);
+
+        let mut bytes = VOX_MAGIC.to_vec();
+        bytes.extend(VOX_VERSION.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+
+        bytes
+    }
+
+    /// Creates a [`WorldEdit`] which places this model with its minimum corner at `origin` world
+    /// voxel. Only model voxels are edited, so empty space around the model is kept as it is.
+    pub fn to_world_edit(&self, mapping: &VoxMapping, origin: IVec3) -> WorldEdit {
+        let mut edit = WorldEdit::default();
+
+        for &(position, index) in &self.voxels {
+            if let Some(kind) = mapping.kind(self.palette[index as usize]) {
+                edit.set(origin + to_world(position, self.size), kind);
+            }
+        }
+
+        edit
+    }
+
+    /// Creates a model of all non-empty voxels of the box between `begin` and `end` world voxels,
+    /// both inclusive. Each kind gets its own palette entry, colored by [`kind_color`].
+    ///
+    /// **Returns** the created model or an error if the box is too big or has too many kinds.
+    pub fn from_region(
+        begin: IVec3,
+        end: IVec3,
+        kind_at: impl Fn(IVec3) -> voxel::Kind,
+    ) -> Result<Self, String> {
+        let (begin, end) = (begin.min(end), begin.max(end));
+        let extent = end - begin + IVec3::ONE;
+
+        if extent.max_element() > VOX_MAX_SIZE {
+            return Err(format!(
+                "Region {extent} is bigger than {VOX_MAX_SIZE} voxels"
+            ));
+        }
+
+        let size = IVec3::new(extent.x, extent.z, extent.y);
+
+        let mut palette = Box::new([[0; 4]; 256]);
+        let mut indices = HashMap::<u16, u8>::default();
+        let mut voxels = vec![];
+
+        for world in query::range_inclusive(begin, end) {
+            let kind = kind_at(world);
+
+            if kind.is_none() {
+                continue;
+            }
+
+            let id = u16::from(kind);
+            let index = match indices.get(&id) {
+                Some(&index) => index,
+                None => {
+                    let index = indices.len() + 1;
+                    if index >= palette.len() {
+                        return Err("Region has more kinds than palette entries".to_string());
+                    }
+
+                    palette[index] = kind_color(id);
+                    indices.insert(id, index as u8);
+                    index as u8
+                }
+            };
+
+            voxels.push((to_vox(world - begin, size), index));
+        }
+
+        Ok(Self {
+            size,
+            voxels,
+            palette,
+        })
+    }
+}
+
+/// Converts a MagicaVoxel model position to a world offset.
+fn to_world(position: IVec3, size: IVec3) -> IVec3 {
+    IVec3::new(position.x, position.z, size.y - 1 - position.y)
+}
+
+/// Converts a world offset to a MagicaVoxel model position. This is the inverse of [`to_world`].
+fn to_vox(offset: IVec3, size: IVec3) -> IVec3 {
+    IVec3::new(offset.x, size.y - 1 - offset.z, offset.y)
+}
+
+/// **Returns** the color of the up side of the given kind, as described on
+/// [`voxel::KindSideTexture`], or white if there is no kind description.
+pub fn kind_color(id: u16) -> VoxColor {
+    let side = voxel::KindsDescs::get()
+        .descriptions
+        .iter()
+        .find(|desc| desc.id == id)
+        .and_then(|desc| match desc.sides {
+            KindSidesDesc::None => None,
+            KindSidesDesc::All(side) => Some(side),
+            KindSidesDesc::Unique { up, .. } => Some(up),
+        });
+
+    match side {
+        Some(side) => {
+            let (r, g, b, a) = side.color;
+            [r, g, b, a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        }
+        None => [255; 4],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> VoxModel {
+        let mut palette = Box::new([[0; 4]; 256]);
+        palette[1] = [255, 0, 0, 255];
+        palette[2] = [0, 255, 0, 255];
+
+        VoxModel {
+            size: (2, 3, 4).into(),
+            voxels: vec![((0, 0, 0).into(), 1), ((1, 2, 3).into(), 2)],
+            palette,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let model = model();
+        let bytes = model.to_bytes();
+
+        assert_eq!(&bytes[0..4], VOX_MAGIC);
+        assert_eq!(VoxModel::parse(&bytes), Ok(model));
+    }
+
+    #[test]
+    fn parse_skips_unknown_chunks() {
+        let bytes = model().to_bytes();
+
+        // Inserts an unknown chunk, with a child, before SIZE chunk.
+        let mut unknown = b"nTRN".to_vec();
+        unknown.extend(2u32.to_le_bytes());
+        unknown.extend(12u32.to_le_bytes());
+        unknown.extend([1, 2]);
+        unknown.extend(b"LAYR");
+        unknown.extend(0u32.to_le_bytes());
+        unknown.extend(0u32.to_le_bytes());
+
+        let mut patched = bytes[..20].to_vec();
+        patched.extend(&unknown);
+        patched.extend(&bytes[20..]);
+
+        assert_eq!(VoxModel::parse(&patched), Ok(model()));
+
+        assert!(VoxModel::parse(b"NOPE").is_err());
+        assert!(VoxModel::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn load_mapping() {
+        let mapping =
+            VoxMapping::load(format!("{}/voxels/vox_mapping.ron", env!("ASSETS_PATH"))).unwrap();
+
+        assert_eq!(mapping.kind([0, 255, 0, 255]), Some(2.into()));
+        assert_eq!(mapping.kind([1, 2, 3, 4]), mapping.default.map(Into::into));
+    }
+
+    #[test]
+    fn to_world_edit() {
+        let mapping = VoxMapping {
+            colors: vec![VoxColorMapping {
+                color: [255, 0, 0, 255],
+                kind: 3,
+            }],
+            default: None,
+        };
+
+        let edit = model().to_world_edit(&mapping, (10, 5, -1).into());
+        assert_eq!(edit.len(), 1, "Unmapped colors should be skipped");
+        assert_eq!(
+            edit.into_updates(),
+            vec![((0, 0, 0).into(), vec![((10, 5, 1).into(), 3.into())])]
+        );
+
+        let mapping = VoxMapping {
+            default: Some(1),
+            ..mapping
+        };
+        let edit = model().to_world_edit(&mapping, IVec3::ZERO);
+        assert_eq!(edit.len(), 2);
+        assert_eq!(
+            edit.into_updates()[0].1,
+            vec![((0, 0, 2).into(), 3.into()), ((1, 3, 0).into(), 1.into())]
+        );
+    }
+
+    #[test]
+    fn from_region() {
+        let kind_at = |world: IVec3| {
+            if world == IVec3::new(1, 2, 3) {
+                voxel::Kind::from(1)
+            } else if world == IVec3::new(0, 0, 0) {
+                voxel::Kind::from(2)
+            } else {
+                voxel::Kind::none()
+            }
+        };
+
+        let model = VoxModel::from_region((0, 0, 0).into(), (1, 2, 3).into(), kind_at).unwrap();
+
+        assert_eq!(model.size, IVec3::new(2, 4, 3));
+        assert_eq!(model.voxels.len(), 2);
+        assert_eq!(model.palette[1], kind_color(2));
+        assert_eq!(model.palette[0], [0; 4]);
+
+        let mapping = VoxMapping {
+            colors: vec![],
+            default: Some(1),
+        };
+        let edit = model.to_world_edit(&mapping, IVec3::ZERO);
+        let voxels = edit
+            .into_updates()
+            .into_iter()
+            .flat_map(|(_, voxels)| voxels)
+            .map(|(voxel, _)| voxel)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            voxels,
+            vec![IVec3::new(0, 0, 0), IVec3::new(1, 2, 3)],
+            "Exported model should be placed back at the same voxels"
+        );
+
+        assert!(VoxModel::from_region(IVec3::ZERO, IVec3::splat(256), kind_at).is_err());
+    }
+}
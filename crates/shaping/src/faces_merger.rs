@@ -78,7 +78,9 @@ fn should_merge(
             occlusion,
         )
         && chunk.kinds.get(voxel) == chunk.kinds.get(next_voxel)
-        && chunk_smooth_light.get(voxel).get(side) == chunk_smooth_light.get(next_voxel).get(side)
+        && chunk_smooth_light
+            .get(voxel)
+            .is_light_equal(&chunk_smooth_light.get(next_voxel), side)
 }
 
 /**
@@ -320,13 +322,9 @@ pub(super) fn merge(
                 vertices: [v1, v2, v3, v4],
                 side,
                 kind,
-                light: smooth_light.get(side),
-                voxel: [
-                    projekto_core::math::pack(v1.x as u8, v1.y as u8, v1.z as u8, 0),
-                    projekto_core::math::pack(v2.x as u8, v2.y as u8, v2.z as u8, 0),
-                    projekto_core::math::pack(v3.x as u8, v3.y as u8, v3.z as u8, 0),
-                    projekto_core::math::pack(v4.x as u8, v4.y as u8, v4.z as u8, 0),
-                ],
+                natural_light: smooth_light.get_natural(side),
+                artificial_light: smooth_light.get_artificial(side),
+                occlusion: smooth_light.get_occlusion(side),
             })
         }
     }
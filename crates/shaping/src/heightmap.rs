@@ -0,0 +1,314 @@
+use bevy_math::{IVec2, IVec3};
+use projekto_core::chunk::{self, ChunkKind};
+
+/// Surface height of each voxel column of a rectangular world area.
+///
+/// Heights are in voxels, so a column of height `h` has voxels filled from `0` up to `h - 1`. When
+/// encoded as PNG, heights are scaled from `0..=Y_AXIS_SIZE` to the full range of a 16-bit
+/// grayscale image.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Heightmap {
+    /// World X and Z coordinates of the first column.
+    pub origin: IVec2,
+    width: u32,
+    depth: u32,
+    heights: Vec<u16>,
+}
+
+impl Heightmap {
+    /// Creates a new heightmap with all columns heights set to zero.
+    pub fn new(origin: IVec2, width: u32, depth: u32) -> Self {
+        Self {
+            origin,
+            width,
+            depth,
+            heights: vec![0; (width * depth) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn index(&self, world_x: i32, world_z: i32) -> Option<usize> {
+        let x = world_x - self.origin.x;
+        let z = world_z - self.origin.y;
+
+        if x < 0 || z < 0 || x >= self.width as i32 || z >= self.depth as i32 {
+            None
+        } else {
+            Some((z as u32 * self.width + x as u32) as usize)
+        }
+    }
+
+    /// **Returns** the height of the given world column or `None` if it's outside the heightmap.
+    pub fn get(&self, world_x: i32, world_z: i32) -> Option<u16> {
+        self.index(world_x, world_z).map(|i| self.heights[i])
+    }
+
+    /// Sets the height of the given world column. Columns outside the heightmap are ignored and
+    /// heights are clamped to chunk height.
+    pub fn set(&mut self, world_x: i32, world_z: i32, height: u16) {
+        if let Some(i) = self.index(world_x, world_z) {
+            self.heights[i] = height.min(chunk::Y_AXIS_SIZE as u16);
+        }
+    }
+
+    /// Creates a heightmap covering all columns of chunks between `begin` and `end`, both
+    /// inclusive. Chunks which `kinds` returns `None` have zero height.
+    pub fn from_chunks<'a>(
+        begin: IVec3,
+        end: IVec3,
+        kinds: impl Fn(IVec3) -> Option<&'a ChunkKind>,
+    ) -> Self {
+        let (begin, end) = (begin.min(end), begin.max(end));
+        let chunks = end - begin + IVec3::ONE;
+
+        let mut heightmap = Self::new(
+            IVec2::new(
+                begin.x * chunk::X_AXIS_SIZE as i32,
+                begin.z * chunk::Z_AXIS_SIZE as i32,
+            ),
+            (chunks.x * chunk::X_AXIS_SIZE as i32) as u32,
+            (chunks.z * chunk::Z_AXIS_SIZE as i32) as u32,
+        );
+
+        for cx in begin.x..=end.x {
+            for cz in begin.z..=end.z {
+                let kinds = match kinds(IVec3::new(cx, begin.y, cz)) {
+                    Some(kinds) => kinds,
+                    None => continue,
+                };
+
+                for x in 0..chunk::X_AXIS_SIZE as i32 {
+                    for z in 0..chunk::Z_AXIS_SIZE as i32 {
+                        heightmap.set(
+                            cx * chunk::X_AXIS_SIZE as i32 + x,
+                            cz * chunk::Z_AXIS_SIZE as i32 + z,
+                            surface_height(kinds, x, z),
+                        );
+                    }
+                }
+            }
+        }
+
+        heightmap
+    }
+
+    /// Decodes a PNG image into a heightmap placed at the given origin. Only the first channel is
+    /// used, so grayscale images are expected. Both 8-bit and 16-bit images are supported.
+    ///
+    /// **Returns** the decoded heightmap or a message describing why it failed.
+    pub fn from_png(bytes: &[u8], origin: IVec2) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND);
+
+        let mut reader = decoder
+            .read_info()
+            .map_err(|err| format!("Failed to read png info: {err}"))?;
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buffer)
+            .map_err(|err| format!("Failed to decode png: {err}"))?;
+
+        let channels = info.color_type.samples();
+        let sample_size = match info.bit_depth {
+            png::BitDepth::Eight => 1,
+            png::BitDepth::Sixteen => 2,
+            depth => return Err(format!("Unsupported png bit depth {depth:?}")),
+        };
+        let max_sample = if sample_size == 1 {
+            u8::MAX as u32
+        } else {
+            u16::MAX as u32
+        };
+
+        let heights = buffer[..info.buffer_size()]
+            .chunks_exact(channels * sample_size)
+            .map(|pixel| {
+                let sample = if sample_size == 1 {
+                    pixel[0] as u32
+                } else {
+                    u16::from_be_bytes([pixel[0], pixel[1]]) as u32
+                };
+
+                to_height(sample, max_sample)
+            })
+            .collect();
+
+        Ok(Self {
+            origin,
+            width: info.width,
+            depth: info.height,
+            heights,
+        })
+    }
+
+    /// Loads a PNG heightmap file and centers it on world origin.
+    ///
+    /// **Returns** the loaded heightmap or a message describing why it failed.
+    pub fn load_centered(path: &str) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|err| format!("Failed to read heightmap {path}: {err}"))?;
+
+        let mut heightmap = Self::from_png(&bytes, IVec2::ZERO)?;
+        heightmap.origin = -IVec2::new(heightmap.width as i32, heightmap.depth as i32) / 2;
+
+        Ok(heightmap)
+    }
+
+    /// Encodes this heightmap as a 16-bit grayscale PNG image. Each row of the image is a Z
+    /// coordinate and each column is a X coordinate.
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.depth);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+
+        let data = self
+            .heights
+            .iter()
+            .flat_map(|&height| to_sample(height).to_be_bytes())
+            .collect::<Vec<_>>();
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(|err| format!("Failed to encode png: {err}"))?;
+
+        Ok(bytes)
+    }
+}
+
+impl std::fmt::Debug for Heightmap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Heights are skipped, since those are too many to be useful on logs.
+        f.debug_struct("Heightmap")
+            .field("origin", &self.origin)
+            .field("width", &self.width)
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
+
+fn to_sample(height: u16) -> u16 {
+    (height as u32 * u16::MAX as u32 / chunk::Y_AXIS_SIZE as u32) as u16
+}
+
+fn to_height(sample: u32, max_sample: u32) -> u16 {
+    ((sample * chunk::Y_AXIS_SIZE as u32 + max_sample / 2) / max_sample) as u16
+}
+
+/// **Returns** the number of voxels from the bottom of the chunk up to the topmost non-empty voxel
+/// of the given column, or zero if the column is empty.
+pub fn surface_height(kinds: &ChunkKind, x: i32, z: i32) -> u16 {
+    (0..chunk::Y_AXIS_SIZE as i32)
+        .rev()
+        .find(|&y| !kinds.get((x, y, z).into()).is_none())
+        .map_or(0, |y| y as u16 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surface_height() {
+        let mut kinds = ChunkKind::default();
+        kinds.set((1, 0, 1).into(), 1.into());
+        kinds.set((1, 10, 1).into(), 1.into());
+        kinds.set((2, 255, 2).into(), 1.into());
+
+        assert_eq!(super::surface_height(&kinds, 0, 0), 0);
+        assert_eq!(super::surface_height(&kinds, 1, 1), 11);
+        assert_eq!(super::surface_height(&kinds, 2, 2), 256);
+    }
+
+    #[test]
+    fn get_set() {
+        let mut heightmap = Heightmap::new(IVec2::new(-2, 3), 4, 2);
+
+        heightmap.set(-2, 3, 5);
+        heightmap.set(1, 4, 999);
+        heightmap.set(2, 4, 1);
+
+        assert_eq!(heightmap.get(-2, 3), Some(5));
+        assert_eq!(heightmap.get(1, 4), Some(256), "Heights should be clamped");
+        assert_eq!(heightmap.get(2, 4), None);
+        assert_eq!(heightmap.get(-3, 3), None);
+    }
+
+    #[test]
+    fn from_chunks() {
+        let mut kinds = ChunkKind::default();
+        kinds.set((3, 7, 4).into(), 1.into());
+
+        let heightmap = Heightmap::from_chunks((0, 0, -1).into(), (-1, 0, 0).into(), |local| {
+            (local == IVec3::new(0, 0, -1)).then_some(&kinds)
+        });
+
+        assert_eq!(heightmap.origin, IVec2::new(-16, -16));
+        assert_eq!(heightmap.width(), 32);
+        assert_eq!(heightmap.depth(), 32);
+        assert_eq!(heightmap.get(3, -12), Some(8));
+        assert_eq!(heightmap.get(-13, 4), Some(0));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut heightmap = Heightmap::new(IVec2::new(5, 5), 3, 2);
+        heightmap.set(5, 5, 1);
+        heightmap.set(7, 5, 128);
+        heightmap.set(6, 6, 256);
+
+        let png = heightmap.to_png().unwrap();
+        let decoded = Heightmap::from_png(&png, IVec2::new(5, 5)).unwrap();
+
+        assert_eq!(decoded, heightmap);
+    }
+
+    #[test]
+    fn from_png_8_bit() {
+        let mut bytes = vec![];
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[255, 0, 0, 0, 255, 255])
+            .unwrap();
+
+        let heightmap = Heightmap::from_png(&bytes, IVec2::ZERO).unwrap();
+
+        assert_eq!(heightmap.get(0, 0), Some(256));
+        assert_eq!(
+            heightmap.get(1, 0),
+            Some(0),
+            "Only first channel should be used"
+        );
+
+        assert!(Heightmap::from_png(&[1, 2, 3], IVec2::ZERO).is_err());
+    }
+
+    #[test]
+    fn load_centered() {
+        let path = std::env::temp_dir().join("projekto_load_centered.png");
+        let heightmap = Heightmap::new(IVec2::new(5, 5), 4, 2);
+        std::fs::write(&path, heightmap.to_png().unwrap()).unwrap();
+
+        let loaded = Heightmap::load_centered(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.origin, IVec2::new(-2, -1));
+        assert_eq!(loaded.width(), 4);
+
+        assert!(Heightmap::load_centered("missing_heightmap.png").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use bevy_log::{trace, warn};
 use bevy_math::{IVec3, Vec3};
 use bevy_tasks::AsyncComputeTaskPool;
@@ -7,19 +9,24 @@ use itertools::Itertools;
 
 use light_smoother::ChunkSmoothLight;
 use projekto_core::{
-    chunk::{ChunkKind, ChunkLight},
+    chunk::{ChunkConnectivity, ChunkKind, ChunkLight, FacesConnectivity, SectionFlags},
     voxel::{self, ChunkFacesOcclusion, FacesOcclusion},
 };
 
 use projekto_core::{
-    chunk::{self, Chunk, ChunkNeighborhood},
+    chunk::{self, Chunk},
     voxel::{VoxelFace, VoxelVertex},
     VoxWorld,
 };
 
 // mod faces_merger;
+mod heightmap;
 mod light_propagator;
 mod light_smoother;
+mod lod;
+
+pub use heightmap::{surface_height, Heightmap};
+pub use lod::generate_lod_vertices;
 
 // v3               v2
 // +-----------+
@@ -89,18 +96,61 @@ pub fn compute_indices(vertex_count: usize) -> Vec<u32> {
     res
 }
 
+/// Noise settings used to generate chunks terrain. Chunks generated with the same config are always
+/// the same.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    pub frequency: f32,
+    pub octaves: i32,
+    pub gain: f32,
+    pub lacunarity: f32,
+    /// Columns covered by this heightmap uses its height instead of noise.
+    pub heightmap: Option<Arc<Heightmap>>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            seed: 15,
+            frequency: 0.03,
+            octaves: 3,
+            gain: 0.9,
+            lacunarity: 0.5,
+            heightmap: None,
+        }
+    }
+}
+
+/// Environment variable with the path of a PNG heightmap used to generate new chunks.
+pub const HEIGHTMAP_ENV: &str = "PROJEKTO_HEIGHTMAP";
+
+impl GeneratorConfig {
+    /// Creates the config used to generate the game world. If [`HEIGHTMAP_ENV`] is set, its
+    /// heightmap is used, centered on world origin.
+    ///
+    /// **Returns** the config or a message describing why the heightmap couldn't be loaded.
+    pub fn from_env() -> Result<Self, String> {
+        let heightmap = match std::env::var(HEIGHTMAP_ENV) {
+            Ok(path) => Some(Arc::new(Heightmap::load_centered(&path)?)),
+            Err(_) => None,
+        };
+
+        Ok(Self {
+            heightmap,
+            ..Default::default()
+        })
+    }
+}
+
 /// Generates a new chunk filling it with [`ChunkKind`] randomly generated by seeded noise
-pub fn generate_chunk(local: IVec3) -> Chunk {
-    let mut noise = FastNoise::seeded(15);
-    noise.set_noise_type(NoiseType::SimplexFractal);
-    noise.set_frequency(0.03);
-    noise.set_fractal_type(FractalType::FBM);
-    noise.set_fractal_octaves(3);
-    noise.set_fractal_gain(0.9);
-    noise.set_fractal_lacunarity(0.5);
-    let world = chunk::to_world(local);
+pub fn generate_chunk(local: IVec3, config: &GeneratorConfig) -> Chunk {
+    new_chunk(generate_chunk_kinds(local, config))
+}
 
-    let mut kinds = ChunkKind::default();
+/// Creates a new chunk with the given [`ChunkKind`] and natural light on the topmost voxels, so it
+/// can be passed to [`build_chunk_internals`].
+pub fn new_chunk(kinds: ChunkKind) -> Chunk {
     let mut lights = ChunkLight::default();
 
     for x in 0..chunk::X_AXIS_SIZE {
@@ -109,9 +159,46 @@ pub fn generate_chunk(local: IVec3) -> Chunk {
                 (x as i32, chunk::Y_END, z as i32).into(),
                 voxel::Light::natural(voxel::Light::MAX_NATURAL_INTENSITY),
             );
+        }
+    }
 
-            let h = noise.get_noise(world.x + x as f32, world.z + z as f32);
-            let world_height = ((h + 1.0) / 2.0) * (chunk::X_AXIS_SIZE * 2) as f32;
+    Chunk {
+        kinds,
+        lights,
+        ..Default::default()
+    }
+}
+
+/// Generates only the [`ChunkKind`] of a chunk, using the same seeded noise of [`generate_chunk`].
+///
+/// This is used by lightweight generation paths, which doesn't need any light data.
+pub fn generate_chunk_kinds(local: IVec3, config: &GeneratorConfig) -> ChunkKind {
+    let mut noise = FastNoise::seeded(config.seed);
+    noise.set_noise_type(NoiseType::SimplexFractal);
+    noise.set_frequency(config.frequency);
+    noise.set_fractal_type(FractalType::FBM);
+    noise.set_fractal_octaves(config.octaves);
+    noise.set_fractal_gain(config.gain);
+    noise.set_fractal_lacunarity(config.lacunarity);
+    let world = chunk::to_world(local);
+
+    let mut kinds = ChunkKind::default();
+
+    for x in 0..chunk::X_AXIS_SIZE {
+        for z in 0..chunk::Z_AXIS_SIZE {
+            let (world_x, world_z) = (world.x as i32 + x as i32, world.z as i32 + z as i32);
+
+            let world_height = match config
+                .heightmap
+                .as_ref()
+                .and_then(|heightmap| heightmap.get(world_x, world_z))
+            {
+                Some(height) => height as f32,
+                None => {
+                    let h = noise.get_noise(world.x + x as f32, world.z + z as f32);
+                    ((h + 1.0) / 2.0) * (chunk::X_AXIS_SIZE * 2) as f32
+                }
+            };
 
             let height_local = world_height - world.y;
 
@@ -130,11 +217,7 @@ pub fn generate_chunk(local: IVec3) -> Chunk {
         }
     }
 
-    Chunk {
-        kinds,
-        lights,
-        ..Default::default()
-    }
+    kinds
 }
 
 /// Build chunk internal data without using world.
@@ -264,46 +347,102 @@ fn update_kind(world: &mut VoxWorld, update: &[(IVec3, Vec<(IVec3, voxel::Kind)>
     dirty
 }
 
-/// Generate the final list of vertices of the given chunks.
+/// Generate the final list of vertices and the faces connectivity of the given chunks sections.
+///
+/// Sections which are fully empty or fully occluded are skipped and yields an empty vertex list.
+///
+/// **Returns** a list of vertices and faces connectivity of each given section, grouped by chunk.
 pub fn generate_chunk_vertices(
     world: &VoxWorld,
-    locals: &[IVec3],
-) -> Vec<(IVec3, Vec<VoxelVertex>)> {
+    locals: &[(IVec3, SectionFlags)],
+) -> Vec<(IVec3, Vec<(usize, Vec<VoxelVertex>, FacesConnectivity)>)> {
     trace!("Generating vertices for {} chunks", locals.len());
 
-    let temp_data = locals
+    locals
         .iter()
-        .map(|&l| (l, world.get(l).unwrap()))
-        .map(|(l, chunk)| (l, faces_occlusion(chunk)))
-        .map(|(l, occ)| (l, light_smoother::smooth_lighting(world, l, &occ), occ))
-        .collect_vec();
-
-    trace!(
-        "Faces occlusion and light smoothing completed on {} chunks",
-        temp_data.len()
-    );
-
-    temp_data
-        .into_iter()
-        .filter_map(|(local, smooth_light, occlusion)| {
-            if occlusion.is_fully_occluded() {
-                Some((local, vec![]))
-            } else {
-                let faces = generate_faces(occlusion, smooth_light, world.get(local)?);
-                Some((local, generate_vertices(faces)))
-            }
+        .filter_map(|&(local, sections)| {
+            let chunk = world.get(local)?;
+
+            let filled = sections
+                .iter()
+                .filter(|&section| !chunk.kinds.is_section_all(section, voxel::Kind::none()))
+                .collect::<SectionFlags>();
+
+            let (occlusion, connectivity) = faces_occlusion(chunk, filled);
+
+            let visible = filled
+                .iter()
+                .filter(|&section| !is_section_fully_occluded(&occlusion, section))
+                .collect::<SectionFlags>();
+
+            let smooth_light = light_smoother::smooth_lighting(world, local, &occlusion, visible);
+
+            let vertices = sections
+                .iter()
+                .map(|section| {
+                    let vertices = if visible.is_set(section) {
+                        let faces = generate_faces(&occlusion, &smooth_light, chunk, section);
+                        generate_vertices(faces)
+                    } else {
+                        vec![]
+                    };
+
+                    (section, vertices, connectivity[section])
+                })
+                .collect();
+
+            Some((local, vertices))
         })
         .collect()
 }
 
-/// Computes the faces occlusion data of the given [`ChunkKind`]
+/// Computes the sections of the given chunk which needs to have it's vertices regenerated.
+///
+/// Faces and smooth light of a voxel depends on the surrounding voxels, so any section which has
+/// it's own data or a neighbor data changed is included.
 ///
-/// Returns** computed [`ChunkFacesOcclusion`]
-fn faces_occlusion(chunk: &Chunk) -> ChunkFacesOcclusion {
+/// **Returns** the dirty sections of the given chunk.
+pub fn dirty_sections(world: &VoxWorld, local: IVec3) -> SectionFlags {
+    std::iter::once(local)
+        .chain(voxel::SIDES.iter().map(|s| local + s.dir()))
+        .filter_map(|l| world.get(l))
+        .fold(SectionFlags::default(), |flags, chunk| {
+            flags | chunk.kinds.dirty_sections() | chunk.lights.dirty_sections()
+        })
+        .expanded()
+}
+
+/// Clears the dirty flag of all sections of the given chunks.
+pub fn clear_dirty_sections(world: &mut VoxWorld, locals: &[IVec3]) {
+    for &local in locals {
+        if let Some(chunk) = world.get_mut(local) {
+            chunk.kinds.clear_dirty_sections();
+            chunk.lights.clear_dirty_sections();
+        }
+    }
+}
+
+fn is_section_fully_occluded(occlusion: &ChunkFacesOcclusion, section: usize) -> bool {
+    chunk::section_voxels(section).all(|voxel| occlusion.get(voxel).is_fully_occluded())
+}
+
+/// Computes the faces occlusion data and the faces connectivity of the given sections of
+/// [`ChunkKind`]. Sections which aren't given are kept fully connected.
+///
+/// Returns** computed [`ChunkFacesOcclusion`] and [`ChunkConnectivity`]
+fn faces_occlusion(
+    chunk: &Chunk,
+    sections: SectionFlags,
+) -> (ChunkFacesOcclusion, ChunkConnectivity) {
     let kinds = &chunk.kinds;
 
+    let mut connectivity = ChunkConnectivity::default();
+    for section in sections.iter() {
+        connectivity[section] = section_connectivity(kinds, section);
+    }
+
     let mut occlusion = ChunkFacesOcclusion::default();
-    for voxel in chunk::voxels() {
+    for voxel in sections.voxels() {
         let mut voxel_faces = FacesOcclusion::default();
 
         if kinds.get(voxel).is_none() {
@@ -322,17 +461,74 @@ fn faces_occlusion(chunk: &Chunk) -> ChunkFacesOcclusion {
         occlusion.set(voxel, voxel_faces);
     }
 
-    occlusion
+    (occlusion, connectivity)
+}
+
+/// Flood fills each isolated area of non-opaque voxels of the given section and connects all
+/// section faces touched by the same area.
+///
+/// **Returns** computed [`FacesConnectivity`] of the section.
+fn section_connectivity(kinds: &ChunkKind, section: usize) -> FacesConnectivity {
+    let begin = (section * chunk::SECTION_AXIS_SIZE) as i32;
+    let end = begin + chunk::SECTION_AXIS_SIZE as i32 - 1;
+
+    let index = |voxel: IVec3| {
+        ((voxel.x as usize * chunk::Z_AXIS_SIZE + voxel.z as usize) * chunk::SECTION_AXIS_SIZE)
+            + (voxel.y - begin) as usize
+    };
+
+    let mut visited =
+        vec![false; chunk::X_AXIS_SIZE * chunk::Z_AXIS_SIZE * chunk::SECTION_AXIS_SIZE];
+    let mut connectivity = FacesConnectivity::none();
+    let mut queue = vec![];
+
+    for voxel in chunk::section_voxels(section) {
+        if visited[index(voxel)] || kinds.get(voxel).is_opaque() {
+            continue;
+        }
+
+        visited[index(voxel)] = true;
+        queue.push(voxel);
+
+        let mut touched = vec![];
+
+        while let Some(voxel) = queue.pop() {
+            for side in voxel::SIDES {
+                let neighbor = voxel + side.dir();
+
+                if !chunk::is_within_bounds(neighbor) || neighbor.y < begin || neighbor.y > end {
+                    if !touched.contains(&side) {
+                        touched.push(side);
+                    }
+                    continue;
+                }
+
+                if visited[index(neighbor)] || kinds.get(neighbor).is_opaque() {
+                    continue;
+                }
+
+                visited[index(neighbor)] = true;
+                queue.push(neighbor);
+            }
+        }
+
+        for (&a, &b) in touched.iter().tuple_combinations() {
+            connectivity.connect(a, b);
+        }
+    }
+
+    connectivity
 }
 
 fn generate_faces(
-    occlusion: ChunkFacesOcclusion,
-    smooth_light: ChunkSmoothLight,
+    occlusion: &ChunkFacesOcclusion,
+    smooth_light: &ChunkSmoothLight,
     chunk: &Chunk,
+    section: usize,
 ) -> Vec<VoxelFace> {
     let mut faces_vertices = vec![];
 
-    for voxel in chunk::voxels() {
+    for voxel in chunk::section_voxels(section) {
         for side in voxel::SIDES {
             // Since this is a top-down game, we don't need down face at all
             if side == voxel::Side::Down {
@@ -352,13 +548,9 @@ fn generate_faces(
                 vertices: [v1, v2, v3, v4],
                 side,
                 kind,
-                light: smooth_light.get(side),
-                voxel: [
-                    projekto_core::math::pack(v1.x as u8, v1.y as u8, v1.z as u8, 0),
-                    projekto_core::math::pack(v2.x as u8, v2.y as u8, v2.z as u8, 0),
-                    projekto_core::math::pack(v3.x as u8, v3.y as u8, v3.z as u8, 0),
-                    projekto_core::math::pack(v4.x as u8, v4.y as u8, v4.z as u8, 0),
-                ],
+                natural_light: smooth_light.get_natural(side),
+                artificial_light: smooth_light.get_artificial(side),
+                occlusion: smooth_light.get_occlusion(side),
             });
         }
     }
@@ -374,13 +566,11 @@ fn generate_faces(
 fn generate_vertices(faces: Vec<VoxelFace>) -> Vec<VoxelVertex> {
     let mut vertices = vec![];
     let kinds_descs = voxel::KindsDescs::get();
-    let tile_texture_size = (kinds_descs.count_tiles() as f32).recip();
+    let tiles_count = kinds_descs.count_tiles();
 
     for face in faces {
-        let normal = face.side.normal();
-
         let face_desc = kinds_descs.get_face_desc(&face);
-        let tile_coord_start = face_desc.offset.as_vec2() * tile_texture_size;
+        let tile_index = face_desc.offset.y as u16 * tiles_count + face_desc.offset.x as u16;
 
         let faces_vertices = face
             .vertices
@@ -388,9 +578,9 @@ fn generate_vertices(faces: Vec<VoxelFace>) -> Vec<VoxelVertex> {
             .enumerate()
             .map(|(i, v)| {
                 let base_vertex_idx = VERTICES_INDICES[face.side as usize][i];
-                let base_vertex: Vec3 = VERTICES[base_vertex_idx].into();
+                let base_vertex = Vec3::from(VERTICES[base_vertex_idx]).as_ivec3();
 
-                base_vertex + v.as_vec3()
+                base_vertex + *v
             })
             .collect::<Vec<_>>();
 
@@ -399,46 +589,48 @@ fn generate_vertices(faces: Vec<VoxelFace>) -> Vec<VoxelVertex> {
             "Each face should have 4 vertices"
         );
 
-        fn calc_tile_size(min: Vec3, max: Vec3) -> f32 {
-            (min.x - max.x).abs() + (min.y - max.y).abs() + (min.z - max.z).abs()
+        fn calc_tile_size(min: IVec3, max: IVec3) -> i32 {
+            (min - max).abs().to_array().into_iter().sum()
         }
 
-        let x_tile = calc_tile_size(faces_vertices[0], faces_vertices[1]) * tile_texture_size;
-        let y_tile = calc_tile_size(faces_vertices[0], faces_vertices[3]) * tile_texture_size;
+        let x_tile = calc_tile_size(faces_vertices[0], faces_vertices[1]);
+        let y_tile = calc_tile_size(faces_vertices[0], faces_vertices[3]);
 
         let tile_uv = [
-            (0.0, y_tile).into(),
+            (0, y_tile).into(),
             (x_tile, y_tile).into(),
-            (x_tile, 0.0).into(),
-            (0.0, 0.0).into(),
+            (x_tile, 0).into(),
+            (0, 0).into(),
         ];
 
-        let light_fraction = (voxel::Light::MAX_NATURAL_INTENSITY as f32).recip();
-
         for (i, v) in faces_vertices.into_iter().enumerate() {
+            let natural =
+                (face.natural_light[i].round() as u8).min(voxel::Light::MAX_NATURAL_INTENSITY);
+            let artificial = (face.artificial_light[i].round() as u8)
+                .min(voxel::Light::MAX_ARTIFICIAL_INTENSITY);
+
             vertices.push(VoxelVertex {
-                position: v,
-                normal,
-                uv: tile_uv[i],
-                tile_coord_start,
-                light: Vec3::splat(face.light[i] * light_fraction),
-                voxel: face.voxel[i],
+                position: VoxelVertex::pack_position(v, face.side, face.vertices[i]),
+                light: VoxelVertex::pack_light(natural, face.occlusion[i]),
+                artificial_light: VoxelVertex::pack_artificial_light(artificial),
+                tile: VoxelVertex::pack_tile(tile_index, tile_uv[i]),
             });
         }
     }
 
-    debug_assert!(!vertices.is_empty());
     vertices
 }
 
-/// Updates the [`ChunkNeighborhood`] of a given locals given.
+/// Updates the [`chunk::ChunkNeighborhood`] of a given locals given.
 /// This function assumes all given chunks exists into the world and updates any neighborhood data
-/// needed by chunk.
+/// needed by chunk. Sides which neighbor doesn't exists are kept, since the world may hold only the
+/// chunks locked by a batch.
 ///
 /// Panics** if a given chunk local doesn't exists
 fn update_kind_neighborhoods(world: &mut VoxWorld, locals: &[IVec3]) {
     for &local in locals {
-        let mut neighborhood = ChunkNeighborhood::default();
+        let mut neighborhood =
+            std::mem::take(&mut world.get_mut(local).unwrap().kinds.neighborhood);
         for side in voxel::SIDES {
             let dir = side.dir();
             let neighbor = local + dir;
@@ -583,7 +775,7 @@ mod tests {
     #[test]
     fn generate_chunk() {
         let local = (5432, 0, 5555).into();
-        let chunk = super::generate_chunk(local);
+        let chunk = super::generate_chunk(local, &Default::default());
 
         assert!(
             !chunk.kinds.is_default(),
@@ -591,6 +783,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn generate_chunk_kinds_seed() {
+        let local = (3, 0, -7).into();
+        let config = super::GeneratorConfig::default();
+
+        assert!(
+            super::generate_chunk_kinds(local, &config)
+                == super::generate_chunk_kinds(local, &config),
+            "Same config should always generate the same chunk"
+        );
+
+        let other = super::GeneratorConfig {
+            seed: config.seed + 1,
+            ..config.clone()
+        };
+
+        assert!(
+            super::generate_chunk_kinds(local, &config)
+                != super::generate_chunk_kinds(local, &other),
+            "Different seeds should generate different chunks"
+        );
+    }
+
+    #[test]
+    fn generate_chunk_kinds_heightmap() {
+        let mut heightmap = super::Heightmap::new((16, 0).into(), 2, 1);
+        heightmap.set(16, 0, 10);
+
+        let config = super::GeneratorConfig {
+            heightmap: Some(std::sync::Arc::new(heightmap)),
+            ..Default::default()
+        };
+
+        let kinds = super::generate_chunk_kinds((1, 0, 0).into(), &config);
+
+        assert_eq!(super::surface_height(&kinds, 0, 0), 10);
+        assert_eq!(
+            super::surface_height(&kinds, 1, 0),
+            0,
+            "Heightmap columns with zero height should be empty"
+        );
+        let noise = super::generate_chunk_kinds((1, 0, 0).into(), &Default::default());
+        assert_eq!(
+            super::surface_height(&kinds, 2, 0),
+            super::surface_height(&noise, 2, 0),
+            "Columns outside heightmap should use noise"
+        );
+    }
+
     #[test]
     fn update_chunks_neighbor_side_light() {
         let mut world = create_test_world();
@@ -669,7 +910,7 @@ mod tests {
         let chunk = Chunk::default();
 
         // Act
-        let occlusions = super::faces_occlusion(&chunk);
+        let (occlusions, _) = super::faces_occlusion(&chunk, SectionFlags::all());
 
         // Assert
         assert!(
@@ -697,7 +938,7 @@ mod tests {
         chunk.kinds.set((10, 10, 11).into(), 1.into());
 
         // Act
-        let faces_occlusion = super::faces_occlusion(&chunk);
+        let (faces_occlusion, _) = super::faces_occlusion(&chunk, SectionFlags::all());
 
         // Assert
         let faces = faces_occlusion.get((1, 2, 1).into());
@@ -852,7 +1093,7 @@ mod tests {
         super::update_kind_neighborhoods(&mut world, &[(0, 0, 0).into()]);
 
         let center = world.get((0, 0, 0).into()).unwrap();
-        let faces_occlusion = super::faces_occlusion(center);
+        let (faces_occlusion, _) = super::faces_occlusion(center, SectionFlags::all());
 
         let faces = faces_occlusion.get((0, chunk::Y_END as i32, 0).into());
         assert_eq!(faces, [false, false, true, false, false, false].into());
@@ -866,56 +1107,152 @@ mod tests {
         // Arrange
         let side = voxel::Side::Up;
 
-        // This face is 2 voxels wide on the -Z axis (0,0) (0,-1)
+        // This face is 2 voxels wide on the -Z axis (0,1) (0,0)
         let faces = vec![VoxelFace {
             side,
             vertices: [
+                (0, 0, 1).into(),
+                (0, 0, 1).into(),
                 (0, 0, 0).into(),
                 (0, 0, 0).into(),
-                (0, 0, -1).into(),
-                (0, 0, -1).into(),
             ],
             kind: 1.into(),
-            ..Default::default()
+            natural_light: [15.0, 14.6, 7.2, 0.0],
+            artificial_light: [0.0, 3.4, 15.0, 9.0],
+            occlusion: [0, 1, 2, 3],
         }];
 
         // Act
         let vertices = super::generate_vertices(faces);
 
         // Assert
-        let normal = side.normal();
+        let positions = vertices.iter().map(VoxelVertex::position).collect_vec();
         assert_eq!(
-            vertices,
+            positions,
             vec![
-                VoxelVertex {
-                    normal,
-                    position: (0.0, 1.0, 1.0).into(),
-                    uv: (0.0, 0.2).into(),
-                    tile_coord_start: (0.2, 0.1).into(),
-                    ..Default::default()
-                },
-                VoxelVertex {
-                    normal,
-                    position: (1.0, 1.0, 1.0).into(),
-                    uv: (0.1, 0.2).into(),
-                    tile_coord_start: (0.2, 0.1).into(),
-                    ..Default::default()
-                },
-                VoxelVertex {
-                    normal,
-                    position: (1.0, 1.0, -1.0).into(),
-                    uv: (0.1, 0.0).into(),
-                    tile_coord_start: (0.2, 0.1).into(),
-                    ..Default::default()
-                },
-                VoxelVertex {
-                    normal,
-                    position: (0.0, 1.0, -1.0).into(),
-                    uv: (0.0, 0.0).into(),
-                    tile_coord_start: (0.2, 0.1).into(),
-                    ..Default::default()
-                },
+                (0, 1, 2).into(),
+                (1, 1, 2).into(),
+                (1, 1, 0).into(),
+                (0, 1, 0).into()
             ]
         );
+
+        let voxels = vertices.iter().map(VoxelVertex::voxel).collect_vec();
+        assert_eq!(
+            voxels,
+            vec![
+                (0, 0, 1).into(),
+                (0, 0, 1).into(),
+                (0, 0, 0).into(),
+                (0, 0, 0).into()
+            ]
+        );
+
+        let uvs = vertices.iter().map(VoxelVertex::uv).collect_vec();
+        assert_eq!(
+            uvs,
+            vec![(0, 2).into(), (1, 2).into(), (1, 0).into(), (0, 0).into()]
+        );
+
+        let lights = vertices
+            .iter()
+            .map(|v| (v.natural_light(), v.artificial_light(), v.occlusion()))
+            .collect_vec();
+        assert_eq!(lights, vec![(15, 0, 0), (15, 3, 1), (7, 15, 2), (0, 9, 3)]);
+
+        for vertex in vertices {
+            assert_eq!(vertex.side(), side);
+            // Dirt is at (2, 1) on a 10x10 tiles atlas
+            assert_eq!(vertex.tile_index(), 12);
+        }
+    }
+
+    #[test]
+    fn generate_chunk_vertices_sections() {
+        let mut chunk = Chunk::default();
+        chunk.kinds.set((1, 20, 1).into(), 1.into());
+        chunk.kinds.set((1, 40, 1).into(), 1.into());
+
+        let mut world = VoxWorld::default();
+        world.add((0, 0, 0).into(), chunk);
+
+        let sections = [0, 1, 2].into_iter().collect::<SectionFlags>();
+        let result = super::generate_chunk_vertices(&world, &[((0, 0, 0).into(), sections)]);
+
+        assert_eq!(result.len(), 1);
+
+        let (local, vertices) = &result[0];
+        assert_eq!(*local, (0, 0, 0).into());
+        assert_eq!(vertices.len(), 3, "Only given sections should be generated");
+
+        for (section, vertices, _) in vertices {
+            match section {
+                0 => assert!(vertices.is_empty(), "Empty sections should be skipped"),
+                1 | 2 => {
+                    assert!(!vertices.is_empty());
+                    assert!(vertices
+                        .iter()
+                        .all(|v| chunk::to_section(v.voxel()) == *section));
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn dirty_sections() {
+        let mut world = VoxWorld::default();
+        world.add((0, 0, 0).into(), Chunk::default());
+        world.add((1, 0, 0).into(), Chunk::default());
+        world.add((3, 0, 0).into(), Chunk::default());
+
+        world
+            .get_mut((1, 0, 0).into())
+            .unwrap()
+            .kinds
+            .set((0, 50, 0).into(), 1.into());
+
+        assert_eq!(
+            super::dirty_sections(&world, (0, 0, 0).into())
+                .iter()
+                .collect_vec(),
+            vec![2, 3, 4],
+            "Neighbor dirty sections and vertical neighbors should be included"
+        );
+        assert!(super::dirty_sections(&world, (3, 0, 0).into()).is_empty());
+
+        super::clear_dirty_sections(&mut world, &[(1, 0, 0).into()]);
+        assert!(super::dirty_sections(&world, (0, 0, 0).into()).is_empty());
+    }
+
+    #[test]
+    fn section_connectivity() {
+        use voxel::Side;
+
+        let mut kinds = ChunkKind::default();
+
+        let connectivity = super::section_connectivity(&kinds, 0);
+        assert_eq!(connectivity, FacesConnectivity::all());
+
+        // Split section 1 in two halves, using a solid wall on X = 8
+        for y in 16..32 {
+            for z in 0..chunk::Z_AXIS_SIZE as i32 {
+                kinds.set((8, y, z).into(), 1.into());
+            }
+        }
+
+        let connectivity = super::section_connectivity(&kinds, 1);
+        assert!(!connectivity.is_connected(Side::Left, Side::Right));
+        assert!(connectivity.is_connected(Side::Left, Side::Up));
+        assert!(connectivity.is_connected(Side::Right, Side::Down));
+        assert!(connectivity.is_connected(Side::Up, Side::Down));
+
+        // Fill section 2 completely
+        for voxel in chunk::section_voxels(2) {
+            kinds.set(voxel, 1.into());
+        }
+
+        let connectivity = super::section_connectivity(&kinds, 2);
+        assert_eq!(connectivity, FacesConnectivity::none());
     }
 }
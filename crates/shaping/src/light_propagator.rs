@@ -315,8 +315,10 @@ impl<'a> Propagator<'a> {
 
     /// Update light [`ChunkNeighborhood`] of the given chunk.
     /// This function should be called whenever neighbors chunks had their light values updated.
+    /// Sides which neighbor doesn't exists are kept, since the world may hold only some chunks.
     fn update_light_chunk_neighborhood(&mut self, local: IVec3) {
-        let mut neighborhood = ChunkNeighborhood::default();
+        let chunk = self.world.get_mut(local).unwrap();
+        let mut neighborhood = std::mem::take(&mut chunk.lights.neighborhood);
         for side in voxel::SIDES {
             let dir = side.dir();
             let neighbor = local + dir;
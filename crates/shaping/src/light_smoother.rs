@@ -2,7 +2,7 @@ use bevy_math::IVec3;
 
 use bevy_utils::default;
 use projekto_core::{
-    chunk::{self, ChunkStorage, ChunkStorageType},
+    chunk::{self, ChunkStorage, ChunkStorageType, SectionFlags},
     voxel::{self, ChunkFacesOcclusion},
     VoxWorld,
 };
@@ -82,17 +82,46 @@ const NEIGHBOR_VERTEX_LOOKUP: [[[usize; VERTEX_COUNT]; VERTEX_NEIGHBOR_COUNT]; v
     ],
 ];
 
-/// Contains smoothed vertex light for each face
+/// Contains smoothed vertex natural light, artificial light and ambient occlusion for each face
 #[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd)]
-pub struct SmoothLight([[f32; 4]; voxel::SIDE_COUNT]);
+pub struct SmoothLight {
+    natural: [[f32; 4]; voxel::SIDE_COUNT],
+    artificial: [[f32; 4]; voxel::SIDE_COUNT],
+    occlusion: [[u8; 4]; voxel::SIDE_COUNT],
+}
 
 impl SmoothLight {
-    fn set(&mut self, side: voxel::Side, light: [f32; 4]) {
-        self.0[side as usize] = light;
+    fn set(
+        &mut self,
+        side: voxel::Side,
+        natural: [f32; 4],
+        artificial: [f32; 4],
+        occlusion: [u8; 4],
+    ) {
+        self.natural[side as usize] = natural;
+        self.artificial[side as usize] = artificial;
+        self.occlusion[side as usize] = occlusion;
+    }
+
+    pub fn get_natural(&self, side: voxel::Side) -> [f32; 4] {
+        self.natural[side as usize]
     }
 
-    pub fn get(&self, side: voxel::Side) -> [f32; 4] {
-        self.0[side as usize]
+    pub fn get_artificial(&self, side: voxel::Side) -> [f32; 4] {
+        self.artificial[side as usize]
+    }
+
+    /// Checks if the given side has the same natural and artificial light of the same side of
+    /// `other`.
+    pub fn is_light_equal(&self, other: &SmoothLight, side: voxel::Side) -> bool {
+        let idx = side as usize;
+
+        self.natural[idx] == other.natural[idx] && self.artificial[idx] == other.artificial[idx]
+    }
+
+    /// **Returns** how many opaque neighbors are occluding each vertex of the given side
+    pub fn get_occlusion(&self, side: voxel::Side) -> [u8; 4] {
+        self.occlusion[side as usize]
     }
 }
 
@@ -106,7 +135,7 @@ pub type ChunkSmoothLight = ChunkStorage<SmoothLight>;
 enum NeighborLight {
     #[default]
     Opaque,
-    Transparent(u8),
+    Transparent(voxel::Light),
 }
 
 impl NeighborLight {
@@ -117,10 +146,10 @@ impl NeighborLight {
         }
     }
 
-    fn intensity(self) -> u8 {
+    fn intensity(self, ty: voxel::LightTy) -> u8 {
         match self {
             NeighborLight::Opaque => 0,
-            NeighborLight::Transparent(i) => i,
+            NeighborLight::Transparent(light) => light.get(ty),
         }
     }
 }
@@ -184,34 +213,34 @@ fn gather_neighborhood_light(
                 let side_voxel = voxel + dir;
 
                 let intensity = if chunk::is_within_bounds(side_voxel) {
-                    let intensity = chunk.lights.get(side_voxel).get_greater_intensity();
+                    let light = chunk.lights.get(side_voxel);
 
                     // Check if returned block is opaque
-                    if intensity == 0 && chunk.kinds.get(side_voxel).is_opaque() {
+                    if light.get_greater_intensity() == 0 && chunk.kinds.get(side_voxel).is_opaque()
+                    {
                         NeighborLight::Opaque
                     } else {
-                        NeighborLight::Transparent(intensity)
+                        NeighborLight::Transparent(light)
                     }
                 } else {
                     let (dir, neighbor_voxel) = chunk::overlap_voxel(side_voxel);
                     let neighbor_local = local + dir;
 
                     if let Some(neighbor_chunk) = world.get(neighbor_local) {
-                        let intensity = neighbor_chunk
-                            .lights
-                            .get(neighbor_voxel)
-                            .get_greater_intensity();
+                        let light = neighbor_chunk.lights.get(neighbor_voxel);
 
                         // Check if returned block is opaque
-                        if intensity == 0 && neighbor_chunk.kinds.get(neighbor_voxel).is_opaque() {
+                        if light.get_greater_intensity() == 0
+                            && neighbor_chunk.kinds.get(neighbor_voxel).is_opaque()
+                        {
                             NeighborLight::Opaque
                         } else {
-                            NeighborLight::Transparent(intensity)
+                            NeighborLight::Transparent(light)
                         }
                     } else {
                         // TODO: When a neighbor chunk isn't loaded we should make it lighter or
                         // darker?
-                        NeighborLight::Transparent(0)
+                        NeighborLight::Transparent(voxel::Light::default())
                     }
                 };
 
@@ -227,18 +256,25 @@ fn gather_neighborhood_light(
 
 /// Calculates the ambient occlusion and light smoothness based on [0fps article](https://0fps.net/2013/07/03/ambient-occlusion-for-minecraft-like-worlds/)
 /// Skips AO and Light Smoothness if voxel is a light emitter
+///
+/// **Returns** the average natural and artificial light intensities of transparent neighbors and
+/// how many neighbors are opaque, which is the ambient occlusion level, ranging from 0 to 3.
 fn smooth_ambient_occlusion(
     neighbors: &[NeighborLight; NEIGHBOR_COUNT],
     side: voxel::Side,
     vertex: usize,
     emitter: bool,
-) -> f32 {
+) -> (f32, f32, u8) {
     let idx = side as usize;
     let side = neighbors[NEIGHBOR_VERTEX_LOOKUP[idx][vertex][0]];
 
     // Light emitter doesn't have ambient occlusion nor light smoothing.
     if emitter {
-        return side.intensity() as f32;
+        return (
+            side.intensity(voxel::LightTy::Natural) as f32,
+            side.intensity(voxel::LightTy::Artificial) as f32,
+            0,
+        );
     }
 
     let side1 = neighbors[NEIGHBOR_VERTEX_LOOKUP[idx][vertex][1]];
@@ -251,20 +287,42 @@ fn smooth_ambient_occlusion(
         corner
     };
 
-    // Convert from i32, which has the info if the voxel is opaque, to pure light intensity
-    (side.intensity() + side1.intensity() + side2.intensity() + corner.intensity()) as f32 / 4.0
+    let vertex_neighbors = [side, side1, side2, corner];
+    let occlusion = vertex_neighbors.iter().filter(|n| n.is_opaque()).count();
+    let transparent = VERTEX_NEIGHBOR_COUNT - occlusion;
+
+    if transparent == 0 {
+        return (0.0, 0.0, occlusion as u8);
+    }
+
+    // Opaque neighbors has no intensity, so they only count as occlusion
+    let average = |ty| {
+        let intensity = vertex_neighbors
+            .iter()
+            .map(|n| n.intensity(ty) as u32)
+            .sum::<u32>();
+
+        intensity as f32 / transparent as f32
+    };
+
+    (
+        average(voxel::LightTy::Natural),
+        average(voxel::LightTy::Artificial),
+        occlusion as u8,
+    )
 }
 
-/// Calculates ambient occlusion and light smoothness for the given chunk.
+/// Calculates ambient occlusion and light smoothness for the given sections of a chunk.
 pub fn smooth_lighting(
     world: &VoxWorld,
     local: IVec3,
     occlusion: &ChunkFacesOcclusion,
+    sections: SectionFlags,
 ) -> ChunkSmoothLight {
     let mut chunk_smooth_light = ChunkSmoothLight::default();
 
     if let Some(chunk) = world.get(local) {
-        for voxel in chunk::voxels() {
+        for voxel in sections.voxels() {
             let occlusion = occlusion.get(voxel);
 
             if occlusion.is_fully_occluded() {
@@ -281,14 +339,14 @@ pub fn smooth_lighting(
                     continue;
                 }
 
+                let vertices = [0, 1, 2, 3]
+                    .map(|vertex| smooth_ambient_occlusion(&neighbors, side, vertex, emitter));
+
                 smooth_light.set(
                     side,
-                    [
-                        smooth_ambient_occlusion(&neighbors, side, 0, emitter),
-                        smooth_ambient_occlusion(&neighbors, side, 1, emitter),
-                        smooth_ambient_occlusion(&neighbors, side, 2, emitter),
-                        smooth_ambient_occlusion(&neighbors, side, 3, emitter),
-                    ],
+                    vertices.map(|(natural, _, _)| natural),
+                    vertices.map(|(_, artificial, _)| artificial),
+                    vertices.map(|(_, _, occlusion)| occlusion),
                 );
             }
 
@@ -301,7 +359,10 @@ pub fn smooth_lighting(
 
 #[cfg(test)]
 mod tests {
-    use projekto_core::{chunk::Chunk, voxel::Light};
+    use projekto_core::{
+        chunk::Chunk,
+        voxel::{Light, LightTy},
+    };
 
     use super::*;
 
@@ -315,10 +376,11 @@ mod tests {
         for y in -1..=1 {
             for z in -1..=1 {
                 for x in -1..=1 {
-                    chunk
-                        .lights
-                        .set(voxel + IVec3::new(x, y, z), Light::natural(i));
-                    i += 1
+                    let mut light = Light::natural(i);
+                    light.set(LightTy::Artificial, 15 - i);
+
+                    chunk.lights.set(voxel + IVec3::new(x, y, z), light);
+                    i = (i + 1) % 16
                 }
             }
         }
@@ -339,17 +401,53 @@ mod tests {
                         continue;
                     }
 
-                    assert_eq!(
-                        chunk.lights.get(neighbor).get_greater_intensity(),
-                        neighbors[i].intensity(),
-                        "Failed at {neighbor} [{i}]"
-                    );
+                    for ty in [LightTy::Natural, LightTy::Artificial] {
+                        assert_eq!(
+                            chunk.lights.get(neighbor).get(ty),
+                            neighbors[i].intensity(ty),
+                            "Failed at {neighbor} [{i}] {ty:?}"
+                        );
+                    }
                     i += 1
                 }
             }
         }
     }
 
+    #[test]
+    fn smooth_ambient_occlusion() {
+        let light = |natural, artificial| {
+            let mut light = Light::natural(natural);
+            light.set(LightTy::Artificial, artificial);
+            NeighborLight::Transparent(light)
+        };
+
+        let mut neighbors = [light(12, 2); NEIGHBOR_COUNT];
+
+        let (natural, artificial, occlusion) =
+            super::smooth_ambient_occlusion(&neighbors, voxel::Side::Up, 0, false);
+        assert_eq!(natural, 12.0);
+        assert_eq!(artificial, 2.0);
+        assert_eq!(occlusion, 0);
+
+        // Both sides of v7 on Up face are opaque, so the corner is also considered opaque
+        neighbors[24] = NeighborLight::Opaque;
+        neighbors[20] = NeighborLight::Opaque;
+        neighbors[21] = light(8, 10);
+
+        let (natural, artificial, occlusion) =
+            super::smooth_ambient_occlusion(&neighbors, voxel::Side::Up, 0, false);
+        assert_eq!(natural, 8.0);
+        assert_eq!(artificial, 10.0);
+        assert_eq!(occlusion, 3);
+
+        let (natural, artificial, occlusion) =
+            super::smooth_ambient_occlusion(&neighbors, voxel::Side::Up, 0, true);
+        assert_eq!(natural, 8.0);
+        assert_eq!(artificial, 10.0);
+        assert_eq!(occlusion, 0, "Emitters should have no ambient occlusion");
+    }
+
     #[test]
     fn lookup_table() {
         let mut count = vec![0; NEIGHBOR_COUNT];
@@ -0,0 +1,224 @@
+use bevy_math::{IVec2, IVec3};
+use projekto_core::{
+    chunk::{self, ChunkKind},
+    voxel::{self, VoxelFace, VoxelVertex},
+};
+
+use super::{VERTICES, VERTICES_INDICES};
+
+/// Depth of the skirts placed on chunk borders, to hide cracks between chunks of different levels.
+const SKIRT_DEPTH: i32 = 8;
+
+/// Surface of a downsampled cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    height: i32,
+    kind: voxel::Kind,
+}
+
+/// Generates vertices of a chunk downsampled at the given level of detail. Each level halves the
+/// chunk resolution, so level 1 merges 2x2 columns into a single cell, level 2 merges 4x4 and so
+/// on.
+///
+/// Only the surface of each cell is generated, with full natural light and no ambient occlusion,
+/// since no light propagation is needed.
+///
+/// **Returns** a list of generated [`VoxelVertex`].
+pub fn generate_lod_vertices(kinds: &ChunkKind, level: u8) -> Vec<VoxelVertex> {
+    let size = 1 << level;
+    let cells = downsample(kinds, size);
+
+    let mut faces = vec![];
+
+    for &(cell_pos, cell) in &cells.cells {
+        let cell = match cell {
+            Some(cell) => cell,
+            None => continue,
+        };
+
+        let min = IVec3::new(cell_pos.x * size, cell.height, cell_pos.y * size);
+        let max = IVec3::new(min.x + size - 1, cell.height, min.z + size - 1);
+
+        faces.push(box_face(voxel::Side::Up, cell.kind, min, max));
+
+        for side in [
+            voxel::Side::Right,
+            voxel::Side::Left,
+            voxel::Side::Front,
+            voxel::Side::Back,
+        ] {
+            let dir = side.dir();
+            let neighbor = cell_pos + IVec2::new(dir.x, dir.z);
+
+            // Cells on chunk border uses skirts, since neighbor chunk may have a different level
+            let bottom = if cells.is_within_bounds(neighbor) {
+                cells.get(neighbor).map_or(0, |n| n.height + 1)
+            } else {
+                cell.height - SKIRT_DEPTH
+            }
+            .max(0);
+
+            if bottom > cell.height {
+                continue;
+            }
+
+            faces.push(box_face(
+                side,
+                cell.kind,
+                IVec3::new(min.x, bottom, min.z),
+                max,
+            ));
+        }
+    }
+
+    super::generate_vertices(faces)
+}
+
+/// Creates a fully lit [`VoxelFace`] on the given side of a box, which spans from min to max
+/// voxels, inclusive.
+fn box_face(side: voxel::Side, kind: voxel::Kind, min: IVec3, max: IVec3) -> VoxelFace {
+    let vertices = VERTICES_INDICES[side as usize].map(|idx| {
+        let [x, y, z] = VERTICES[idx];
+        IVec3::new(
+            if x > 0.0 { max.x } else { min.x },
+            if y > 0.0 { max.y } else { min.y },
+            if z > 0.0 { max.z } else { min.z },
+        )
+    });
+
+    VoxelFace {
+        vertices,
+        side,
+        kind,
+        natural_light: [voxel::Light::MAX_NATURAL_INTENSITY as f32; 4],
+        artificial_light: [0.0; 4],
+        occlusion: [0; 4],
+    }
+}
+
+/// Holds the downsampled cells of a chunk, indexed by cell X and Z.
+struct Cells {
+    axis_size: IVec2,
+    cells: Vec<(IVec2, Option<Cell>)>,
+}
+
+impl Cells {
+    fn is_within_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.axis_size.x && pos.y < self.axis_size.y
+    }
+
+    fn get(&self, pos: IVec2) -> Option<Cell> {
+        debug_assert!(self.is_within_bounds(pos));
+        self.cells[(pos.x * self.axis_size.y + pos.y) as usize].1
+    }
+}
+
+/// Merges each size x size columns into a single cell, using the highest voxel as cell surface.
+fn downsample(kinds: &ChunkKind, size: i32) -> Cells {
+    let axis_size = IVec2::new(
+        chunk::X_AXIS_SIZE as i32 / size,
+        chunk::Z_AXIS_SIZE as i32 / size,
+    );
+
+    let mut cells = vec![];
+
+    for x in 0..axis_size.x {
+        for z in 0..axis_size.y {
+            let mut cell: Option<Cell> = None;
+
+            for column_x in x * size..(x + 1) * size {
+                for column_z in z * size..(z + 1) * size {
+                    if let Some(surface) = column_surface(kinds, column_x, column_z) {
+                        if cell.map_or(true, |c| surface.height > c.height) {
+                            cell = Some(surface);
+                        }
+                    }
+                }
+            }
+
+            cells.push((IVec2::new(x, z), cell));
+        }
+    }
+
+    Cells { axis_size, cells }
+}
+
+/// **Returns** the highest non-empty voxel of the given column, if any.
+fn column_surface(kinds: &ChunkKind, x: i32, z: i32) -> Option<Cell> {
+    (0..=chunk::Y_END).rev().find_map(|y| {
+        let kind = kinds.get((x, y, z).into());
+
+        if kind.is_none() {
+            None
+        } else {
+            Some(Cell { height: y, kind })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample() {
+        let mut kinds = ChunkKind::default();
+        kinds.set((0, 3, 0).into(), 1.into());
+        kinds.set((1, 5, 1).into(), 2.into());
+        kinds.set((15, 10, 15).into(), 3.into());
+
+        let cells = super::downsample(&kinds, 2);
+
+        assert_eq!(cells.axis_size, IVec2::new(8, 8));
+        assert_eq!(
+            cells.get((0, 0).into()),
+            Some(Cell {
+                height: 5,
+                kind: 2.into()
+            }),
+            "Highest voxel should be the cell surface"
+        );
+        assert_eq!(
+            cells.get((7, 7).into()),
+            Some(Cell {
+                height: 10,
+                kind: 3.into()
+            })
+        );
+        assert_eq!(cells.get((3, 3).into()), None);
+    }
+
+    #[test]
+    fn generate_lod_vertices() {
+        let mut kinds = ChunkKind::default();
+        for x in 0..chunk::X_AXIS_SIZE as i32 {
+            for z in 0..chunk::Z_AXIS_SIZE as i32 {
+                kinds.set((x, 20, z).into(), 1.into());
+            }
+        }
+
+        let vertices = super::generate_lod_vertices(&kinds, 2);
+
+        // 4x4 cells, each one with a top face. Only border cells has side faces (skirts).
+        let top_faces = 4 * 4;
+        let skirt_faces = 4 * 4;
+        assert_eq!(vertices.len(), (top_faces + skirt_faces) * 4);
+
+        let top = vertices
+            .iter()
+            .filter(|v| v.side() == voxel::Side::Up)
+            .collect::<Vec<_>>();
+
+        assert!(top.iter().all(|v| v.position().y == 21));
+        assert!(top
+            .iter()
+            .all(|v| v.natural_light() == 15 && v.artificial_light() == 0 && v.occlusion() == 0));
+
+        let skirt_bottom = vertices
+            .iter()
+            .filter(|v| v.side() != voxel::Side::Up)
+            .map(|v| v.position().y)
+            .min();
+        assert_eq!(skirt_bottom, Some(20 - SKIRT_DEPTH));
+    }
+}
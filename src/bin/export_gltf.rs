@@ -0,0 +1,505 @@
+//! Exports a region of chunks as a single binary glTF 2.0 file, so it can be rendered or edited on
+//! external tools. Chunks are loaded from chunk cache or generated when missing, using the same
+//! generator config of the game. Generated chunks aren't saved, so the cache is left untouched.
+
+use std::path::PathBuf;
+
+use bevy::math::{IVec2, IVec3, Vec2, Vec3};
+use projekto_core::{
+    chunk::{self, SectionFlags},
+    query,
+    voxel::{self, VoxelVertex},
+    VoxWorld,
+};
+use projekto_genesis::headless;
+use projekto_shaping::{self as shaping, GeneratorConfig};
+use serde_json::json;
+
+const USAGE: &str = "Exports a region of chunks as a binary glTF file.
+
+Usage: export_gltf [OPTIONS]
+
+Options:
+    --begin <X,Z>         First chunk of the region [default: 0,0]
+    --end <X,Z>           Last chunk of the region, inclusive [default: 0,0]
+    --output <FILE>       Output file [default: region.glb]
+    --help                Prints this message";
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_NEAREST: u32 = 9728;
+const GL_REPEAT: u32 = 10497;
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    begin: IVec3,
+    end: IVec3,
+    output: PathBuf,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            begin: IVec3::ZERO,
+            end: IVec3::ZERO,
+            output: PathBuf::from("region.glb"),
+        }
+    }
+}
+
+/// Parses command line arguments, without the program name.
+///
+/// **Returns** `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value of argument {arg}"))?;
+
+        match arg.as_str() {
+            "--begin" => parsed.begin = parse_chunk(&arg, &value)?,
+            "--end" => parsed.end = parse_chunk(&arg, &value)?,
+            "--output" => parsed.output = PathBuf::from(value),
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    Ok(Some(parsed))
+}
+
+fn parse_chunk(arg: &str, value: &str) -> Result<IVec3, String> {
+    let coords = value
+        .split(',')
+        .map(|c| c.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|c| c.len() == 2)
+        .ok_or_else(|| format!("Invalid value {value} of argument {arg}. Expected X,Z"))?;
+
+    Ok(IVec3::new(coords[0], 0, coords[1]))
+}
+
+/// Unpacked vertex attributes of the whole region, in world space.
+#[derive(Debug, Default)]
+struct MeshData {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+/// Vertex light as a grayscale color, the same way `voxel.wgsl` shader does at full daylight. Each
+/// opaque neighbor removes a quarter of vertex light.
+fn light_color(vertex: &VoxelVertex) -> f32 {
+    let natural = vertex.natural_light() as f32 / voxel::Light::MAX_NATURAL_INTENSITY as f32;
+    let artificial =
+        vertex.artificial_light() as f32 / voxel::Light::MAX_ARTIFICIAL_INTENSITY as f32;
+    natural.max(artificial) * (4.0 - vertex.occlusion() as f32) / 4.0
+}
+
+/// Texture coordinate of the top left corner of the given atlas tile, in `0.0..1.0` range.
+fn tile_coord_start(tile: u16, tiles_per_row: u16) -> Vec2 {
+    IVec2::new((tile % tiles_per_row) as i32, (tile / tiles_per_row) as i32).as_vec2()
+        / tiles_per_row as f32
+}
+
+impl MeshData {
+    /// Adds the given chunk vertices, which are grouped as faces of 4 vertices.
+    ///
+    /// The shader repeats the atlas tile on faces bigger than a voxel, which isn't possible on
+    /// glTF, so those faces are split into one face per voxel.
+    fn add_faces(&mut self, origin: Vec3, vertices: &[VoxelVertex], tiles_per_row: u16) {
+        let tile_size = 1.0 / tiles_per_row as f32;
+
+        for face in vertices.chunks_exact(4) {
+            let corners = face
+                .iter()
+                .map(|v| origin + v.position().as_vec3())
+                .collect::<Vec<_>>();
+            let lights = face.iter().map(light_color).collect::<Vec<_>>();
+
+            // Vertices uv are (0, v), (u, v), (u, 0) and (0, 0), in voxel units.
+            let size = face[1].uv().max(IVec2::ONE);
+            let normal = face[0].side().normal().to_array();
+            let start = tile_coord_start(face[0].tile_index(), tiles_per_row);
+
+            let lerp = |u: f32, v: f32| {
+                let u = u / size.x as f32;
+                let v = v / size.y as f32;
+
+                let position =
+                    corners[3] + (corners[2] - corners[3]) * u + (corners[0] - corners[3]) * v;
+                let light = lights[3] * (1.0 - u) * (1.0 - v)
+                    + lights[2] * u * (1.0 - v)
+                    + lights[1] * u * v
+                    + lights[0] * (1.0 - u) * v;
+
+                (position, light)
+            };
+
+            for u in 0..size.x {
+                for v in 0..size.y {
+                    let first = self.positions.len() as u32;
+                    let (u, v) = (u as f32, v as f32);
+
+                    for (offset, uv) in [
+                        (Vec2::new(0.0, 1.0), Vec2::new(0.0, 1.0)),
+                        (Vec2::new(1.0, 1.0), Vec2::new(1.0, 1.0)),
+                        (Vec2::new(1.0, 0.0), Vec2::new(1.0, 0.0)),
+                        (Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0)),
+                    ] {
+                        let (position, light) = lerp(u + offset.x, v + offset.y);
+
+                        self.positions.push(position.to_array());
+                        self.normals.push(normal);
+                        self.uvs.push((start + uv * tile_size).to_array());
+                        self.colors.push([light; 3]);
+                    }
+
+                    self.indices
+                        .extend([0, 1, 2, 2, 3, 0].into_iter().map(|i| first + i));
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+fn to_bytes<const N: usize>(items: &[[f32; N]]) -> Vec<u8> {
+    items
+        .iter()
+        .flat_map(|item| item.iter().flat_map(|f| f.to_le_bytes()))
+        .collect()
+}
+
+fn pad(bytes: &mut Vec<u8>, fill: u8) {
+    while bytes.len() % 4 != 0 {
+        bytes.push(fill);
+    }
+}
+
+/// Builds a binary glTF file with a single mesh and the given PNG atlas embedded as base color
+/// texture. Vertex colors holds the baked light and multiplies the base color.
+fn to_glb(mesh: &MeshData, atlas: &[u8]) -> Vec<u8> {
+    let (min, max) = mesh.positions.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), &p| (min.min(p.into()), max.max(p.into())),
+    );
+
+    let mut buffer = vec![];
+    let mut views = vec![];
+
+    let indices = mesh
+        .indices
+        .iter()
+        .flat_map(|i| i.to_le_bytes())
+        .collect::<Vec<_>>();
+
+    for (data, target) in [
+        (to_bytes(&mesh.positions), Some(GL_ARRAY_BUFFER)),
+        (to_bytes(&mesh.normals), Some(GL_ARRAY_BUFFER)),
+        (to_bytes(&mesh.uvs), Some(GL_ARRAY_BUFFER)),
+        (to_bytes(&mesh.colors), Some(GL_ARRAY_BUFFER)),
+        (indices, Some(GL_ELEMENT_ARRAY_BUFFER)),
+        (atlas.to_vec(), None),
+    ] {
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": data.len(),
+        });
+
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+
+        views.push(view);
+        buffer.extend(data);
+        pad(&mut buffer, 0);
+    }
+
+    let vertex_count = mesh.positions.len();
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "projekto export_gltf" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": "region" }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2, "COLOR_0": 3 },
+                "indices": 4,
+                "material": 0,
+            }],
+        }],
+        "materials": [{
+            "name": "voxel",
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+        }],
+        "textures": [{ "source": 0, "sampler": 0 }],
+        "images": [{ "bufferView": 5, "mimeType": "image/png" }],
+        "samplers": [{
+            "magFilter": GL_NEAREST,
+            "minFilter": GL_NEAREST,
+            "wrapS": GL_REPEAT,
+            "wrapT": GL_REPEAT,
+        }],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": GL_FLOAT, "count": vertex_count, "type": "VEC3",
+                "min": min.to_array(), "max": max.to_array(),
+            },
+            { "bufferView": 1, "componentType": GL_FLOAT, "count": vertex_count, "type": "VEC3" },
+            { "bufferView": 2, "componentType": GL_FLOAT, "count": vertex_count, "type": "VEC2" },
+            { "bufferView": 3, "componentType": GL_FLOAT, "count": vertex_count, "type": "VEC3" },
+            {
+                "bufferView": 4, "componentType": GL_UNSIGNED_INT,
+                "count": mesh.indices.len(), "type": "SCALAR",
+            },
+        ],
+        "bufferViews": views,
+        "buffers": [{ "byteLength": buffer.len() }],
+    });
+
+    let mut json = document.to_string().into_bytes();
+    pad(&mut json, b' ');
+
+    let length = 12 + 8 + json.len() + 8 + buffer.len();
+
+    let mut glb = Vec::with_capacity(length);
+    for word in [GLB_MAGIC, GLB_VERSION, length as u32] {
+        glb.extend(word.to_le_bytes());
+    }
+    for (ty, data) in [(GLB_CHUNK_JSON, json), (GLB_CHUNK_BIN, buffer)] {
+        glb.extend((data.len() as u32).to_le_bytes());
+        glb.extend(ty.to_le_bytes());
+        glb.extend(data);
+    }
+
+    glb
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let descs = voxel::KindsDescs::get();
+    let atlas_path = format!("{}/{}", env!("ASSETS_PATH"), descs.atlas_path);
+    let atlas = std::fs::read(&atlas_path)
+        .map_err(|err| format!("Failed to read atlas {atlas_path}: {err}"))?;
+
+    let locals = query::range_inclusive(args.begin.min(args.end), args.begin.max(args.end))
+        .collect::<Vec<_>>();
+
+    // Missing chunks must be generated the same way as chunks already on cache
+    let config = match headless::load_config()? {
+        Some(config) => config,
+        None => GeneratorConfig::from_env()?,
+    };
+    let world = headless::load(VoxWorld::default(), &locals, config);
+
+    let sections = locals
+        .iter()
+        .map(|&local| (local, SectionFlags::all()))
+        .collect::<Vec<_>>();
+
+    let mut mesh = MeshData::default();
+
+    for (local, sections) in shaping::generate_chunk_vertices(&world, &sections) {
+        let origin = chunk::to_world(local);
+
+        for (_, vertices, _) in sections {
+            mesh.add_faces(origin, &vertices, descs.count_tiles());
+        }
+    }
+
+    if mesh.is_empty() {
+        return Err("Region has no visible faces".to_string());
+    }
+
+    std::fs::write(&args.output, to_glb(&mesh, &atlas))
+        .map_err(|err| format!("Failed to write {}: {err}", args.output.display()))?;
+
+    println!(
+        "Exported {} chunks ({} vertices) to {}",
+        locals.len(),
+        mesh.positions.len(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    voxel::KindsDescs::init(format!("{}{}", env!("ASSETS_PATH"), "/voxels/kind.ron"));
+    headless::init();
+
+    if let Err(err) = run(args) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use voxel::Side;
+
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<Args>, String> {
+        super::parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    /// Creates an up face, starting at the given voxel and spanning `size` voxels on X and Z. Each
+    /// vertex is owned by the voxel right below it, so it can be packed.
+    fn up_face(voxel: IVec3, size: IVec2, tile: u16, light: u8) -> Vec<VoxelVertex> {
+        let top = voxel + IVec3::Y;
+        [
+            (top + IVec3::Z * size.y, IVec2::new(0, size.y)),
+            (top + IVec3::new(size.x, 0, size.y), size),
+            (top + IVec3::X * size.x, IVec2::new(size.x, 0)),
+            (top, IVec2::ZERO),
+        ]
+        .into_iter()
+        .map(|(position, uv)| VoxelVertex {
+            position: VoxelVertex::pack_position(position, Side::Up, position - IVec3::Y),
+            light: VoxelVertex::pack_light(light, 0),
+            artificial_light: VoxelVertex::pack_artificial_light(0),
+            tile: VoxelVertex::pack_tile(tile, uv),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(args(&[]), Ok(Some(Args::default())));
+        assert_eq!(args(&["--help"]), Ok(None));
+
+        let parsed = args(&["--begin", "-1,2", "--end", "3, 4", "--output", "a.glb"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.begin, IVec3::new(-1, 0, 2));
+        assert_eq!(parsed.end, IVec3::new(3, 0, 4));
+        assert_eq!(parsed.output, PathBuf::from("a.glb"));
+
+        assert!(args(&["--begin", "1"]).is_err());
+        assert!(args(&["--end"]).is_err());
+    }
+
+    #[test]
+    fn light_color() {
+        let vertex = VoxelVertex {
+            light: VoxelVertex::pack_light(15, 2),
+            ..Default::default()
+        };
+
+        assert_eq!(super::light_color(&vertex), 0.5);
+
+        let vertex = VoxelVertex {
+            light: VoxelVertex::pack_light(3, 0),
+            artificial_light: VoxelVertex::pack_artificial_light(15),
+            ..Default::default()
+        };
+
+        assert_eq!(super::light_color(&vertex), 1.0);
+    }
+
+    #[test]
+    fn tile_coord_start() {
+        assert_eq!(super::tile_coord_start(0, 10), Vec2::ZERO);
+        assert_eq!(super::tile_coord_start(23, 10), Vec2::new(0.3, 0.2));
+    }
+
+    #[test]
+    fn add_faces_split() {
+        let mut mesh = MeshData::default();
+        let origin = Vec3::new(16.0, 0.0, -16.0);
+
+        mesh.add_faces(
+            origin,
+            &up_face((1, 2, 3).into(), (1, 1).into(), 11, 15),
+            10,
+        );
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 3, 0]);
+        assert_eq!(mesh.positions[3], [17.0, 3.0, -13.0]);
+        assert_eq!(mesh.normals[0], [0.0, 1.0, 0.0]);
+        assert_eq!(mesh.colors[0], [1.0; 3]);
+        assert_eq!(mesh.uvs[3], [0.1, 0.1]);
+        assert_eq!(mesh.uvs[1], [0.2, 0.2]);
+
+        let mut mesh = MeshData::default();
+        mesh.add_faces(Vec3::ZERO, &up_face(IVec3::ZERO, (3, 2).into(), 0, 15), 10);
+
+        assert_eq!(mesh.positions.len(), 6 * 4, "Merged faces should be split");
+        assert_eq!(mesh.indices.len(), 6 * 6);
+        assert!(
+            mesh.uvs.iter().all(|uv| uv[0] <= 0.1 && uv[1] <= 0.1),
+            "All faces should use only the first tile"
+        );
+        assert!(mesh.positions.contains(&[3.0, 1.0, 2.0]));
+    }
+
+    #[test]
+    fn to_glb() {
+        let mut mesh = MeshData::default();
+        mesh.add_faces(Vec3::ZERO, &up_face(IVec3::ZERO, IVec2::ONE, 0, 15), 10);
+
+        let atlas = [1, 2, 3, 4, 5];
+        let glb = super::to_glb(&mesh, &atlas);
+
+        let word = |offset: usize| u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(word(0), GLB_MAGIC);
+        assert_eq!(word(4), GLB_VERSION);
+        assert_eq!(word(8) as usize, glb.len());
+        assert_eq!(glb.len() % 4, 0);
+
+        let json_len = word(12) as usize;
+        assert_eq!(word(16), GLB_CHUNK_JSON);
+
+        let document: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        assert_eq!(document["accessors"][0]["count"], 4);
+        assert_eq!(document["accessors"][0]["max"], json!([1.0, 1.0, 1.0]));
+        assert_eq!(document["accessors"][4]["count"], 6);
+        assert_eq!(document["bufferViews"][5]["byteLength"], atlas.len());
+
+        let bin_start = 20 + json_len;
+        assert_eq!(word(bin_start + 4), GLB_CHUNK_BIN);
+        assert_eq!(
+            word(bin_start) as u64,
+            document["buffers"][0]["byteLength"].as_u64().unwrap()
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Minecraft Anvil region files and chunk sections decoding.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Read,
+    path::Path,
+};
+
+use bevy::math::IVec3;
+use projekto_core::{
+    chunk::{self, ChunkKind},
+    voxel,
+};
+use serde::Deserialize;
+
+use super::nbt::{self, Tag};
+
+const SECTOR_SIZE: usize = 4096;
+const REGION_CHUNKS: usize = 32 * 32;
+const SECTION_VOXELS: usize = 16 * 16 * 16;
+
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+const COMPRESSION_NONE: u8 = 3;
+
+/// Blocks which are always empty, even if they aren't mapped.
+const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// Maps Minecraft block names to kinds, loaded from a ron file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AnvilMapping {
+    pub blocks: HashMap<String, u16>,
+    /// Kind used by blocks which aren't mapped. Those blocks are left empty when `None`.
+    pub default: Option<u16>,
+}
+
+impl AnvilMapping {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|err| format!("Failed to open {}: {err}", path.display()))?;
+
+        ron::de::from_reader(file)
+            .map_err(|err| format!("Failed to parse {}: {err}", path.display()))
+    }
+
+    fn is_mapped(&self, name: &str) -> bool {
+        AIR_BLOCKS.contains(&name) || self.blocks.contains_key(name)
+    }
+
+    /// **Returns** the kind of the given block name, which is empty for air and unmapped blocks
+    /// without a default kind.
+    pub fn kind(&self, name: &str) -> voxel::Kind {
+        if AIR_BLOCKS.contains(&name) {
+            return voxel::Kind::none();
+        }
+
+        self.blocks
+            .get(name)
+            .copied()
+            .or(self.default)
+            .map_or_else(voxel::Kind::none, voxel::Kind::from)
+    }
+}
+
+/// Reads all chunks of the given region file content. Chunks which fails to be read doesn't stop
+/// the remaining chunks from being read.
+///
+/// **Returns** the NBT of each chunk present on region or an error if the region header is invalid.
+pub fn read_region(bytes: &[u8]) -> Result<Vec<Result<Tag, String>>, String> {
+    if bytes.len() < SECTOR_SIZE * 2 {
+        return Err("Region file is smaller than its header".to_string());
+    }
+
+    let chunks = bytes[..SECTOR_SIZE]
+        .chunks_exact(4)
+        .take(REGION_CHUNKS)
+        .enumerate()
+        .filter_map(|(index, location)| {
+            let offset = u32::from_be_bytes([0, location[0], location[1], location[2]]) as usize;
+            let sectors = location[3] as usize;
+
+            if offset == 0 && sectors == 0 {
+                None
+            } else {
+                Some(
+                    read_chunk(bytes, offset * SECTOR_SIZE)
+                        .map_err(|err| format!("Chunk {index}: {err}")),
+                )
+            }
+        })
+        .collect();
+
+    Ok(chunks)
+}
+
+fn read_chunk(bytes: &[u8], start: usize) -> Result<Tag, String> {
+    let header = bytes
+        .get(start..start + 5)
+        .ok_or("Chunk offset is out of file")?;
+
+    let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let compression = header[4];
+
+    let data = bytes
+        .get(start + 5..start + 4 + length.max(1))
+        .ok_or("Chunk length is out of file")?;
+
+    let mut decompressed = vec![];
+    let result = match compression {
+        COMPRESSION_GZIP => flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed),
+        COMPRESSION_ZLIB => flate2::read::ZlibDecoder::new(data).read_to_end(&mut decompressed),
+        COMPRESSION_NONE => {
+            decompressed.extend(data);
+            Ok(data.len())
+        }
+        _ => return Err(format!("Unsupported compression {compression}")),
+    };
+
+    result.map_err(|err| format!("Failed to decompress: {err}"))?;
+
+    nbt::parse(&decompressed)
+}
+
+/// Unpacks the palette index of each block of a section. Since Minecraft 1.16, indices doesn't
+/// span across longs, so the remaining bits of each long are unused.
+pub fn unpack_indices(data: &[i64], palette_len: usize) -> Result<Vec<usize>, String> {
+    if palette_len <= 1 {
+        return Ok(vec![0; SECTION_VOXELS]);
+    }
+
+    let bits = ((usize::BITS - (palette_len - 1).leading_zeros()) as usize).max(4);
+    let per_long = 64 / bits;
+    let mask = (1u64 << bits) - 1;
+
+    if data.len() * per_long < SECTION_VOXELS {
+        return Err(format!(
+            "Block states has {} longs, but {} bits indices needs {}",
+            data.len(),
+            bits,
+            (SECTION_VOXELS + per_long - 1) / per_long
+        ));
+    }
+
+    Ok((0..SECTION_VOXELS)
+        .map(|i| ((data[i / per_long] as u64 >> (i % per_long * bits)) & mask) as usize)
+        .collect())
+}
+
+/// Gets the palette and block states of a section, either on 1.18+ format or on older format.
+fn section_states(section: &Tag) -> Option<(&[Tag], &[i64])> {
+    let (palette, data) = match section.get("block_states") {
+        Some(states) => (states.get("palette"), states.get("data")),
+        None => (section.get("Palette"), section.get("BlockStates")),
+    };
+
+    Some((
+        palette?.as_list()?,
+        data.and_then(Tag::as_long_array).unwrap_or_default(),
+    ))
+}
+
+/// A chunk converted to Projekto kinds.
+#[derive(Debug, Default)]
+pub struct Converted {
+    pub local: IVec3,
+    pub kinds: ChunkKind,
+    /// Names of blocks which aren't on mapping.
+    pub unmapped: BTreeSet<String>,
+    /// Number of non-empty blocks dropped since they are out of chunk height after offset.
+    pub dropped: usize,
+}
+
+/// Converts a Minecraft chunk NBT to Projekto kinds. Chunks have the same horizontal size, so each
+/// Minecraft chunk is converted to the chunk with the same X and Z.
+///
+/// `y_offset` is added to each block height and blocks outside [`chunk::Y_AXIS_SIZE`] are dropped.
+pub fn convert_chunk(
+    tag: &Tag,
+    mapping: &AnvilMapping,
+    y_offset: i32,
+) -> Result<Converted, String> {
+    // Before 1.18 all chunk data is inside a Level compound
+    let level = tag.get("Level").unwrap_or(tag);
+
+    let pos = |name: &str| level.get(name).and_then(Tag::as_i64).map(|v| v as i32);
+    let local = IVec3::new(
+        pos("xPos").ok_or("Missing xPos")?,
+        0,
+        pos("zPos").ok_or("Missing zPos")?,
+    );
+
+    let sections = level
+        .get("sections")
+        .or_else(|| level.get("Sections"))
+        .and_then(Tag::as_list)
+        .unwrap_or_default();
+
+    let mut converted = Converted {
+        local,
+        ..Default::default()
+    };
+
+    for section in sections {
+        let section_y = section.get("Y").and_then(Tag::as_i64).ok_or("Missing Y")? as i32;
+
+        let (palette, data) = match section_states(section) {
+            Some(states) => states,
+            // Sections with only light data
+            None => continue,
+        };
+
+        let kinds = palette
+            .iter()
+            .map(|entry| {
+                let name = entry.get("Name").and_then(Tag::as_str).unwrap_or_default();
+
+                if !mapping.is_mapped(name) {
+                    converted.unmapped.insert(name.to_string());
+                }
+
+                mapping.kind(name)
+            })
+            .collect::<Vec<_>>();
+
+        if kinds.iter().all(|kind| kind.is_none()) {
+            continue;
+        }
+
+        let indices = unpack_indices(data, palette.len())
+            .map_err(|err| format!("Section {section_y}: {err}"))?;
+
+        for (i, &index) in indices.iter().enumerate() {
+            let kind = *kinds
+                .get(index)
+                .ok_or_else(|| format!("Section {section_y}: Invalid palette index {index}"))?;
+
+            if kind.is_none() {
+                continue;
+            }
+
+            let y = section_y * 16 + (i >> 8) as i32 + y_offset;
+
+            if (0..chunk::Y_AXIS_SIZE as i32).contains(&y) {
+                let voxel = IVec3::new((i & 0xF) as i32, y, (i >> 4 & 0xF) as i32);
+                converted.kinds.set(voxel, kind);
+            } else {
+                converted.dropped += 1;
+            }
+        }
+    }
+
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(children: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(
+            children
+                .into_iter()
+                .map(|(name, tag)| (name.to_string(), tag))
+                .collect(),
+        )
+    }
+
+    fn block(name: &str) -> Tag {
+        compound(vec![("Name", Tag::String(name.to_string()))])
+    }
+
+    fn mapping() -> AnvilMapping {
+        AnvilMapping {
+            blocks: [("minecraft:stone".to_string(), 3)].into_iter().collect(),
+            default: None,
+        }
+    }
+
+    #[test]
+    fn mapping_kind() {
+        let mut mapping = mapping();
+
+        assert_eq!(mapping.kind("minecraft:stone"), 3.into());
+        assert!(mapping.kind("minecraft:cave_air").is_none());
+        assert!(mapping.kind("minecraft:gold_block").is_none());
+        assert!(!mapping.is_mapped("minecraft:gold_block"));
+
+        mapping.default = Some(1);
+        assert_eq!(mapping.kind("minecraft:gold_block"), 1.into());
+        assert!(mapping.kind("minecraft:air").is_none());
+    }
+
+    #[test]
+    fn unpack_indices() {
+        assert_eq!(super::unpack_indices(&[], 1), Ok(vec![0; SECTION_VOXELS]));
+
+        // 5 bits per index, 12 indices per long
+        let mut data = vec![0i64; (SECTION_VOXELS + 11) / 12];
+        data[0] = 1 | 16 << 5 | 3 << 55;
+        data[1] = 7;
+
+        let indices = super::unpack_indices(&data, 17).unwrap();
+        assert_eq!(indices.len(), SECTION_VOXELS);
+        assert_eq!(&indices[..3], &[1, 16, 0]);
+        assert_eq!(indices[11], 3);
+        assert_eq!(indices[12], 7);
+
+        assert!(super::unpack_indices(&data[1..], 17).is_err());
+    }
+
+    #[test]
+    fn convert_chunk() {
+        // 4 bits per index, 16 indices per long. First block of section is stone.
+        let mut data = vec![0i64; SECTION_VOXELS / 16];
+        data[0] = 1;
+
+        let section = |y: i8| {
+            compound(vec![
+                ("Y", Tag::Byte(y)),
+                (
+                    "block_states",
+                    compound(vec![
+                        (
+                            "palette",
+                            Tag::List(vec![block("minecraft:air"), block("minecraft:stone")]),
+                        ),
+                        ("data", Tag::LongArray(data.clone())),
+                    ]),
+                ),
+            ])
+        };
+
+        let tag = compound(vec![
+            ("xPos", Tag::Int(-3)),
+            ("zPos", Tag::Int(5)),
+            (
+                "sections",
+                Tag::List(vec![
+                    section(-4),
+                    section(2),
+                    compound(vec![
+                        ("Y", Tag::Byte(3)),
+                        (
+                            "block_states",
+                            compound(vec![(
+                                "palette",
+                                Tag::List(vec![block("minecraft:diamond_ore")]),
+                            )]),
+                        ),
+                    ]),
+                ]),
+            ),
+        ]);
+
+        let converted = super::convert_chunk(&tag, &mapping(), 0).unwrap();
+
+        assert_eq!(converted.local, IVec3::new(-3, 0, 5));
+        assert_eq!(converted.kinds.get((0, 32, 0).into()), 3.into());
+        assert!(converted.kinds.get((1, 32, 0).into()).is_none());
+        assert_eq!(converted.dropped, 1, "Blocks below zero should be dropped");
+        assert_eq!(
+            converted.unmapped.into_iter().collect::<Vec<_>>(),
+            vec!["minecraft:diamond_ore".to_string()]
+        );
+
+        let converted = super::convert_chunk(&tag, &mapping(), 64).unwrap();
+        assert_eq!(converted.kinds.get((0, 0, 0).into()), 3.into());
+        assert_eq!(converted.kinds.get((0, 96, 0).into()), 3.into());
+        assert_eq!(converted.dropped, 0);
+
+        assert!(super::convert_chunk(&compound(vec![]), &mapping(), 0).is_err());
+    }
+
+    #[test]
+    fn read_region() {
+        let mut chunk = vec![10, 0, 0];
+        chunk.extend([3, 0, 4, b'x', b'P', b'o', b's', 0, 0, 0, 7, 0]);
+
+        let mut region = vec![0; SECTOR_SIZE * 3];
+        // Second chunk of region, on third sector
+        region[4..8].copy_from_slice(&[0, 0, 2, 1]);
+
+        let start = SECTOR_SIZE * 2;
+        region[start..start + 4].copy_from_slice(&(chunk.len() as u32 + 1).to_be_bytes());
+        region[start + 4] = COMPRESSION_NONE;
+        region[start + 5..start + 5 + chunk.len()].copy_from_slice(&chunk);
+
+        let chunks = super::read_region(&region).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_ref().unwrap().get("xPos"), Some(&Tag::Int(7)));
+
+        region[start + 4] = 9;
+        assert!(super::read_region(&region).unwrap()[0].is_err());
+        assert!(super::read_region(&region[..SECTOR_SIZE]).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Minecraft Anvil region importer. Reads `.mca` region files, maps blocks to kinds and writes the
+//! resulting chunks into the chunk cache.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use projekto_core::{voxel, VoxWorld};
+use projekto_genesis::headless;
+
+use anvil::AnvilMapping;
+
+mod anvil;
+mod nbt;
+
+const USAGE: &str = "Imports Minecraft Anvil region files into the chunk cache.
+
+Usage: import_anvil [OPTIONS] <REGION_FILE>...
+
+Options:
+    --mapping <FILE>      Block names to kinds mapping [default: assets/voxels/anvil_mapping.ron]
+    --y-offset <N>        Added to each block height. Blocks out of chunk height are dropped
+                          [default: 0]
+    --help                Prints this message";
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    regions: Vec<PathBuf>,
+    mapping: Option<PathBuf>,
+    y_offset: i32,
+}
+
+/// Parses command line arguments, without the program name.
+///
+/// **Returns** `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+
+        if !arg.starts_with("--") {
+            parsed.regions.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value of argument {arg}"))?;
+
+        match arg.as_str() {
+            "--mapping" => parsed.mapping = Some(PathBuf::from(value)),
+            "--y-offset" => {
+                parsed.y_offset = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid value {value} of argument {arg}"))?
+            }
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    if parsed.regions.is_empty() {
+        return Err("Missing region file".to_string());
+    }
+
+    Ok(Some(parsed))
+}
+
+/// Imports a single region file.
+///
+/// **Returns** the number of imported chunks.
+fn import_region(
+    path: &Path,
+    mapping: &AnvilMapping,
+    y_offset: i32,
+    unmapped: &mut BTreeMap<String, usize>,
+) -> Result<usize, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+
+    let mut chunks = vec![];
+    let mut dropped = 0;
+
+    for chunk in anvil::read_region(&bytes)? {
+        let converted = chunk.and_then(|tag| anvil::convert_chunk(&tag, mapping, y_offset));
+
+        match converted {
+            Ok(converted) => {
+                for name in converted.unmapped {
+                    *unmapped.entry(name).or_default() += 1;
+                }
+
+                dropped += converted.dropped;
+                chunks.push((converted.local, converted.kinds));
+            }
+            Err(err) => eprintln!("Skipping chunk of {}. {err}", path.display()),
+        }
+    }
+
+    if dropped > 0 {
+        eprintln!(
+            "Dropped {dropped} blocks of {} which are out of chunk height. Consider using --y-offset",
+            path.display()
+        );
+    }
+
+    let count = chunks.len();
+
+    if count > 0 {
+        let (_, timings) = headless::import(VoxWorld::default(), chunks);
+
+        println!(
+            "Imported {} chunks of {} in {:.2}s",
+            count,
+            path.display(),
+            timings.total().as_secs_f32()
+        );
+    }
+
+    Ok(count)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mapping_path = args.mapping.unwrap_or_else(|| {
+        PathBuf::from(format!("{}/voxels/anvil_mapping.ron", env!("ASSETS_PATH")))
+    });
+    let mapping = AnvilMapping::load(mapping_path)?;
+
+    let start = Instant::now();
+    let mut unmapped = BTreeMap::new();
+    let mut total = 0;
+
+    for path in &args.regions {
+        total += import_region(path, &mapping, args.y_offset, &mut unmapped)?;
+    }
+
+    println!(
+        "Imported {} chunks in {:.2}s",
+        total,
+        start.elapsed().as_secs_f32()
+    );
+
+    if !unmapped.is_empty() {
+        println!("Unmapped blocks (chunks count):");
+        for (name, count) in unmapped {
+            println!("    {name} ({count})");
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    voxel::KindsDescs::init(format!("{}{}", env!("ASSETS_PATH"), "/voxels/kind.ron"));
+    headless::init();
+
+    if let Err(err) = run(args) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<Args>, String> {
+        super::parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert!(args(&[]).is_err(), "Region file is required");
+        assert_eq!(args(&["--help"]), Ok(None));
+
+        let parsed = args(&["r.0.0.mca", "--y-offset", "-64", "r.0.1.mca"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed.regions,
+            vec![PathBuf::from("r.0.0.mca"), PathBuf::from("r.0.1.mca")]
+        );
+        assert_eq!(parsed.y_offset, -64);
+        assert_eq!(parsed.mapping, None);
+
+        assert!(args(&["r.0.0.mca", "--y-offset", "up"]).is_err());
+    }
+
+    #[test]
+    fn load_mapping() {
+        let mapping =
+            AnvilMapping::load(format!("{}/voxels/anvil_mapping.ron", env!("ASSETS_PATH")))
+                .unwrap();
+
+        assert_eq!(mapping.kind("minecraft:grass_block"), 2.into());
+        assert!(mapping.kind("minecraft:air").is_none());
+    }
+}
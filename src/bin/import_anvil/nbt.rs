@@ -0,0 +1,207 @@
+//! Minimal reader of Minecraft Named Binary Tag format, which is big endian and uncompressed at
+//! this point.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const TAG_END: u8 = 0;
+const TAG_COMPOUND: u8 = 10;
+
+impl Tag {
+    /// **Returns** the child tag with the given name, if this is a compound.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// **Returns** any integer tag value as [`i64`].
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        self.slice(N).map(|bytes| bytes.try_into().unwrap())
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("Unexpected end of NBT data".to_string());
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let len = i32::from_be_bytes(self.take()?);
+        usize::try_from(len).map_err(|_| format!("Invalid NBT length {len}"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+        Ok(String::from_utf8_lossy(self.slice(len)?).into_owned())
+    }
+
+    fn array<T, const N: usize>(&mut self, f: fn([u8; N]) -> T) -> Result<Vec<T>, String> {
+        let len = self.len()?;
+        (0..len).map(|_| self.take().map(f)).collect()
+    }
+
+    fn payload(&mut self, ty: u8) -> Result<Tag, String> {
+        let tag = match ty {
+            1 => Tag::Byte(i8::from_be_bytes(self.take()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.take()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.take()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.take()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.take()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.take()?)),
+            7 => Tag::ByteArray(self.array(i8::from_be_bytes)?),
+            8 => Tag::String(self.string()?),
+            9 => {
+                let [item_ty] = self.take()?;
+                let len = self.len()?;
+                Tag::List(
+                    (0..len)
+                        .map(|_| self.payload(item_ty))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            TAG_COMPOUND => {
+                let mut children = HashMap::new();
+                loop {
+                    let [child_ty] = self.take()?;
+                    if child_ty == TAG_END {
+                        break;
+                    }
+
+                    let name = self.string()?;
+                    children.insert(name, self.payload(child_ty)?);
+                }
+                Tag::Compound(children)
+            }
+            11 => Tag::IntArray(self.array(i32::from_be_bytes)?),
+            12 => Tag::LongArray(self.array(i64::from_be_bytes)?),
+            _ => return Err(format!("Unknown NBT tag type {ty}")),
+        };
+
+        Ok(tag)
+    }
+}
+
+/// Parses an uncompressed NBT document, which root is a named compound. The root name is ignored.
+pub fn parse(bytes: &[u8]) -> Result<Tag, String> {
+    let mut reader = Reader { bytes };
+
+    let [ty] = reader.take()?;
+    if ty != TAG_COMPOUND {
+        return Err(format!("NBT root should be a compound, but it is {ty}"));
+    }
+
+    reader.string()?;
+    reader.payload(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(ty: u8, name: &str, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![ty];
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        let mut list = vec![8];
+        list.extend(2i32.to_be_bytes());
+        for value in ["a", "bc"] {
+            list.extend((value.len() as u16).to_be_bytes());
+            list.extend(value.as_bytes());
+        }
+
+        let mut longs = 2i32.to_be_bytes().to_vec();
+        longs.extend((-1i64).to_be_bytes());
+        longs.extend(7i64.to_be_bytes());
+
+        let mut root = vec![];
+        root.extend(named(1, "byte", &[0xFF]));
+        root.extend(named(3, "int", &42i32.to_be_bytes()));
+        root.extend(named(9, "list", &list));
+        root.extend(named(12, "longs", &longs));
+        root.extend(named(TAG_COMPOUND, "inner", &[TAG_END]));
+        root.push(TAG_END);
+
+        let tag = super::parse(&named(TAG_COMPOUND, "", &root)).unwrap();
+
+        assert_eq!(tag.get("byte").and_then(Tag::as_i64), Some(-1));
+        assert_eq!(tag.get("int").and_then(Tag::as_i64), Some(42));
+        assert_eq!(
+            tag.get("list").and_then(Tag::as_list),
+            Some(&[Tag::String("a".into()), Tag::String("bc".into())][..])
+        );
+        assert_eq!(
+            tag.get("longs").and_then(Tag::as_long_array),
+            Some(&[-1, 7][..])
+        );
+        assert_eq!(tag.get("inner"), Some(&Tag::Compound(HashMap::new())));
+        assert_eq!(tag.get("missing"), None);
+
+        assert!(super::parse(&named(3, "", &[0, 0, 0, 1])).is_err());
+        assert!(super::parse(&named(TAG_COMPOUND, "", &root[..root.len() - 1])).is_err());
+    }
+}
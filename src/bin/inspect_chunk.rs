@@ -0,0 +1,362 @@
+//! Chunk cache file inspector. Decodes a chunk file, prints a summary of its content, dumps a
+//! horizontal slice as ASCII or exports the whole chunk as RON or JSON.
+
+use std::path::PathBuf;
+
+use bevy::math::IVec3;
+use projekto_core::{
+    chunk::{self, Chunk, ChunkKind, ChunkLight, ChunkStorage, ChunkStorageType},
+    voxel::{self, LightTy},
+};
+use projekto_genesis::{cache_path, decode_chunk};
+
+const USAGE: &str = "Inspects a chunk cache file.
+
+Usage: inspect_chunk [OPTIONS] <FILE>
+       inspect_chunk [OPTIONS] --local <X,Y,Z>
+
+Options:
+    --local <X,Y,Z>       Inspects the cache file of the given chunk local
+    --slice <Y>           Dumps the voxels kinds of the given height as ASCII
+    --export <FORMAT>     Exports the whole chunk, either as ron or json
+    --output <FILE>       Writes the export to the given file instead of stdout
+    --help                Prints this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ron,
+    Json,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    path: Option<PathBuf>,
+    slice: Option<i32>,
+    export: Option<Format>,
+    output: Option<PathBuf>,
+}
+
+/// Parses command line arguments, without the program name.
+///
+/// **Returns** `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+
+        if !arg.starts_with("--") {
+            parsed.path = Some(PathBuf::from(arg));
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value of argument {arg}"))?;
+
+        match arg.as_str() {
+            "--local" => {
+                let coords = value
+                    .split(',')
+                    .map(|c| c.trim().parse::<i32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|c| c.len() == 3)
+                    .ok_or_else(|| format!("Invalid local {value}. Expected X,Y,Z"))?;
+
+                parsed.path = Some(cache_path(IVec3::new(coords[0], coords[1], coords[2])));
+            }
+            "--slice" => {
+                let y = value
+                    .trim()
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|&y| (0..chunk::Y_AXIS_SIZE as i32).contains(&y))
+                    .ok_or_else(|| format!("Invalid slice {value}"))?;
+
+                parsed.slice = Some(y);
+            }
+            "--export" => {
+                parsed.export = match value.as_str() {
+                    "ron" => Some(Format::Ron),
+                    "json" => Some(Format::Json),
+                    _ => return Err(format!("Invalid export format {value}")),
+                }
+            }
+            "--output" => parsed.output = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    if parsed.path.is_none() {
+        return Err("Missing chunk file".to_string());
+    }
+
+    Ok(Some(parsed))
+}
+
+/// **Returns** how many voxels there are of each kind id, sorted by kind id.
+fn kind_histogram(kinds: &ChunkKind) -> Vec<(u16, usize)> {
+    let mut histogram = std::collections::BTreeMap::new();
+
+    for &kind in kinds.iter() {
+        *histogram.entry(u16::from(kind)).or_insert(0) += 1;
+    }
+
+    histogram.into_iter().collect()
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct LightStats {
+    min: u8,
+    max: u8,
+    avg: f32,
+    lit: usize,
+}
+
+fn light_stats(lights: &ChunkLight, ty: LightTy) -> LightStats {
+    let mut stats = LightStats {
+        min: u8::MAX,
+        ..Default::default()
+    };
+    let mut sum = 0usize;
+
+    for light in lights.iter() {
+        let intensity = light.get(ty);
+
+        stats.min = stats.min.min(intensity);
+        stats.max = stats.max.max(intensity);
+        sum += intensity as usize;
+
+        if intensity > 0 {
+            stats.lit += 1;
+        }
+    }
+
+    stats.avg = sum as f32 / chunk::BUFFER_SIZE as f32;
+    stats
+}
+
+/// ASCII character used to represent a kind id on slices.
+fn kind_char(id: u16) -> char {
+    match id {
+        0 => '.',
+        id if id < 36 => std::char::from_digit(id as u32, 36).unwrap_or('#'),
+        _ => '#',
+    }
+}
+
+/// Dumps the voxels kinds of the given height. Each row is a Z coordinate and each column is a X
+/// coordinate.
+fn slice_ascii(kinds: &ChunkKind, y: i32) -> String {
+    let mut ascii = format!(
+        "    {}\n",
+        (0..chunk::X_AXIS_SIZE)
+            .map(|x| format!("{:x}", x))
+            .collect::<String>()
+    );
+
+    for z in 0..chunk::Z_AXIS_SIZE as i32 {
+        ascii.push_str(&format!("{:>3} ", z));
+
+        for x in 0..chunk::X_AXIS_SIZE as i32 {
+            ascii.push(kind_char(kinds.get((x, y, z).into()).into()));
+        }
+
+        ascii.push('\n');
+    }
+
+    ascii
+}
+
+fn kind_name(id: u16) -> String {
+    voxel::KindsDescs::get()
+        .descriptions
+        .iter()
+        .find(|desc| desc.id == id)
+        .map_or_else(|| "unknown".to_string(), |desc| desc.name.clone())
+}
+
+fn neighborhood_summary<T: ChunkStorageType>(storage: &ChunkStorage<T>) -> String {
+    voxel::SIDES
+        .iter()
+        .map(|&side| {
+            let present = if storage.neighborhood.has_side(side) {
+                "yes"
+            } else {
+                "no"
+            };
+            format!("{:?}: {}", side, present)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_summary(chunk: &Chunk) {
+    println!("Kinds:");
+    for (id, count) in kind_histogram(&chunk.kinds) {
+        println!(
+            "    {:>5} {:<16}{:>8} ({:.1}%)",
+            id,
+            kind_name(id),
+            count,
+            count as f32 * 100.0 / chunk::BUFFER_SIZE as f32
+        );
+    }
+
+    println!("Lights:");
+    for ty in [LightTy::Natural, LightTy::Artificial] {
+        let LightStats { min, max, avg, lit } = light_stats(&chunk.lights, ty);
+        println!("    {ty:?}: min {min}, max {max}, avg {avg:.2}, lit voxels {lit}");
+    }
+
+    let vertices = chunk.vertices.iter().map(Vec::len).collect::<Vec<_>>();
+    println!(
+        "Vertices: {} {:?}",
+        vertices.iter().sum::<usize>(),
+        vertices
+    );
+
+    println!("Neighborhood:");
+    println!("    Kinds: {}", neighborhood_summary(&chunk.kinds));
+    println!("    Lights: {}", neighborhood_summary(&chunk.lights));
+}
+
+fn export(chunk: &Chunk, format: Format) -> Result<String, String> {
+    match format {
+        Format::Ron => ron::ser::to_string_pretty(chunk, Default::default())
+            .map_err(|err| format!("Failed to export as ron: {err}")),
+        Format::Json => serde_json::to_string_pretty(chunk)
+            .map_err(|err| format!("Failed to export as json: {err}")),
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let path = args.path.expect("Path should be validated on parsing");
+
+    let compressed =
+        std::fs::read(&path).map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    let chunk = decode_chunk(&compressed)?;
+
+    if let Some(format) = args.export {
+        let exported = export(&chunk, format)?;
+
+        return match args.output {
+            Some(output) => std::fs::write(&output, exported)
+                .map_err(|err| format!("Failed to write {}: {err}", output.display())),
+            None => {
+                println!("{exported}");
+                Ok(())
+            }
+        };
+    }
+
+    println!("Chunk file {} ({} bytes)", path.display(), compressed.len());
+    print_summary(&chunk);
+
+    if let Some(y) = args.slice {
+        println!("Slice at height {y}:");
+        print!("{}", slice_ascii(&chunk.kinds, y));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    voxel::KindsDescs::init(format!("{}{}", env!("ASSETS_PATH"), "/voxels/kind.ron"));
+
+    if let Err(err) = run(args) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<Args>, String> {
+        super::parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert!(args(&[]).is_err(), "Chunk file is required");
+        assert_eq!(args(&["--help"]), Ok(None));
+
+        let parsed = args(&["chunk.bin", "--slice", "10", "--export", "json"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.path, Some(PathBuf::from("chunk.bin")));
+        assert_eq!(parsed.slice, Some(10));
+        assert_eq!(parsed.export, Some(Format::Json));
+
+        let parsed = args(&["--local", "-1, 0, 2"]).unwrap().unwrap();
+        assert_eq!(parsed.path, Some(cache_path((-1, 0, 2).into())));
+
+        assert!(args(&["--local", "1,2"]).is_err());
+        assert!(args(&["a.bin", "--slice", "256"]).is_err());
+        assert!(args(&["a.bin", "--export", "xml"]).is_err());
+    }
+
+    #[test]
+    fn kind_histogram() {
+        let mut kinds = ChunkKind::default();
+        kinds.set((0, 0, 0).into(), 2.into());
+        kinds.set((1, 0, 0).into(), 2.into());
+        kinds.set((2, 0, 0).into(), 1.into());
+
+        assert_eq!(
+            super::kind_histogram(&kinds),
+            vec![(0, chunk::BUFFER_SIZE - 3), (1, 1), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn light_stats() {
+        let mut lights = ChunkLight::default();
+        lights.set_type((0, 0, 0).into(), LightTy::Natural, 15);
+        lights.set_type((1, 0, 0).into(), LightTy::Natural, 5);
+
+        let stats = super::light_stats(&lights, LightTy::Natural);
+        assert_eq!(stats.min, 0);
+        assert_eq!(stats.max, 15);
+        assert_eq!(stats.lit, 2);
+        assert_eq!(stats.avg, 20.0 / chunk::BUFFER_SIZE as f32);
+
+        assert_eq!(
+            super::light_stats(&lights, LightTy::Artificial),
+            LightStats::default()
+        );
+    }
+
+    #[test]
+    fn slice_ascii() {
+        let mut kinds = ChunkKind::default();
+        kinds.set((0, 5, 0).into(), 1.into());
+        kinds.set((15, 5, 2).into(), 11.into());
+        kinds.set((3, 6, 0).into(), 1.into());
+
+        let ascii = super::slice_ascii(&kinds, 5);
+        let lines = ascii.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), chunk::Z_AXIS_SIZE + 1);
+        assert_eq!(lines[0], "    0123456789abcdef");
+        assert_eq!(lines[1], "  0 1...............");
+        assert_eq!(lines[3], "  2 ...............b");
+    }
+}
@@ -0,0 +1,422 @@
+//! Headless world pre-generation. Generates an area of chunks into the chunk cache, without any
+//! window or renderer, and prints the time spent on each pipeline stage.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    math::{IVec2, IVec3},
+    utils::HashSet,
+};
+use projekto_core::{query, voxel, VoxWorld};
+use projekto_genesis::{cache_path, decode_chunk, headless, StageTimings};
+use projekto_shaping::{GeneratorConfig, Heightmap};
+
+const USAGE: &str = "Pre-generates chunks into the chunk cache. The generator options are saved on
+cache too, so the game generates chunks around the pre-generated area the same way.
+
+Usage: pregen [OPTIONS]
+
+Options:
+    --radius <N>          Area radius, in chunks [default: 8]
+    --shape <SHAPE>       Area shape, either square or circle [default: square]
+    --center <X,Z>        Area center chunk [default: 0,0]
+    --batch <N>           Chunks generated on each batch [default: 32]
+    --seed <N>            Generator noise seed
+    --frequency <F>       Generator noise frequency
+    --octaves <N>         Generator noise fractal octaves
+    --gain <F>            Generator noise fractal gain
+    --lacunarity <F>      Generator noise fractal lacunarity
+    --heightmap <FILE>    Generates terrain from a grayscale PNG instead of noise
+    --heightmap-origin <X,Z>
+                          World column of heightmap top left pixel [default: centered on world
+                          origin, the same way the game places PROJEKTO_HEIGHTMAP]
+    --export-heightmap <FILE>
+                          Exports the area surface height, read from cache, as a 16-bit PNG
+    --overwrite           Generates chunks again, even if those already exists on cache
+    --help                Prints this message";
+
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Square,
+    Circle,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    radius: i32,
+    shape: Shape,
+    center: IVec3,
+    batch: usize,
+    config: GeneratorConfig,
+    heightmap: Option<PathBuf>,
+    heightmap_origin: Option<IVec2>,
+    export_heightmap: Option<PathBuf>,
+    overwrite: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            radius: 8,
+            shape: Shape::Square,
+            center: IVec3::ZERO,
+            batch: 32,
+            config: GeneratorConfig::default(),
+            heightmap: None,
+            heightmap_origin: None,
+            export_heightmap: None,
+            overwrite: false,
+        }
+    }
+}
+
+/// Parses command line arguments, without the program name.
+///
+/// **Returns** `None` if help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+
+        if arg == "--overwrite" {
+            parsed.overwrite = true;
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value of argument {arg}"))?;
+
+        match arg.as_str() {
+            "--radius" => parsed.radius = parse_value(&arg, &value)?,
+            "--batch" => parsed.batch = parse_value::<usize>(&arg, &value)?.max(1),
+            "--shape" => {
+                parsed.shape = match value.as_str() {
+                    "square" => Shape::Square,
+                    "circle" => Shape::Circle,
+                    _ => return Err(format!("Invalid shape {value}")),
+                }
+            }
+            "--center" => {
+                let (x, z) = parse_pair(&arg, &value)?;
+                parsed.center = IVec3::new(x, 0, z);
+            }
+            "--heightmap" => parsed.heightmap = Some(PathBuf::from(value)),
+            "--heightmap-origin" => {
+                parsed.heightmap_origin = Some(parse_pair(&arg, &value)?.into())
+            }
+            "--export-heightmap" => parsed.export_heightmap = Some(PathBuf::from(value)),
+            "--seed" => parsed.config.seed = parse_value(&arg, &value)?,
+            "--frequency" => parsed.config.frequency = parse_value(&arg, &value)?,
+            "--octaves" => parsed.config.octaves = parse_value(&arg, &value)?,
+            "--gain" => parsed.config.gain = parse_value(&arg, &value)?,
+            "--lacunarity" => parsed.config.lacunarity = parse_value(&arg, &value)?,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    Ok(Some(parsed))
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid value {value} of argument {arg}"))
+}
+
+fn parse_pair(arg: &str, value: &str) -> Result<(i32, i32), String> {
+    let (x, z) = value
+        .split_once(',')
+        .ok_or_else(|| format!("Invalid value {value} of argument {arg}. Expected X,Z"))?;
+
+    Ok((parse_value(arg, x)?, parse_value(arg, z)?))
+}
+
+/// Lists all chunks inside the given area, sorted by distance to center, so each batch is generated
+/// next to already generated chunks.
+fn area_locals(center: IVec3, radius: i32, shape: Shape) -> Vec<IVec3> {
+    let radius = radius.max(0);
+    let extent = IVec3::new(radius, 0, radius);
+
+    let mut locals = query::range_inclusive(center - extent, center + extent)
+        .filter(|&local| match shape {
+            Shape::Square => true,
+            Shape::Circle => (local - center).as_vec3().length() <= radius as f32 + 0.5,
+        })
+        .collect::<Vec<_>>();
+
+    locals.sort_by_key(|&local| {
+        let offset = local - center;
+        (offset.x * offset.x + offset.z * offset.z, local.x, local.z)
+    });
+
+    locals
+}
+
+/// Removes chunks which all horizontal neighbors inside the area were already generated, since
+/// those won't be updated anymore.
+fn release_chunks(world: &mut VoxWorld, area: &HashSet<IVec3>, generated: &HashSet<IVec3>) {
+    for local in world.list_chunks() {
+        let done = voxel::SIDES
+            .iter()
+            .map(|side| local + side.dir())
+            .filter(|neighbor| neighbor.y == local.y)
+            .all(|neighbor| !area.contains(&neighbor) || generated.contains(&neighbor));
+
+        if done {
+            world.remove(local);
+        }
+    }
+}
+
+/// Removes cache files of the given chunks, so those are generated again.
+fn remove_cached(locals: &[IVec3]) -> Result<(), String> {
+    for &local in locals {
+        let path = cache_path(local);
+
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .map_err(|err| format!("Failed to remove {}: {err}", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Exports the surface height of the given chunks, read from cache, as a 16-bit PNG.
+fn export_heightmap(locals: &[IVec3], path: &Path) -> Result<(), String> {
+    let (begin, end) = locals.iter().fold(
+        (IVec3::splat(i32::MAX), IVec3::splat(i32::MIN)),
+        |(begin, end), &local| (begin.min(local), end.max(local)),
+    );
+
+    let chunks = locals
+        .iter()
+        .filter_map(|&local| {
+            let bytes = std::fs::read(cache_path(local)).ok()?;
+            decode_chunk(&bytes).ok().map(|chunk| (local, chunk.kinds))
+        })
+        .collect::<bevy::utils::HashMap<_, _>>();
+
+    let png = Heightmap::from_chunks(begin, end, |local| chunks.get(&local)).to_png()?;
+
+    std::fs::write(path, png).map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
+
+fn print_progress(done: usize, total: usize, elapsed: Duration) {
+    let ratio = if total == 0 {
+        1.0
+    } else {
+        done as f32 / total as f32
+    };
+    let filled = (ratio * PROGRESS_BAR_WIDTH as f32) as usize;
+
+    eprint!(
+        "\r[{}{}] {}/{} ({:.0}%) {:.1}s",
+        "#".repeat(filled),
+        ".".repeat(PROGRESS_BAR_WIDTH - filled),
+        done,
+        total,
+        ratio * 100.0,
+        elapsed.as_secs_f32()
+    );
+    std::io::stderr().flush().ok();
+}
+
+fn print_timings(timings: &StageTimings, chunks: usize, elapsed: Duration) {
+    println!(
+        "Pre-generated {} chunks in {:.2}s",
+        chunks,
+        elapsed.as_secs_f32()
+    );
+
+    for (stage, duration) in [
+        ("generate", timings.generate),
+        ("load", timings.load),
+        ("light", timings.light),
+        ("vertices", timings.vertices),
+        ("save", timings.save),
+    ] {
+        println!("    {:<10}{:>10.3}s", stage, duration.as_secs_f32());
+    }
+}
+
+fn main() {
+    let mut args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    voxel::KindsDescs::init(format!("{}{}", env!("ASSETS_PATH"), "/voxels/kind.ron"));
+    headless::init();
+
+    if let Some(path) = &args.heightmap {
+        let heightmap = match args.heightmap_origin {
+            Some(origin) => std::fs::read(path)
+                .map_err(|err| format!("Failed to read {}: {err}", path.display()))
+                .and_then(|bytes| Heightmap::from_png(&bytes, origin)),
+            None => Heightmap::load_centered(&path.to_string_lossy()),
+        };
+
+        match heightmap {
+            Ok(heightmap) => args.config.heightmap = Some(Arc::new(heightmap)),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Err(err) = headless::save_config(&args.config) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+
+    println!(
+        "Pre-generating {:?} area of radius {} around chunk {} using {:?}",
+        args.shape, args.radius, args.center, args.config
+    );
+
+    let locals = area_locals(args.center, args.radius, args.shape);
+    let area = locals.iter().copied().collect::<HashSet<_>>();
+
+    if args.overwrite {
+        if let Err(err) = remove_cached(&locals) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+
+    let mut world = VoxWorld::default();
+    let mut generated = HashSet::default();
+    let mut timings = StageTimings::default();
+    let start = Instant::now();
+
+    print_progress(0, locals.len(), start.elapsed());
+
+    for batch in locals.chunks(args.batch) {
+        let (result, batch_timings) = headless::generate(world, batch, args.config.clone());
+
+        world = result;
+        timings += batch_timings;
+        generated.extend(batch.iter().copied());
+
+        release_chunks(&mut world, &area, &generated);
+
+        print_progress(generated.len(), locals.len(), start.elapsed());
+    }
+
+    eprintln!();
+    print_timings(&timings, locals.len(), start.elapsed());
+
+    if let Some(path) = &args.export_heightmap {
+        match export_heightmap(&locals, path) {
+            Ok(_) => println!("Exported heightmap to {}", path.display()),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<Args>, String> {
+        super::parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(args(&[]), Ok(Some(Args::default())));
+        assert_eq!(args(&["--radius", "3", "--help"]), Ok(None));
+
+        let parsed = args(&[
+            "--radius", "3", "--shape", "circle", "--center", "-2, 5", "--seed", "42",
+        ])
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(parsed.radius, 3);
+        assert_eq!(parsed.shape, Shape::Circle);
+        assert_eq!(parsed.center, IVec3::new(-2, 0, 5));
+        assert_eq!(parsed.config.seed, 42);
+        assert!(!parsed.overwrite);
+
+        let parsed = args(&[
+            "--overwrite",
+            "--heightmap",
+            "island.png",
+            "--heightmap-origin",
+            "-64,32",
+        ])
+        .unwrap()
+        .unwrap();
+
+        assert!(parsed.overwrite);
+        assert_eq!(parsed.heightmap, Some(PathBuf::from("island.png")));
+        assert_eq!(parsed.heightmap_origin, Some(IVec2::new(-64, 32)));
+
+        assert!(args(&["--radius"]).is_err());
+        assert!(args(&["--shape", "triangle"]).is_err());
+        assert!(args(&["--unknown", "1"]).is_err());
+        assert!(args(&["--heightmap-origin", "1"]).is_err());
+    }
+
+    #[test]
+    fn area_locals() {
+        let center = IVec3::new(1, 0, -1);
+
+        let square = super::area_locals(center, 2, Shape::Square);
+        assert_eq!(square.len(), 25);
+        assert_eq!(square[0], center, "Center should be generated first");
+
+        let circle = super::area_locals(center, 2, Shape::Circle);
+        assert_eq!(circle.len(), 21, "Square corners should be skipped");
+
+        assert_eq!(super::area_locals(center, 0, Shape::Circle), vec![center]);
+    }
+
+    #[test]
+    fn release_chunks() {
+        let area = super::area_locals(IVec3::ZERO, 1, Shape::Square)
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let mut world = VoxWorld::default();
+        world.add(IVec3::ZERO, Default::default());
+        world.add(IVec3::X, Default::default());
+
+        let generated = [IVec3::ZERO, IVec3::X, -IVec3::X, IVec3::Z, -IVec3::Z]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        super::release_chunks(&mut world, &area, &generated);
+
+        assert!(!world.exists(IVec3::ZERO));
+        assert!(
+            world.exists(IVec3::X),
+            "Chunks with neighbors not generated yet should be kept"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use bevy::{ecs::system::SystemParam, prelude::*};
 use projekto_camera::{fly_by::FlyByCameraConfig, orbit::OrbitCameraConfig};
+use serde::{Deserialize, Serialize};
 
 pub struct CameraControllerPlugin;
 
@@ -21,25 +22,25 @@ fn setup_camera(
     flyby_config.rotate_speed = 1.0;
 }
 
-#[derive(Default, Debug)]
-enum CurrentCameraType {
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum CurrentCameraType {
     #[default]
     Orbit,
     FlyBy,
 }
 
 #[derive(SystemParam)]
-struct CameraConfig<'w, 's> {
-    orbit: ResMut<'w, OrbitCameraConfig>,
-    flyby: ResMut<'w, FlyByCameraConfig>,
-    cam_type: ResMut<'w, CurrentCameraType>,
+pub(crate) struct CameraConfig<'w, 's> {
+    pub orbit: ResMut<'w, OrbitCameraConfig>,
+    pub flyby: ResMut<'w, FlyByCameraConfig>,
+    pub cam_type: ResMut<'w, CurrentCameraType>,
 
     #[system_param(ignore)]
     _pd: std::marker::PhantomData<&'s ()>,
 }
 
 impl<'w, 's> CameraConfig<'w, 's> {
-    fn toggle(&mut self) {
+    pub fn toggle(&mut self) {
         trace!("Toggling cameras");
 
         match *self.cam_type {
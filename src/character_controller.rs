@@ -4,11 +4,15 @@ use bevy::{
     ecs::{query::QuerySingleError, schedule::ShouldRun},
     math::Vec3Swizzles,
     prelude::*,
-    utils::HashSet,
+    utils::{HashMap, HashSet},
 };
 use bevy_inspector_egui::{Inspectable, InspectorPlugin};
 use projekto_camera::orbit::{OrbitCamera, OrbitCameraConfig};
-use projekto_core::{chunk, landscape, voxel};
+use projekto_core::{
+    chunk, pathfinding,
+    query::{self, Aabb, KindLookup},
+    voxel,
+};
 use projekto_genesis::{ChunkKindRes, ChunkLightRes};
 
 use crate::world::{
@@ -25,10 +29,12 @@ impl Plugin for CharacterControllerPlugin {
             .init_resource::<ChunkMaterialImage>()
             .register_type::<ChunkMaterialImage>()
             .add_system(sync_material_image)
+            .add_system(toggle_free_flight)
             .add_system_set(
                 SystemSet::new()
                     .with_run_criteria(is_active)
                     .with_system(move_character)
+                    .with_system(walk_character)
                     .with_system(sync_rotation)
                     .with_system(update_character_position.label(CharacterPositionUpdate))
                     .with_system(
@@ -53,6 +59,23 @@ pub struct CharacterController;
 pub struct CharacterControllerConfig {
     pub active: bool,
     pub move_speed: f32,
+    /// Moves freely through voxels without gravity, using [`KeyCode::Space`] and
+    /// [`KeyCode::LControl`] to go up and down. Toggled with [`KeyCode::F10`].
+    pub free_flight: bool,
+    /// Half size of character bounding box, which is centered on character [`Transform`].
+    pub half_extents: Vec3,
+    /// Downwards acceleration, in voxels per second squared.
+    pub gravity: f32,
+    /// Upwards speed set when jumping, in voxels per second.
+    pub jump_speed: f32,
+    pub max_fall_speed: f32,
+    /// Max height of obstacles which are automatically stepped over while walking on ground.
+    pub step_height: f32,
+    /// Max number of walkable voxels flooded while looking for the room which has its roof cut
+    /// away, so open areas doesn't flood the whole landscape.
+    pub cutaway_max_area: usize,
+    /// Voxels with at least this natural light are outdoors, so they aren't part of any room.
+    pub cutaway_light_threshold: u8,
 }
 
 impl Default for CharacterControllerConfig {
@@ -60,10 +83,26 @@ impl Default for CharacterControllerConfig {
         Self {
             active: true,
             move_speed: 10.0,
+            free_flight: false,
+            half_extents: Vec3::new(0.25, 1.0, 0.25),
+            gravity: 30.0,
+            jump_speed: 9.0,
+            max_fall_speed: 50.0,
+            step_height: 1.0,
+            cutaway_max_area: 4096,
+            cutaway_light_threshold: voxel::Light::MAX_NATURAL_INTENSITY,
         }
     }
 }
 
+/// Kinematic state of a [`CharacterController`] while walking.
+#[derive(Component, Default, Debug, Reflect)]
+pub struct CharacterMotion {
+    pub velocity: Vec3,
+    /// Is the character standing on a solid voxel.
+    pub grounded: bool,
+}
+
 #[derive(Default, Debug, Reflect, Deref, DerefMut)]
 pub struct ChunkMaterialImage(pub Handle<Image>);
 
@@ -125,6 +164,10 @@ fn move_character(
     input: Res<Input<KeyCode>>,
     mut q: Query<&mut Transform, With<CharacterController>>,
 ) {
+    if !config.free_flight {
+        return;
+    }
+
     let input_vec = calc_input_vector(&input);
 
     if input_vec == Vec3::ZERO {
@@ -148,6 +191,197 @@ fn move_character(
     transform.translation += config.move_speed * time.delta_seconds() * move_vector;
 }
 
+fn toggle_free_flight(
+    input: Res<Input<KeyCode>>,
+    mut config: ResMut<CharacterControllerConfig>,
+    mut q: Query<&mut CharacterMotion>,
+) {
+    if !input.just_pressed(KeyCode::F10) {
+        return;
+    }
+
+    config.free_flight = !config.free_flight;
+    info!("Character free flight: {}", config.free_flight);
+
+    for mut motion in q.iter_mut() {
+        *motion = CharacterMotion::default();
+    }
+}
+
+fn walk_character(
+    config: Res<CharacterControllerConfig>,
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    kinds: Res<ChunkKindRes>,
+    mut q: Query<(&mut Transform, &mut CharacterMotion), With<CharacterController>>,
+) {
+    if config.free_flight {
+        return;
+    }
+
+    let (mut transform, mut motion) = match q.get_single_mut() {
+        Ok(t) => t,
+        Err(QuerySingleError::NoEntities(_)) => return,
+        Err(QuerySingleError::MultipleEntities(_)) => {
+            panic!("There can be only one character controlled entity.")
+        }
+    };
+
+    // Wait until terrain around the character is loaded, so it doesn't fall forever
+    let half = config.half_extents;
+    let loaded = [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+        .into_iter()
+        .map(|(x, z)| transform.translation + half * Vec3::new(x, 0.0, z))
+        .all(|corner| kinds.exists(chunk::to_local(corner)));
+
+    if !loaded {
+        return;
+    }
+
+    let input_vec = calc_input_vector(&input);
+    let wish = (transform.forward() * input_vec.z + transform.right() * input_vec.x)
+        * Vec3::new(1.0, 0.0, 1.0);
+    let wish = wish.normalize_or_zero() * config.move_speed;
+    let jump = input_vec.y > 0.0;
+
+    let mut position = transform.translation;
+    motion.update(
+        &mut position,
+        wish,
+        jump,
+        time.delta_seconds(),
+        &config,
+        &*kinds,
+    );
+
+    if position != transform.translation {
+        transform.translation = position;
+    }
+}
+
+/// Gap kept between character bounding box and voxels, so touching faces doesn't count as
+/// overlapping.
+const SKIN: f32 = 0.001;
+
+impl CharacterMotion {
+    /// Moves a character bounding box centered at `position` using horizontal velocity `wish`,
+    /// gravity and jump, colliding against solid voxels of `lookup`.
+    fn update(
+        &mut self,
+        position: &mut Vec3,
+        wish: Vec3,
+        jump: bool,
+        dt: f32,
+        config: &CharacterControllerConfig,
+        lookup: &impl KindLookup,
+    ) {
+        let half = config.half_extents;
+        let aabb = Aabb::from_center(*position, half);
+
+        if query::overlaps(&aabb, lookup) {
+            // Stuck inside terrain, which happens when a voxel is placed on the character or
+            // when it's spawned underground. Pop out the shortest way, preferring upwards.
+            if let Some(offset) = query::penetration_resolve(&aabb, lookup) {
+                *position += offset;
+            }
+            self.velocity = Vec3::ZERO;
+            self.grounded = false;
+            return;
+        }
+
+        if self.grounded && jump {
+            self.velocity.y = config.jump_speed;
+        }
+
+        self.velocity.x = wish.x;
+        self.velocity.z = wish.z;
+        self.velocity.y = (self.velocity.y - config.gravity * dt).max(-config.max_fall_speed);
+
+        let delta = self.velocity * dt;
+
+        let (dy, hit) = move_axis(*position, half, 1, delta.y, lookup);
+        position.y += dy;
+
+        if hit {
+            self.grounded = delta.y < 0.0;
+            self.velocity.y = 0.0;
+        } else {
+            self.grounded = false;
+        }
+
+        for axis in [0, 2] {
+            let (moved, hit) = move_axis(*position, half, axis, delta[axis], lookup);
+
+            if hit && self.grounded && config.step_height > 0.0 {
+                if let Some(stepped) = step_up(*position, half, axis, delta[axis], config, lookup) {
+                    *position = stepped;
+                    continue;
+                }
+            }
+
+            position[axis] += moved;
+        }
+    }
+}
+
+/// Tries to move over an obstacle by raising the character up to
+/// [`CharacterControllerConfig::step_height`], moving along the given axis and lowering it back
+/// on top of the obstacle.
+///
+/// **Returns** the new position or `None` if raising doesn't allow the character to move farther.
+fn step_up(
+    position: Vec3,
+    half: Vec3,
+    axis: usize,
+    delta: f32,
+    config: &CharacterControllerConfig,
+    lookup: &impl KindLookup,
+) -> Option<Vec3> {
+    let (blocked, _) = move_axis(position, half, axis, delta, lookup);
+
+    let (up, hit) = move_axis(position, half, 1, config.step_height, lookup);
+    if hit {
+        return None;
+    }
+
+    let mut raised = position + Vec3::Y * up;
+    let (moved, _) = move_axis(raised, half, axis, delta, lookup);
+
+    if moved.abs() <= blocked.abs() + SKIN {
+        return None;
+    }
+
+    raised[axis] += moved;
+
+    let (down, _) = move_axis(raised, half, 1, -config.step_height, lookup);
+    raised.y += down;
+
+    Some(raised)
+}
+
+/// Moves a bounding box centered at `center` along a single axis, stopping right before the first
+/// solid voxel.
+///
+/// **Returns** how much the box was moved and if it was blocked by any voxel.
+fn move_axis(
+    center: Vec3,
+    half: Vec3,
+    axis: usize,
+    delta: f32,
+    lookup: &impl KindLookup,
+) -> (f32, bool) {
+    let mut motion = Vec3::ZERO;
+    motion[axis] = delta;
+
+    match query::sweep_aabb(&Aabb::from_center(center, half), motion, lookup) {
+        Some(hit) => (
+            (delta.abs() * hit.time - SKIN).max(0.0) * delta.signum(),
+            true,
+        ),
+        None => (delta, false),
+    }
+}
+
 fn calc_input_vector(input: &Res<Input<KeyCode>>) -> Vec3 {
     let mut res = Vec3::ZERO;
 
@@ -207,13 +441,14 @@ fn update_character_position(
 
 enum ViewFrustumChain {
     DoNothing,
-    ClipMaterial(IVec3, Vec<Vec3>),
+    ClipMaterial(IVec3, HashMap<IVec2, i32>),
     RevertMaterial,
 }
 
 fn update_view_frustum(
     kinds: Res<ChunkKindRes>,
     lights: Res<ChunkLightRes>,
+    config: Res<CharacterControllerConfig>,
     position: Res<CharacterPosition>,
     q: Query<&Transform, With<CharacterController>>,
 ) -> ViewFrustumChain {
@@ -221,68 +456,92 @@ fn update_view_frustum(
         return ViewFrustumChain::DoNothing;
     }
 
-    // Use normalized direction to avoid diagonal voxels
-    let forward = projekto_core::math::to_dir(q.single().forward());
-    let front_world = (forward + **position).as_vec3();
+    let feet = projekto_core::math::floor(q.single().translation - Vec3::Y * config.half_extents.y);
 
-    let front = match kinds.get_at_world(front_world) {
-        Some(k) => k,
-        None => return ViewFrustumChain::DoNothing,
+    // Character may be jumping or falling, so look for the ground bellow it
+    let start = match (0..=pathfinding::MAX_DROP)
+        .map(|depth| feet - IVec3::Y * depth)
+        .find(|&voxel| pathfinding::is_walkable(&*kinds, voxel))
+    {
+        Some(voxel) => voxel,
+        None => return ViewFrustumChain::RevertMaterial,
     };
 
-    if front.is_opaque() {
-        // Facing a wall. Does nothing
-        return ViewFrustumChain::RevertMaterial;
-    }
-
-    let above_world = position.as_vec3() + Vec3::Y;
-    let above = match lights.get_at_world(above_world) {
-        Some(l) => l,
-        None => return ViewFrustumChain::DoNothing,
+    let natural_light = |voxel: IVec3| {
+        lights
+            .get_at_world(voxel.as_vec3())
+            .map_or(voxel::Light::MAX_NATURAL_INTENSITY, |light| {
+                light.get(voxel::LightTy::Natural)
+            })
     };
 
-    // TODO: Check many blocks using view frustum
-    if above.get(voxel::LightTy::Natural) == voxel::Light::MAX_NATURAL_INTENSITY {
+    match detect_room(
+        &*kinds,
+        natural_light,
+        start,
+        position.y - start.y,
+        config.cutaway_max_area,
+        config.cutaway_light_threshold,
+    ) {
+        Some(room) => ViewFrustumChain::ClipMaterial(**position, room),
         // We aren't inside any building. Skip
-        return ViewFrustumChain::RevertMaterial;
+        None => ViewFrustumChain::RevertMaterial,
     }
+}
 
-    let mut queue = VecDeque::new();
-    queue.push_back(front_world);
+/// Finds the room which contains the given walkable voxel, by flooding walkable voxels around it.
+/// Voxels which has natural light of at least `light_threshold` above them are outdoors, so they
+/// bound the room just like walls. Flooding also stops after `max_area` voxels, so open areas
+/// don't flood the whole landscape.
+///
+/// **Returns** the clip height of each column of the room, which is `height` voxels above the
+/// highest walkable voxel of that column, or `None` if `start` is outdoors.
+fn detect_room(
+    lookup: &impl KindLookup,
+    natural_light: impl Fn(IVec3) -> u8,
+    start: IVec3,
+    height: i32,
+    max_area: usize,
+    light_threshold: u8,
+) -> Option<HashMap<IVec2, i32>> {
+    let is_outdoors = |voxel: IVec3| natural_light(voxel + IVec3::Y) >= light_threshold;
+
+    if is_outdoors(start) {
+        return None;
+    }
 
-    let mut flooded_voxels = vec![];
+    let mut room = HashMap::<IVec2, i32>::default();
     let mut walked = HashSet::default();
+    let mut queue = VecDeque::new();
 
-    while let Some(voxel_world) = queue.pop_front() {
-        for side in voxel::SIDES {
-            // Let's work with X, Z axis only for now.
-            if matches!(side, voxel::Side::Up) {
-                continue;
-            }
-            let next_voxel = voxel_world + side.dir().as_vec3();
-
-            if walked.contains(&next_voxel.as_ivec3()) {
-                continue;
-            }
+    walked.insert(start);
+    queue.push_back(start);
 
-            let kind = match kinds.get_at_world(next_voxel) {
-                Some(k) => k,
-                None => continue,
-            };
+    while let Some(voxel) = queue.pop_front() {
+        let clip_height = room.entry(voxel.xz()).or_insert(i32::MIN);
+        *clip_height = (*clip_height).max(voxel.y + height);
 
-            if kind.is_opaque() {
-                continue;
+        for (next, _) in pathfinding::moves(lookup, voxel) {
+            if walked.len() >= max_area {
+                break;
             }
 
-            flooded_voxels.push(next_voxel);
-            queue.push_back(next_voxel);
-            walked.insert(next_voxel.as_ivec3());
+            if !is_outdoors(next) && walked.insert(next) {
+                queue.push_back(next);
+            }
         }
     }
 
-    info!("Flooded: {} voxels.", flooded_voxels.len());
+    Some(room)
+}
 
-    ViewFrustumChain::ClipMaterial(**position, flooded_voxels)
+/// Clip map applied on chunk material, so it's rebuilt only when something changes.
+#[derive(PartialEq)]
+struct ClipState {
+    char_chunk: IVec3,
+    clip_height: i32,
+    axis_size: u32,
+    room: HashMap<IVec2, i32>,
 }
 
 fn update_chunk_material(
@@ -292,7 +551,7 @@ fn update_chunk_material(
     mut materials: ResMut<Assets<ChunkMaterial>>,
     mut commands: Commands,
     mut debug_entity: Local<Option<Entity>>,
-    mut clipped: Local<bool>,
+    mut applied: Local<Option<ClipState>>,
 ) {
     if debug_entity.is_none() {
         *debug_entity = Some(
@@ -306,7 +565,7 @@ fn update_chunk_material(
     match voxels {
         ViewFrustumChain::DoNothing => (),
         ViewFrustumChain::RevertMaterial => {
-            if !*clipped {
+            if applied.take().is_none() {
                 return;
             }
 
@@ -326,63 +585,97 @@ fn update_chunk_material(
                 .entity(debug_entity.unwrap())
                 .insert(DrawVoxels::default());
         }
-        ViewFrustumChain::ClipMaterial(char_pos, voxels_world) => {
+        ViewFrustumChain::ClipMaterial(char_pos, room) => {
+            let axis_size = match materials.get(&chunk_material_handle) {
+                Some(material) => material.clip_map_axis_size,
+                None => return,
+            };
+
+            let state = ClipState {
+                char_chunk: chunk::to_local(char_pos.as_vec3()),
+                clip_height: char_pos.y,
+                axis_size,
+                room,
+            };
+
+            if applied.as_ref() == Some(&state) {
+                return;
+            }
+
             trace!("Clip!");
 
-            *clipped = true;
+            let voxels = state
+                .room
+                .iter()
+                .map(|(column, &y)| IVec3::new(column.x, y, column.y))
+                .collect::<Vec<_>>();
 
             commands.entity(debug_entity.unwrap()).insert(DrawVoxels {
                 color: "pink".into(),
-                voxels: voxels_world.iter().map(Vec3::as_ivec3).collect(),
-                offset: voxels_world[0],
+                offset: voxels.first().map_or(Vec3::ZERO, |v| v.as_vec3()),
+                voxels,
                 visible: false,
             });
 
             if let Some(material) = materials.get_mut(&chunk_material_handle) {
                 if let Some(image) = images.get_mut(&material.clip_map) {
-                    let char_chunk = chunk::to_local(char_pos.as_vec3());
-                    let left_bottom_chunk =
-                        char_chunk - IVec3::splat(landscape::HORIZONTAL_RADIUS as i32);
+                    let axis_size = axis_size as usize;
+
+                    // Clip map covers the full detail landscape, so it's size is always odd
+                    let radius = (axis_size / chunk::X_AXIS_SIZE) / 2;
+                    let left_bottom_chunk = state.char_chunk - IVec3::splat(radius as i32);
 
                     let clip_origin = chunk::to_world(left_bottom_chunk).xz();
-                    let clip_height = char_pos.y as f32;
 
-                    material.clip_height = clip_height;
+                    material.clip_height = state.clip_height as f32;
                     material.clip_map_origin = clip_origin;
                     material.show_back_faces = true;
 
-                    let len = image.data.len();
-                    let mut data = vec![0; len];
-
-                    for voxel in voxels_world {
-                        if voxel.y > clip_height {
-                            continue;
-                        }
-
-                        let coords = (voxel.xz() - clip_origin).as_ivec2();
-                        if is_on_landscape_bounds(coords) {
-                            let idx = pack_landscape_coords(coords);
-                            if voxel.y > data[idx] as f32 {
-                                data[idx] = voxel.y as u8;
-                            }
-                        }
-                    }
-
-                    image.data = data;
+                    image.data = build_clip_map(
+                        &state.room,
+                        clip_origin.as_ivec2(),
+                        state.clip_height,
+                        axis_size,
+                    );
                 }
             }
+
+            *applied = Some(state);
         }
     }
 }
 
-const X_AXIS: usize = landscape::HORIZONTAL_SIZE * chunk::Z_AXIS_SIZE;
+/// Builds clip map data of a landscape starting at `origin`, which holds the clip height of each
+/// room column. Columns above `clip_height` or outside landscape bounds are skipped.
+fn build_clip_map(
+    room: &HashMap<IVec2, i32>,
+    origin: IVec2,
+    clip_height: i32,
+    axis_size: usize,
+) -> Vec<u8> {
+    let mut data = vec![0; axis_size * axis_size];
+
+    for (&column, &height) in room {
+        if height > clip_height {
+            continue;
+        }
+
+        let coords = column - origin;
+        if is_on_landscape_bounds(coords, axis_size) {
+            data[pack_landscape_coords(coords, axis_size)] = height.clamp(0, u8::MAX as i32) as u8;
+        }
+    }
+
+    data
+}
 
-fn is_on_landscape_bounds(coords: IVec2) -> bool {
-    coords.x >= 0 && coords.x < X_AXIS as i32 && coords.y >= 0 && coords.y < X_AXIS as i32
+fn is_on_landscape_bounds(coords: IVec2, axis_size: usize) -> bool {
+    let axis_size = axis_size as i32;
+    coords.x >= 0 && coords.x < axis_size && coords.y >= 0 && coords.y < axis_size
 }
 
-fn pack_landscape_coords(coords: IVec2) -> usize {
-    coords.x as usize * X_AXIS + coords.y as usize
+fn pack_landscape_coords(coords: IVec2, axis_size: usize) -> usize {
+    coords.x as usize * axis_size + coords.y as usize
 }
 
 #[cfg(test)]
@@ -391,35 +684,263 @@ mod tests {
 
     #[test]
     fn pack_landscape_coords() {
-        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 0)), 0);
-        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 1)), 1);
-        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 2)), 2);
-        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 3)), 3);
+        const X_AXIS: usize = 144;
+
+        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 0), X_AXIS), 0);
+        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 1), X_AXIS), 1);
+        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 2), X_AXIS), 2);
+        assert_eq!(super::pack_landscape_coords(IVec2::new(0, 3), X_AXIS), 3);
 
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(1, 0)),
-            super::X_AXIS
+            super::pack_landscape_coords(IVec2::new(1, 0), X_AXIS),
+            X_AXIS
         );
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(2, 0)),
-            2 * super::X_AXIS
+            super::pack_landscape_coords(IVec2::new(2, 0), X_AXIS),
+            2 * X_AXIS
         );
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(3, 0)),
-            3 * super::X_AXIS
+            super::pack_landscape_coords(IVec2::new(3, 0), X_AXIS),
+            3 * X_AXIS
         );
 
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(1, 1)),
-            super::X_AXIS + 1
+            super::pack_landscape_coords(IVec2::new(1, 1), X_AXIS),
+            X_AXIS + 1
+        );
+        assert_eq!(
+            super::pack_landscape_coords(IVec2::new(2, 2), X_AXIS),
+            2 * X_AXIS + 2
         );
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(2, 2)),
-            2 * super::X_AXIS + 2
+            super::pack_landscape_coords(IVec2::new(3, 3), X_AXIS),
+            3 * X_AXIS + 3
+        );
+    }
+
+    /// Flat floor with its top at y = 1, plus the given voxels.
+    fn terrain(voxels: &[IVec3]) -> impl Fn(IVec3) -> Option<voxel::Kind> + '_ {
+        move |voxel: IVec3| Some(((voxel.y < 1 || voxels.contains(&voxel)) as u16).into())
+    }
+
+    fn simulate(
+        motion: &mut CharacterMotion,
+        position: &mut Vec3,
+        wish: Vec3,
+        jump: bool,
+        frames: usize,
+        lookup: impl KindLookup,
+    ) {
+        let config = CharacterControllerConfig::default();
+
+        for _ in 0..frames {
+            motion.update(position, wish, jump, 1.0 / 60.0, &config, &lookup);
+        }
+    }
+
+    #[test]
+    fn move_axis() {
+        let half = Vec3::new(0.25, 1.0, 0.25);
+        let lookup = terrain(&[IVec3::new(3, 1, 0)]);
+
+        let (moved, hit) = super::move_axis(Vec3::new(0.5, 3.0, 0.5), half, 1, -5.0, &lookup);
+        assert!(hit);
+        assert!((moved + 1.0).abs() < 0.01, "Should stop on floor top");
+
+        let (moved, hit) = super::move_axis(Vec3::new(0.5, 2.01, 0.5), half, 0, 5.0, &lookup);
+        assert!(hit);
+        assert!((moved - 2.25).abs() < 0.01, "Should stop before the block");
+
+        let (moved, hit) = super::move_axis(Vec3::new(0.5, 2.01, 0.5), half, 0, -5.0, &lookup);
+        assert!(!hit);
+        assert_eq!(moved, -5.0);
+    }
+
+    #[test]
+    fn fall_to_ground() {
+        let mut motion = CharacterMotion::default();
+        let mut position = Vec3::new(0.5, 10.0, 0.5);
+
+        simulate(
+            &mut motion,
+            &mut position,
+            Vec3::ZERO,
+            false,
+            120,
+            terrain(&[]),
+        );
+
+        assert!(motion.grounded);
+        assert!((position.y - 2.0).abs() < 0.01, "Feet should rest on y = 1");
+        assert_eq!(motion.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn jump() {
+        let mut motion = CharacterMotion::default();
+        let mut position = Vec3::new(0.5, 2.001, 0.5);
+        let mut highest = position.y;
+
+        simulate(
+            &mut motion,
+            &mut position,
+            Vec3::ZERO,
+            false,
+            1,
+            terrain(&[]),
         );
+        assert!(motion.grounded);
+
+        simulate(
+            &mut motion,
+            &mut position,
+            Vec3::ZERO,
+            true,
+            1,
+            terrain(&[]),
+        );
+        assert!(!motion.grounded);
+
+        for _ in 0..120 {
+            simulate(
+                &mut motion,
+                &mut position,
+                Vec3::ZERO,
+                false,
+                1,
+                terrain(&[]),
+            );
+            highest = highest.max(position.y);
+        }
+
+        assert!(highest > 3.0, "Should jump higher than one block");
+        assert!(motion.grounded, "Should land back");
+    }
+
+    #[test]
+    fn step_up() {
+        let mut motion = CharacterMotion::default();
+        let mut position = Vec3::new(0.5, 2.001, 0.5);
+        // A single block step followed by a wall two blocks higher than the step
+        let blocks = [
+            IVec3::new(2, 1, 0),
+            IVec3::new(3, 1, 0),
+            IVec3::new(3, 2, 0),
+            IVec3::new(3, 3, 0),
+        ];
+
+        simulate(
+            &mut motion,
+            &mut position,
+            Vec3::X * 5.0,
+            false,
+            60,
+            terrain(&blocks),
+        );
+
+        assert!(
+            (position.y - 3.0).abs() < 0.01,
+            "Should step over a single block"
+        );
+        assert!(
+            position.x < 2.76,
+            "Shouldn't step over two blocks, got {}",
+            position.x
+        );
+        assert!(motion.grounded);
+    }
+
+    #[test]
+    fn pop_out_of_terrain() {
+        let mut motion = CharacterMotion::default();
+        let mut position = Vec3::new(0.5, 1.5, 0.5);
+
+        simulate(
+            &mut motion,
+            &mut position,
+            Vec3::ZERO,
+            false,
+            60,
+            terrain(&[]),
+        );
+
+        assert!((position.y - 2.0).abs() < 0.01);
+        assert!(motion.grounded);
+    }
+
+    /// Room with walls 3 voxels high around a 3x3 floor, from (1, 1) until (3, 3), and a door at
+    /// (2, 0). There is a step at (3, 1, 3).
+    fn room() -> Vec<IVec3> {
+        let mut voxels = vec![IVec3::new(3, 1, 3)];
+
+        for y in 1..=3 {
+            for i in 0..=4 {
+                voxels.extend([
+                    IVec3::new(0, y, i),
+                    IVec3::new(4, y, i),
+                    IVec3::new(i, y, 4),
+                ]);
+
+                if i != 2 || y > 2 {
+                    voxels.push(IVec3::new(i, y, 0));
+                }
+            }
+        }
+
+        voxels
+    }
+
+    fn indoors_light(voxel: IVec3) -> u8 {
+        if (1..=3).contains(&voxel.x) && (1..=3).contains(&voxel.z) {
+            0
+        } else {
+            voxel::Light::MAX_NATURAL_INTENSITY
+        }
+    }
+
+    #[test]
+    fn detect_room() {
+        let voxels = room();
+        let lookup = terrain(&voxels);
+        let max = voxel::Light::MAX_NATURAL_INTENSITY;
+
+        let room = super::detect_room(&lookup, indoors_light, IVec3::new(2, 1, 2), 1, 100, max)
+            .expect("Start voxel is indoors");
+
+        assert_eq!(room.len(), 9, "Door and outside should be excluded");
+        assert_eq!(room[&IVec2::new(1, 1)], 2);
+        assert_eq!(room[&IVec2::new(3, 3)], 3, "Step raises clip height");
+        assert!(!room.contains_key(&IVec2::new(2, 0)));
+
+        assert_eq!(
+            super::detect_room(&lookup, indoors_light, IVec3::new(10, 1, 10), 1, 100, max),
+            None
+        );
+
+        // Without natural light, only max area stops flooding
+        let room = super::detect_room(&lookup, |_| 0, IVec3::new(2, 1, 2), 1, 30, max).unwrap();
+        assert!(room.len() > 9 && room.len() <= 30);
+    }
+
+    #[test]
+    fn build_clip_map() {
+        const AXIS: usize = 4;
+
+        let room = [
+            (IVec2::new(1, 1), 2),
+            (IVec2::new(2, 1), 5),
+            (IVec2::new(-1, 0), 2),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+        let data = super::build_clip_map(&room, IVec2::ZERO, 3, AXIS);
+
+        assert_eq!(data.len(), AXIS * AXIS);
         assert_eq!(
-            super::pack_landscape_coords(IVec2::new(3, 3)),
-            3 * super::X_AXIS + 3
+            data[super::pack_landscape_coords(IVec2::new(1, 1), AXIS)],
+            2
         );
+        assert_eq!(data.iter().filter(|&&h| h > 0).count(), 1);
     }
 }
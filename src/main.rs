@@ -10,7 +10,7 @@ use bevy::{
 
 mod debug;
 use camera_controller::CameraControllerPlugin;
-use character_controller::{CharacterController, CharacterControllerPlugin};
+use character_controller::{CharacterController, CharacterControllerPlugin, CharacterMotion};
 use debug::DebugPlugin;
 
 mod world;
@@ -27,6 +27,9 @@ use ui::UiPlugin;
 mod camera_controller;
 mod character_controller;
 
+mod persistence;
+use persistence::PersistencePlugin;
+
 fn main() {
     // env_logger::init();
 
@@ -50,6 +53,7 @@ fn main() {
     .add_plugin(UiPlugin)
     .add_plugin(CameraControllerPlugin)
     .add_plugin(CharacterControllerPlugin)
+    .add_plugin(PersistencePlugin)
     // .add_system_to_stage(CoreStage::PreUpdate, limit_fps)
     .add_startup_system(setup);
 
@@ -99,6 +103,7 @@ fn setup(
         .insert(LandscapeCenter)
         .insert(OrbitCameraTarget)
         .insert(CharacterController)
+        .insert(CharacterMotion::default())
         .with_children(|p| {
             p.spawn_bundle(PbrBundle {
                 mesh: meshes.add(Mesh::from(shape::Box {
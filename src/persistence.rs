@@ -0,0 +1,275 @@
+use std::path::Path;
+
+use bevy::{app::AppExit, prelude::*};
+use projekto_camera::fly_by::FlyByCamera;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera_controller::{CameraConfig, CurrentCameraType},
+    character_controller::CharacterController,
+    world::WorldTime,
+};
+
+/// Save file of player and world state. Kept next to chunk cache, since both belongs to the same
+/// world.
+const SAVE_PATH: &str = "cache/save.ron";
+
+/// Restores player and world state on startup and saves it back when the app exits.
+///
+/// Chunks are saved by genesis on their own, so only state which isn't part of any chunk is kept.
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system_to_stage(StartupStage::PostStartup, restore_state)
+            .add_system_to_stage(CoreStage::Last, save_on_exit);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct SavedTransform {
+    translation: Vec3,
+    rotation: Quat,
+}
+
+impl From<&Transform> for SavedTransform {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation,
+            rotation: transform.rotation,
+        }
+    }
+}
+
+impl SavedTransform {
+    fn apply(&self, transform: &mut Transform) {
+        transform.translation = self.translation;
+        transform.rotation = self.rotation;
+    }
+}
+
+/// Parameters of [`projekto_camera::orbit::OrbitCameraConfig`] which are changed while playing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct OrbitState {
+    radial_distance: f32,
+    polar_angle: f32,
+    azimuthal_angle: f32,
+    key_rotate_speed: f32,
+    key_zoom_speed: f32,
+    mouse_rotate_speed: f32,
+    mouse_zoom_speed: f32,
+}
+
+/// Parameters of [`projekto_camera::fly_by::FlyByCameraConfig`] which are changed while playing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct FlyByState {
+    move_speed: f32,
+    move_speed_boost: f32,
+    rotate_speed: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SaveState {
+    character: SavedTransform,
+    camera: SavedTransform,
+    camera_type: CurrentCameraType,
+    orbit: OrbitState,
+    fly_by: FlyByState,
+    world_time: f64,
+}
+
+impl SaveState {
+    fn capture(
+        character: &Transform,
+        camera: &Transform,
+        config: &CameraConfig,
+        world_time: &WorldTime,
+    ) -> Self {
+        let orbit = &config.orbit;
+        let flyby = &config.flyby;
+
+        Self {
+            character: character.into(),
+            camera: camera.into(),
+            camera_type: *config.cam_type,
+            orbit: OrbitState {
+                radial_distance: orbit.radial_distance,
+                polar_angle: orbit.polar_angle,
+                azimuthal_angle: orbit.azimuthal_angle,
+                key_rotate_speed: orbit.key_rotate_speed,
+                key_zoom_speed: orbit.key_zoom_speed,
+                mouse_rotate_speed: orbit.mouse_rotate_speed,
+                mouse_zoom_speed: orbit.mouse_zoom_speed,
+            },
+            fly_by: FlyByState {
+                move_speed: flyby.move_speed,
+                move_speed_boost: flyby.move_speed_boost,
+                rotate_speed: flyby.rotate_speed,
+            },
+            world_time: world_time.elapsed,
+        }
+    }
+
+    fn apply(&self, config: &mut CameraConfig) {
+        let orbit = &mut config.orbit;
+        orbit.radial_distance = self
+            .orbit
+            .radial_distance
+            .clamp(orbit.min_distance, orbit.max_distance);
+        orbit.polar_angle = self
+            .orbit
+            .polar_angle
+            .clamp(orbit.min_polar_angle, orbit.max_polar_angle);
+        orbit.azimuthal_angle = self.orbit.azimuthal_angle;
+        orbit.key_rotate_speed = self.orbit.key_rotate_speed;
+        orbit.key_zoom_speed = self.orbit.key_zoom_speed;
+        orbit.mouse_rotate_speed = self.orbit.mouse_rotate_speed;
+        orbit.mouse_zoom_speed = self.orbit.mouse_zoom_speed;
+
+        let flyby = &mut config.flyby;
+        flyby.move_speed = self.fly_by.move_speed;
+        flyby.move_speed_boost = self.fly_by.move_speed_boost;
+        flyby.rotate_speed = self.fly_by.rotate_speed;
+
+        if *config.cam_type != self.camera_type {
+            config.toggle();
+        }
+    }
+}
+
+/// **Returns** the save state stored at the given path or a message describing why it failed.
+fn load(path: &Path) -> Result<SaveState, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to read file: {err}"))?;
+
+    ron::from_str(&content).map_err(|err| format!("Failed to parse: {err}"))
+}
+
+/// Saves the given state at the given path, creating parent directories if needed.
+fn save(path: &Path, state: &SaveState) -> Result<(), String> {
+    let content = ron::ser::to_string_pretty(state, Default::default())
+        .map_err(|err| format!("Failed to serialize: {err}"))?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("Failed to create directory: {err}"))?;
+    }
+
+    std::fs::write(path, content).map_err(|err| format!("Failed to write file: {err}"))
+}
+
+fn restore_state(
+    mut character: Query<&mut Transform, With<CharacterController>>,
+    mut camera: Query<&mut Transform, (With<FlyByCamera>, Without<CharacterController>)>,
+    mut config: CameraConfig,
+    mut world_time: ResMut<WorldTime>,
+) {
+    let path = Path::new(SAVE_PATH);
+
+    if !path.exists() {
+        return;
+    }
+
+    let state = match load(path) {
+        Ok(state) => state,
+        Err(err) => {
+            error!("Failed to load save {}: {}", SAVE_PATH, err);
+            return;
+        }
+    };
+
+    debug!("Restoring save {:?}", state);
+
+    if let Ok(mut transform) = character.get_single_mut() {
+        state.character.apply(&mut transform);
+    }
+
+    if let Ok(mut transform) = camera.get_single_mut() {
+        state.camera.apply(&mut transform);
+    }
+
+    state.apply(&mut config);
+    world_time.elapsed = state.world_time;
+}
+
+fn save_on_exit(
+    mut exit_reader: EventReader<AppExit>,
+    character: Query<&Transform, With<CharacterController>>,
+    camera: Query<&Transform, With<FlyByCamera>>,
+    config: CameraConfig,
+    world_time: Res<WorldTime>,
+) {
+    if exit_reader.iter().last().is_none() {
+        return;
+    }
+
+    let (character, camera) = match (character.get_single(), camera.get_single()) {
+        (Ok(character), Ok(camera)) => (character, camera),
+        _ => {
+            warn!("Unable to find character or camera. Skipping save");
+            return;
+        }
+    };
+
+    let state = SaveState::capture(character, camera, &config, &world_time);
+
+    match save(Path::new(SAVE_PATH), &state) {
+        Ok(()) => info!("Saved {}", SAVE_PATH),
+        Err(err) => error!("Failed to save {}: {}", SAVE_PATH, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> SaveState {
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0).looking_at(Vec3::ONE, Vec3::Y);
+
+        SaveState {
+            character: (&transform).into(),
+            camera: (&Transform::from_xyz(-1.5, 20.0, 0.25)).into(),
+            camera_type: CurrentCameraType::FlyBy,
+            orbit: OrbitState {
+                radial_distance: 5.0,
+                polar_angle: 0.5,
+                azimuthal_angle: -1.0,
+                key_rotate_speed: 1.0,
+                key_zoom_speed: 2.0,
+                mouse_rotate_speed: 3.0,
+                mouse_zoom_speed: 4.0,
+            },
+            fly_by: FlyByState {
+                move_speed: 10.0,
+                move_speed_boost: 20.0,
+                rotate_speed: 0.1,
+            },
+            world_time: 1234.5,
+        }
+    }
+
+    #[test]
+    fn save_load() {
+        let path = std::env::temp_dir()
+            .join("projekto_persistence_test")
+            .join("save.ron");
+        let state = state();
+
+        super::save(&path, &state).unwrap();
+
+        assert_eq!(super::load(&path).unwrap(), state);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn load_invalid() {
+        let path = std::env::temp_dir().join("projekto_persistence_invalid.ron");
+        std::fs::write(&path, "not a save").unwrap();
+
+        assert!(super::load(&path).is_err());
+        assert!(super::load(&path.with_extension("missing")).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,10 +1,13 @@
 use bevy::prelude::*;
 use bevy_ecss::StyleSheet;
+use projekto_core::landscape::{ViewDistance, MAX_VIEW_DISTANCE};
 use projekto_widgets::{
     console::{CommandIssued, Console, ConsoleAction},
     widget::{ToStringLabel, Widget},
 };
 
+use crate::world::WorldTime;
+
 pub struct ConsolePlugin;
 
 impl Plugin for ConsolePlugin {
@@ -30,10 +33,141 @@ fn toggle_console(input: Res<Input<KeyCode>>, mut writer: EventWriter<ConsoleAct
     }
 }
 
-fn process_command(mut cmds: EventReader<CommandIssued>, mut q_sheet: Query<&mut StyleSheet>) {
-    for CommandIssued(entity, _cmd) in cmds.iter() {
+fn process_command(
+    mut cmds: EventReader<CommandIssued>,
+    mut q_sheet: Query<&mut StyleSheet>,
+    mut world_time: ResMut<WorldTime>,
+    mut view_distance: ResMut<ViewDistance>,
+    mut writer: EventWriter<ConsoleAction>,
+) {
+    for CommandIssued(entity, cmd) in cmds.iter() {
         if let Ok(mut sheet) = q_sheet.get_mut(*entity) {
             sheet.refresh();
         }
+
+        let mut args = cmd.split_whitespace();
+
+        let reply = match (args.next(), args.next()) {
+            (Some("time"), None) => format_hours(world_time.hours()),
+            (Some("time"), Some(arg)) => match parse_hours(arg) {
+                Ok(hours) => {
+                    world_time.set_hours(hours);
+                    format_hours(world_time.hours())
+                }
+                Err(err) => err,
+            },
+            (Some("view_distance"), None) => format_view_distance(*view_distance),
+            (Some("view_distance"), Some(arg)) => match parse_view_distance(arg) {
+                Ok(distance) => {
+                    *view_distance = distance;
+                    format_view_distance(distance)
+                }
+                Err(err) => err,
+            },
+            _ => continue,
+        };
+
+        writer.send(ConsoleAction::AddEntries(vec![reply]));
+    }
+}
+
+/// Parses a time of day as decimal hours (`13.5`), `HH:MM` (`13:30`) or one of `dawn`, `noon`,
+/// `dusk` and `midnight`.
+///
+/// **Returns** the hour of the day, from `0.0` until `24.0`.
+fn parse_hours(arg: &str) -> Result<f32, String> {
+    let hours = match arg {
+        "dawn" => 6.0,
+        "noon" => 12.0,
+        "dusk" => 18.0,
+        "midnight" => 0.0,
+        _ => {
+            if let Some((h, m)) = arg.split_once(':') {
+                let h = h
+                    .parse::<u32>()
+                    .map_err(|err| format!("Failed to parse hour {h}: {err}"))?;
+                let m = m
+                    .parse::<u32>()
+                    .map_err(|err| format!("Failed to parse minute {m}: {err}"))?;
+
+                if m >= 60 {
+                    return Err(format!("Invalid minute {m}. It should be less than 60"));
+                }
+
+                h as f32 + m as f32 / 60.0
+            } else {
+                arg.parse::<f32>()
+                    .map_err(|err| format!("Failed to parse time {arg}: {err}"))?
+            }
+        }
+    };
+
+    if (0.0..24.0).contains(&hours) {
+        Ok(hours)
+    } else {
+        Err(format!(
+            "Invalid time {arg}. It should be between 00:00 and 23:59"
+        ))
+    }
+}
+
+fn format_hours(hours: f32) -> String {
+    let minutes = (hours * 60.0) as u32 % (24 * 60);
+    format!("Time is {:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Parses a view distance, in chunks, up to [`MAX_VIEW_DISTANCE`].
+fn parse_view_distance(arg: &str) -> Result<ViewDistance, String> {
+    let distance = arg
+        .parse::<u32>()
+        .map_err(|err| format!("Failed to parse view distance {arg}: {err}"))?;
+
+    if distance <= MAX_VIEW_DISTANCE {
+        Ok(ViewDistance::new(distance))
+    } else {
+        Err(format!(
+            "Invalid view distance {arg}. It should be at most {MAX_VIEW_DISTANCE}"
+        ))
+    }
+}
+
+fn format_view_distance(distance: ViewDistance) -> String {
+    format!("View distance is {}", distance.get())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_hours() {
+        assert_eq!(super::parse_hours("noon"), Ok(12.0));
+        assert_eq!(super::parse_hours("midnight"), Ok(0.0));
+        assert_eq!(super::parse_hours("13.5"), Ok(13.5));
+        assert_eq!(super::parse_hours("06:45"), Ok(6.75));
+
+        assert!(super::parse_hours("24").is_err());
+        assert!(super::parse_hours("12:60").is_err());
+        assert!(super::parse_hours("-1").is_err());
+        assert!(super::parse_hours("later").is_err());
+    }
+
+    #[test]
+    fn format_hours() {
+        assert_eq!(super::format_hours(0.0), "Time is 00:00");
+        assert_eq!(super::format_hours(13.5), "Time is 13:30");
+        assert_eq!(super::format_hours(23.99), "Time is 23:59");
+    }
+
+    #[test]
+    fn parse_view_distance() {
+        assert_eq!(super::parse_view_distance("8").map(|d| d.get()), Ok(8));
+        assert_eq!(super::parse_view_distance("0").map(|d| d.get()), Ok(0));
+        assert_eq!(
+            super::parse_view_distance("32").map(|d| d.get()),
+            Ok(super::MAX_VIEW_DISTANCE)
+        );
+
+        assert!(super::parse_view_distance("33").is_err());
+        assert!(super::parse_view_distance("-1").is_err());
+        assert!(super::parse_view_distance("far").is_err());
     }
 }
@@ -2,11 +2,11 @@ use std::collections::HashMap;
 
 use bevy::{
     prelude::*,
-    render::mesh::{Indices, PrimitiveTopology},
+    render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
 };
 use itertools::Itertools;
 use projekto_camera::fly_by::{self, FlyByCamera};
-use projekto_genesis::{ChunkKindRes, GenesisCommandBuffer};
+use projekto_genesis::{ChunkKindRes, VoxMapping, VoxModel, VoxelEditor};
 
 use crate::world::rendering::*;
 use projekto_core::*;
@@ -31,11 +31,15 @@ impl Plugin for WireframeDebugPlugin {
                     .with_run_criteria(fly_by::is_active)
                     .with_system(do_raycast)
                     .with_system(remove_voxel)
-                    .with_system(add_voxel),
+                    .with_system(add_voxel)
+                    .with_system(import_vox)
+                    .with_system(export_vox),
             )
             .add_system(toggle_mesh_wireframe)
             .add_system(toggle_chunk_voxels_wireframe)
             .add_system(toggle_landscape_pause)
+            .add_system(undo_redo_edits)
+            .add_system(export_heightmap)
             .add_system(draw_voxels)
             .add_system(draw_raycast)
             .add_system(check_raycast_intersections);
@@ -86,6 +90,142 @@ fn toggle_landscape_pause(keyboard: Res<Input<KeyCode>>, mut config: ResMut<Land
     config.paused = !config.paused;
 }
 
+fn undo_redo_edits(keyboard: Res<Input<KeyCode>>, mut editor: VoxelEditor) {
+    if !keyboard.pressed(KeyCode::LControl) {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Z) {
+        if let Some(id) = editor.undo() {
+            debug!("Undone edit transaction {}", id);
+        }
+    } else if keyboard.just_pressed(KeyCode::Y) {
+        if let Some(id) = editor.redo() {
+            debug!("Redone edit transaction {}", id);
+        }
+    }
+}
+
+const HEIGHTMAP_EXPORT_PATH: &str = "cache/heightmap.png";
+
+/// Exports the surface height of all loaded chunks as a 16-bit grayscale PNG.
+fn export_heightmap(keyboard: Res<Input<KeyCode>>, kinds: Res<ChunkKindRes>) {
+    if !keyboard.just_pressed(KeyCode::F6) {
+        return;
+    }
+
+    let locals = kinds.list_chunks();
+
+    let (begin, end) = match locals.first() {
+        Some(&first) => locals.iter().fold((first, first), |(begin, end), &local| {
+            (begin.min(local), end.max(local))
+        }),
+        None => return,
+    };
+
+    let heightmap = shaping::Heightmap::from_chunks(begin, end, |local| kinds.get(local));
+
+    match heightmap
+        .to_png()
+        .and_then(|png| std::fs::write(HEIGHTMAP_EXPORT_PATH, png).map_err(|err| err.to_string()))
+    {
+        Ok(_) => info!(
+            "Exported heightmap {:?} to {}",
+            heightmap, HEIGHTMAP_EXPORT_PATH
+        ),
+        Err(err) => error!("Failed to export heightmap: {}", err),
+    }
+}
+
+const VOX_IMPORT_PATH: &str = "cache/import.vox";
+const VOX_EXPORT_PATH: &str = "cache/export.vox";
+
+/// Half size, on X and Z, and height of the region exported to [`VOX_EXPORT_PATH`].
+const VOX_EXPORT_EXTENT: IVec3 = IVec3::new(8, 32, 8);
+
+/// **Returns** the world position of the first non-empty voxel the camera is looking at.
+fn camera_hit(transform: &Transform, kinds: &ChunkKindRes) -> Option<IVec3> {
+    let origin = transform.translation;
+    let dir = transform.rotation.mul_vec3(Vec3::Z).normalize() * -1.0;
+    let range = 100.0;
+
+    query::raycast(origin, dir, range)
+        .into_iter()
+        .flat_map(|(_, voxel_hits)| voxel_hits)
+        .map(|hit| hit.position)
+        .find(|&w| kinds.get_at_world(w).is_some_and(|k| !k.is_none()))
+        .map(|w| w.floor().as_ivec3())
+}
+
+/// Places the MagicaVoxel model on [`VOX_IMPORT_PATH`] on top of the voxel the camera is looking
+/// at. Palette colors are mapped to kinds using `voxels/vox_mapping.ron`.
+fn import_vox(
+    q_cam: Query<&Transform, With<FlyByCamera>>,
+    keyboard: Res<Input<KeyCode>>,
+    mut editor: VoxelEditor,
+) {
+    if !keyboard.just_pressed(KeyCode::F7) {
+        return;
+    }
+
+    let hit = match q_cam.get_single() {
+        Ok(transform) => camera_hit(transform, editor.kinds()),
+        Err(_) => None,
+    };
+
+    if let Some(world) = hit {
+        let mapping_path = format!("{}/voxels/vox_mapping.ron", env!("ASSETS_PATH"));
+
+        let model = std::fs::read(VOX_IMPORT_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| VoxModel::parse(&bytes))
+            .and_then(|model| VoxMapping::load(mapping_path).map(|mapping| (model, mapping)));
+
+        match model {
+            Ok((model, mapping)) => {
+                let edit = model.to_world_edit(&mapping, world + IVec3::Y);
+                debug!("Importing {} voxels at {}", edit.len(), world);
+                editor.apply(edit);
+            }
+            Err(err) => error!("Failed to import {}: {}", VOX_IMPORT_PATH, err),
+        }
+    }
+}
+
+/// Exports the region around the voxel the camera is looking at as a MagicaVoxel model on
+/// [`VOX_EXPORT_PATH`].
+fn export_vox(
+    q_cam: Query<&Transform, With<FlyByCamera>>,
+    keyboard: Res<Input<KeyCode>>,
+    kinds: Res<ChunkKindRes>,
+) {
+    if !keyboard.just_pressed(KeyCode::F8) {
+        return;
+    }
+
+    let hit = match q_cam.get_single() {
+        Ok(transform) => camera_hit(transform, &kinds),
+        Err(_) => None,
+    };
+
+    if let Some(world) = hit {
+        let begin = world - VOX_EXPORT_EXTENT * IVec3::new(1, 0, 1);
+        let end = world + VOX_EXPORT_EXTENT - IVec3::ONE;
+
+        let result = VoxModel::from_region(begin, end, |w| {
+            kinds.get_at_world(w.as_vec3()).unwrap_or_default()
+        })
+        .and_then(|model| {
+            std::fs::write(VOX_EXPORT_PATH, model.to_bytes()).map_err(|err| err.to_string())
+        });
+
+        match result {
+            Ok(_) => info!("Exported region {} {} to {}", begin, end, VOX_EXPORT_PATH),
+            Err(err) => error!("Failed to export {}: {}", VOX_EXPORT_PATH, err),
+        }
+    }
+}
+
 #[derive(Component)]
 struct WireframeVoxels;
 
@@ -188,7 +328,21 @@ fn toggle_mesh_wireframe(
             let mut wireframe_mesh = Mesh::new(PrimitiveTopology::LineList);
 
             if let Some(mesh_asset) = meshes.get_mut(mesh) {
-                let vertices = mesh_asset.attribute(Mesh::ATTRIBUTE_POSITION).unwrap();
+                let vertices = match mesh_asset.attribute(ChunkMaterial::ATTRIBUTE_POSITION) {
+                    Some(VertexAttributeValues::Uint32(packed)) => packed
+                        .iter()
+                        .map(|&position| {
+                            voxel::VoxelVertex {
+                                position,
+                                ..Default::default()
+                            }
+                            .position()
+                            .as_vec3()
+                            .to_array()
+                        })
+                        .collect_vec(),
+                    _ => continue,
+                };
 
                 wireframe_mesh.set_indices(Some(Indices::U32(compute_wireframe_indices(
                     vertices.len(),
@@ -410,8 +564,7 @@ fn draw_raycast(
 fn remove_voxel(
     q_cam: Query<&Transform, With<FlyByCamera>>,
     mouse_input: Res<Input<MouseButton>>,
-    mut cmd_buffer: ResMut<GenesisCommandBuffer>,
-    kinds: Res<ChunkKindRes>,
+    mut editor: VoxelEditor,
 ) {
     if !mouse_input.just_pressed(MouseButton::Right) {
         return;
@@ -426,14 +579,14 @@ fn remove_voxel(
             .into_iter()
             .flat_map(|(_, voxel_hits)| voxel_hits)
             .map(|hit| hit.position)
-            .find(|&w| kinds.get_at_world(w).is_some_and(|k| !k.is_none()));
+            .find(|&w| editor.kinds().get_at_world(w).is_some_and(|k| !k.is_none()));
 
         if let Some(world) = world_hit {
             let local = chunk::to_local(world);
             let voxel = voxel::to_local(world);
 
             debug!("Hit voxel at {:?} {:?}", local, voxel);
-            cmd_buffer.update(local, vec![(voxel, voxel::Kind::none())]);
+            editor.update(local, vec![(voxel, voxel::Kind::none())]);
         }
     }
 }
@@ -441,8 +594,7 @@ fn remove_voxel(
 fn add_voxel(
     q_cam: Query<&Transform, With<FlyByCamera>>,
     mouse_input: Res<Input<MouseButton>>,
-    mut cmd_buffer: ResMut<GenesisCommandBuffer>,
-    kinds: Res<ChunkKindRes>,
+    mut editor: VoxelEditor,
 ) {
     if !mouse_input.just_pressed(MouseButton::Right) {
         return;
@@ -457,14 +609,14 @@ fn add_voxel(
             .into_iter()
             .flat_map(|(_, voxel_hits)| voxel_hits)
             .map(|hit| hit.position)
-            .find(|&w| kinds.get_at_world(w).is_some_and(|k| !k.is_none()));
+            .find(|&w| editor.kinds().get_at_world(w).is_some_and(|k| !k.is_none()));
 
         if let Some(world) = world_hit {
             let local = chunk::to_local(world);
             let voxel = voxel::to_local(world);
 
             debug!("Hit voxel at {:?} {:?}", local, voxel);
-            cmd_buffer.update(local, vec![(voxel, voxel::Kind::id(4))]);
+            editor.update(local, vec![(voxel, voxel::Kind::id(4))]);
         }
     }
 }
@@ -1,5 +1,5 @@
 use bevy::{prelude::*, reflect::TypeUuid};
-use projekto_core::voxel;
+use projekto_core::{landscape::ViewDistance, voxel};
 
 pub(crate) mod debug;
 pub mod rendering;
@@ -10,13 +10,73 @@ pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(terraformation::TerraformationPlugin)
+        app.init_resource::<ViewDistance>()
+            .init_resource::<WorldTime>()
+            .add_plugin(terraformation::TerraformationPlugin)
             .add_plugin(rendering::PipelinePlugin)
             .add_plugin(debug::WireframeDebugPlugin)
-            .add_startup_system_to_stage(StartupStage::PreStartup, setup_resources);
+            .add_startup_system_to_stage(StartupStage::PreStartup, setup_resources)
+            .add_system_to_stage(CoreStage::First, advance_world_time);
     }
 }
 
+/// Length of a full in-game day, in seconds.
+pub const DAY_LENGTH: f64 = 20.0 * 60.0;
+
+/// Hour of the day when the world is created, so new worlds doesn't start at night.
+const WORLD_START_HOUR: f64 = 8.0;
+
+/// Sky brightness at midnight. Surface is still barely visible without artificial light.
+const NIGHT_SKY_BRIGHTNESS: f32 = 0.15;
+
+/// How far the sun is below the horizon when sky brightness reaches its minimum, from `-1.0`
+/// (midnight) to `1.0` (noon). Sky brightness raises smoothly until the sun is as far above it.
+const TWILIGHT: f32 = 0.2;
+
+/// In-game time, in seconds since the world was created. It's advanced by real time and persisted
+/// together with the world.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct WorldTime {
+    pub elapsed: f64,
+}
+
+impl WorldTime {
+    /// **Returns** the hour of the day, from `0.0` (midnight) until `24.0`.
+    pub fn hours(&self) -> f32 {
+        self.day_hours() as f32
+    }
+
+    fn day_hours(&self) -> f64 {
+        (self.elapsed / DAY_LENGTH * 24.0 + WORLD_START_HOUR).rem_euclid(24.0)
+    }
+
+    /// Moves time forward until the next time the day reaches the given hour. Time never goes
+    /// backwards, so chunk and world state which depends on elapsed time stays consistent.
+    pub fn set_hours(&mut self, hours: f32) {
+        let target = (hours as f64).rem_euclid(24.0);
+        let ahead = (target - self.day_hours()).rem_euclid(24.0);
+
+        // Skip when already at the target. Float errors may leave current hour slightly off it
+        if ahead > 1e-6 && 24.0 - ahead > 1e-6 {
+            self.elapsed += ahead / 24.0 * DAY_LENGTH;
+        }
+    }
+
+    /// **Returns** how bright the sky is, from [`NIGHT_SKY_BRIGHTNESS`] at night until `1.0` on
+    /// daylight. This only scales natural light, so artificial light stays the same.
+    pub fn sky_brightness(&self) -> f32 {
+        let sun = -(self.hours() / 24.0 * std::f32::consts::TAU).cos();
+        let t = ((sun + TWILIGHT) / (2.0 * TWILIGHT)).clamp(0.0, 1.0);
+        let smooth = t * t * (3.0 - 2.0 * t);
+
+        NIGHT_SKY_BRIGHTNESS + (1.0 - NIGHT_SKY_BRIGHTNESS) * smooth
+    }
+}
+
+fn advance_world_time(time: Res<Time>, mut world_time: ResMut<WorldTime>) {
+    world_time.elapsed += time.delta_seconds_f64();
+}
+
 #[derive(TypeUuid, Debug)]
 #[uuid = "e6edff2a-e204-497f-999c-bdebd1f92f62"]
 pub struct KindsAtlasRes {
@@ -31,3 +91,43 @@ fn setup_resources(mut commands: Commands, asset_server: Res<AssetServer>) {
 
     commands.insert_resource(KindsAtlasRes { atlas });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_time_hours() {
+        let mut time = WorldTime::default();
+        assert_eq!(time.hours(), WORLD_START_HOUR as f32);
+
+        time.elapsed = DAY_LENGTH / 2.0;
+        assert!((time.hours() - 20.0).abs() < 0.001);
+
+        time.set_hours(6.5);
+        assert!((time.hours() - 6.5).abs() < 0.001);
+        assert!(
+            time.elapsed > DAY_LENGTH / 2.0,
+            "Time should never go backwards"
+        );
+
+        let elapsed = time.elapsed;
+        time.set_hours(30.5);
+        assert_eq!(time.elapsed, elapsed, "Same hour shouldn't move time");
+    }
+
+    #[test]
+    fn sky_brightness() {
+        let at = |hours| {
+            let mut time = WorldTime::default();
+            time.set_hours(hours);
+            time.sky_brightness()
+        };
+
+        assert_eq!(at(12.0), 1.0);
+        assert_eq!(at(0.0), NIGHT_SKY_BRIGHTNESS);
+        assert!(at(6.0) > NIGHT_SKY_BRIGHTNESS && at(6.0) < 1.0);
+        assert!(at(7.0) > at(6.0), "Sky should get brighter on the morning");
+        assert!(at(19.0) < at(18.0), "Sky should get darker on the evening");
+    }
+}
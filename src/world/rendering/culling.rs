@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashSet};
+use projekto_core::{
+    chunk::{self, FacesConnectivity},
+    voxel,
+};
+use projekto_genesis::ChunkConnectivityRes;
+
+use super::{ChunkLocal, ChunkMaterial, ChunkMaterialHandle, ChunkSection, ChunkSections};
+
+pub(super) struct CullingPlugin;
+
+impl Plugin for CullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(cull_hidden_sections);
+    }
+}
+
+/// Chunk local and section index.
+type SectionKey = (IVec3, usize);
+
+/// Hides chunk sections which can't be seen from camera, like caves behind solid ground.
+///
+/// Sections above material clip height are cut away, so those are always considered fully
+/// connected. If camera chunk isn't loaded yet, all sections are shown.
+fn cull_hidden_sections(
+    connectivity: Res<ChunkConnectivityRes>,
+    material_handle: Res<ChunkMaterialHandle>,
+    materials: Res<Assets<ChunkMaterial>>,
+    camera_query: Query<&GlobalTransform, With<Camera3d>>,
+    mut chunks_query: Query<(&ChunkLocal, &ChunkSections, &mut Visibility), Without<ChunkSection>>,
+    mut sections_query: Query<&mut Visibility, With<ChunkSection>>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(t) => t.translation(),
+        Err(_) => return,
+    };
+
+    let clip_height = materials
+        .get(&material_handle)
+        .map_or(f32::MAX, |m| m.clip_height);
+
+    let start = (
+        chunk::to_local(camera),
+        (camera.y.max(0.0) as usize / chunk::SECTION_AXIS_SIZE).min(chunk::SECTION_COUNT - 1),
+    );
+
+    let visible = flood_visible_sections(start, |(local, section)| {
+        let top = ((section + 1) * chunk::SECTION_AXIS_SIZE) as f32;
+
+        connectivity.get(local).map(|c| {
+            if top > clip_height {
+                FacesConnectivity::all()
+            } else {
+                c[section]
+            }
+        })
+    });
+
+    for (local, sections, mut chunk_visibility) in &mut chunks_query {
+        let mut any_visible = false;
+
+        for (section, entity) in sections.0.iter().enumerate() {
+            let entity = match entity {
+                Some(entity) => *entity,
+                None => continue,
+            };
+
+            let is_visible = visible.is_empty() || visible.contains(&(local.0, section));
+            any_visible |= is_visible;
+
+            if let Ok(mut visibility) = sections_query.get_mut(entity) {
+                if visibility.is_visible != is_visible {
+                    visibility.is_visible = is_visible;
+                }
+            }
+        }
+
+        if chunk_visibility.is_visible != any_visible {
+            chunk_visibility.is_visible = any_visible;
+        }
+    }
+}
+
+fn side_bit(side: voxel::Side) -> u8 {
+    1 << side as u8
+}
+
+/// Floods through sections connectivity graph, starting at the given section.
+///
+/// A section is left through a face only if that face is connected to the face it was entered
+/// from. The flood never goes back on a direction it already went, so each section is visited at
+/// most once and only sections in front of the previous ones are reached.
+///
+/// **Returns** all sections which may be visible from the starting section, or an empty set if
+/// the starting section doesn't exists.
+fn flood_visible_sections(
+    start: SectionKey,
+    connectivity: impl Fn(SectionKey) -> Option<FacesConnectivity>,
+) -> HashSet<SectionKey> {
+    let mut visible = HashSet::default();
+
+    if connectivity(start).is_none() {
+        return visible;
+    }
+
+    let mut queue = VecDeque::new();
+    visible.insert(start);
+    queue.push_back((start, None, 0u8));
+
+    while let Some((key @ (local, section), entered, directions)) = queue.pop_front() {
+        let current = match connectivity(key) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        for side in voxel::SIDES {
+            if directions & side_bit(side.opposite()) != 0 {
+                continue;
+            }
+
+            if let Some(entered) = entered {
+                if !current.is_connected(entered, side) {
+                    continue;
+                }
+            }
+
+            let next = match side {
+                voxel::Side::Up if section + 1 < chunk::SECTION_COUNT => (local, section + 1),
+                voxel::Side::Down if section > 0 => (local, section - 1),
+                voxel::Side::Up | voxel::Side::Down => continue,
+                _ => (local + side.dir(), section),
+            };
+
+            if connectivity(next).is_none() || !visible.insert(next) {
+                continue;
+            }
+
+            queue.push_back((next, Some(side.opposite()), directions | side_bit(side)));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn flood_visible_sections() {
+        let mut graph = HashMap::<SectionKey, FacesConnectivity>::default();
+
+        // A line of 4 chunks with 3 sections each, from bottom to top: an open cave section, a
+        // fully solid ground section and an open sky section.
+        for x in 0..4 {
+            graph.insert((IVec3::new(x, 0, 0), 0), FacesConnectivity::all());
+            graph.insert((IVec3::new(x, 0, 0), 1), FacesConnectivity::none());
+            graph.insert((IVec3::new(x, 0, 0), 2), FacesConnectivity::all());
+        }
+
+        let flood = |graph: &HashMap<SectionKey, FacesConnectivity>| {
+            super::flood_visible_sections((IVec3::ZERO, 2), |key| graph.get(&key).copied())
+        };
+
+        let visible = flood(&graph);
+        assert!((0..4).all(|x| visible.contains(&(IVec3::new(x, 0, 0), 2))));
+        assert!(
+            (0..4).all(|x| visible.contains(&(IVec3::new(x, 0, 0), 1))),
+            "Ground top faces can be seen from the sky"
+        );
+        assert!(
+            (0..4).all(|x| !visible.contains(&(IVec3::new(x, 0, 0), 0))),
+            "Caves behind solid ground can't be seen"
+        );
+
+        // Open a shaft through the ground of third chunk
+        let mut shaft = FacesConnectivity::none();
+        shaft.connect(voxel::Side::Up, voxel::Side::Down);
+        graph.insert((IVec3::new(2, 0, 0), 1), shaft);
+
+        let visible = flood(&graph);
+        assert!(visible.contains(&(IVec3::new(2, 0, 0), 0)));
+        assert!(visible.contains(&(IVec3::new(3, 0, 0), 0)));
+        assert!(
+            !visible.contains(&(IVec3::new(1, 0, 0), 0)),
+            "Flood should never go back toward the starting section"
+        );
+
+        let visible =
+            super::flood_visible_sections((IVec3::splat(10), 0), |key| graph.get(&key).copied());
+        assert!(visible.is_empty());
+    }
+}
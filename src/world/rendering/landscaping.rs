@@ -4,11 +4,15 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
     utils::{HashMap, HashSet},
 };
-use projekto_core::{chunk, landscape, query, voxel};
+use projekto_core::{
+    chunk::{self, SectionFlags},
+    landscape::ViewDistance,
+    query, voxel,
+};
 use projekto_genesis::{events::ChunkUpdated, ChunkKindRes};
 
 use crate::world::{
-    rendering::{ChunkMaterial, ChunkMaterialHandle},
+    rendering::{ChunkLodMaterialHandle, ChunkMaterial, ChunkMaterialHandle},
     KindsAtlasRes,
 };
 
@@ -22,6 +26,7 @@ impl Plugin for LandscapingPlugin {
             .add_plugin(MaterialPlugin::<ChunkMaterial>::default())
             .add_startup_system(setup_resources)
             .add_system(process_chunk_updated_events)
+            .add_system(resize_clip_map)
             .add_system(update_landscape);
     }
 }
@@ -37,35 +42,76 @@ struct LandscapeMeta {
     next_sync: f32,
 }
 
+/// Number of voxels on each horizontal axis of the clip map, which covers the full detail
+/// landscape.
+fn clip_map_axis_size(view_distance: &ViewDistance) -> u32 {
+    debug_assert_eq!(chunk::X_AXIS_SIZE, chunk::Z_AXIS_SIZE);
+    (view_distance.size() * chunk::X_AXIS_SIZE) as u32
+}
+
+/// Clip map is a square texture, which each row is a X axis column and each texel is a Z axis
+/// column, the same layout used to pack landscape coordinates.
+fn clip_map_extent(axis_size: u32) -> Extent3d {
+    Extent3d {
+        width: axis_size,
+        height: axis_size,
+        ..Default::default()
+    }
+}
+
+/// Re-packs clip map data using a new axis size. Both clip maps are centered on the same chunk, so
+/// each column keeps its clip height and columns outside of the new clip map are discarded.
+///
+/// **Returns** the new clip map data and the offset, in voxels, from the old clip map origin to the
+/// new one.
+fn resize_clip_map_data(data: &[u8], old_axis_size: u32, new_axis_size: u32) -> (Vec<u8>, i32) {
+    let (old_axis_size, new_axis_size) = (old_axis_size as i32, new_axis_size as i32);
+    let offset = (old_axis_size - new_axis_size) / 2;
+
+    let mut resized = vec![0; (new_axis_size * new_axis_size) as usize];
+
+    for x in 0..new_axis_size {
+        for z in 0..new_axis_size {
+            let (old_x, old_z) = (x + offset, z + offset);
+
+            if old_x >= 0 && old_x < old_axis_size && old_z >= 0 && old_z < old_axis_size {
+                resized[(x * new_axis_size + z) as usize] =
+                    data[(old_x * old_axis_size + old_z) as usize];
+            }
+        }
+    }
+
+    (resized, offset)
+}
+
 fn setup_resources(
     mut commands: Commands,
     mut materials: ResMut<Assets<ChunkMaterial>>,
     mut images: ResMut<Assets<Image>>,
     kinds_res: Res<KindsAtlasRes>,
+    view_distance: Res<ViewDistance>,
 ) {
-    const WIDTH: usize = landscape::HORIZONTAL_SIZE * chunk::X_AXIS_SIZE;
-    const HEIGHT: usize = landscape::HORIZONTAL_SIZE * chunk::Z_AXIS_SIZE;
+    let clip_map_axis_size = clip_map_axis_size(&view_distance);
     let clip_map = images.add(Image::new(
-        Extent3d {
-            width: (WIDTH * HEIGHT) as u32,
-            height: 1u32,
-            ..Default::default()
-        },
-        TextureDimension::D1,
-        vec![0; WIDTH * HEIGHT],
+        clip_map_extent(clip_map_axis_size),
+        TextureDimension::D2,
+        vec![0; (clip_map_axis_size * clip_map_axis_size) as usize],
         TextureFormat::R8Uint,
     ));
 
-    let material = materials.add(ChunkMaterial {
+    let material = ChunkMaterial {
         texture: kinds_res.atlas.clone(),
         tile_texture_size: 1.0 / voxel::KindsDescs::get().count_tiles() as f32,
         clip_map_origin: Vec2::ZERO,
         clip_height: f32::MAX,
+        clip_map_axis_size,
         clip_map,
+        sky_brightness: 1.0,
         show_back_faces: false,
-    });
+    };
 
-    commands.insert_resource(ChunkMaterialHandle(material));
+    commands.insert_resource(ChunkLodMaterialHandle(materials.add(material.clone())));
+    commands.insert_resource(ChunkMaterialHandle(materials.add(material)));
     commands.insert_resource(ChunkEntityMap(HashMap::default()));
     commands.insert_resource(LandscapeConfig { paused: false });
 
@@ -80,12 +126,49 @@ fn setup_resources(
     });
 }
 
+/// Resizes the clip map texture whenever [`ViewDistance`] changes. Existing clip data is re-packed
+/// using the new size and other material settings are kept, so clipping isn't lost.
+fn resize_clip_map(
+    view_distance: Res<ViewDistance>,
+    material_handle: Res<ChunkMaterialHandle>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !view_distance.is_changed() {
+        return;
+    }
+
+    let axis_size = clip_map_axis_size(&view_distance);
+
+    if materials
+        .get(&material_handle)
+        .map_or(true, |m| m.clip_map_axis_size == axis_size)
+    {
+        return;
+    }
+
+    debug!("Resizing clip map to {} voxels per axis", axis_size);
+
+    if let Some(material) = materials.get_mut(&material_handle) {
+        if let Some(image) = images.get_mut(&material.clip_map) {
+            let (data, offset) =
+                resize_clip_map_data(&image.data, material.clip_map_axis_size, axis_size);
+
+            image.resize(clip_map_extent(axis_size));
+            image.data = data;
+            material.clip_map_origin += Vec2::splat(offset as f32);
+        }
+
+        material.clip_map_axis_size = axis_size;
+    }
+}
+
 #[derive(SystemParam)]
 struct UpdateLandscapeParams<'w, 's> {
+    view_distance: Res<'w, ViewDistance>,
     kinds: Res<'w, ChunkKindRes>,
     meta: ResMut<'w, LandscapeMeta>,
     writer: EventWriter<'w, 's, EvtChunkMeshDirty>,
-    material: Res<'w, ChunkMaterialHandle>,
     entity_map: ResMut<'w, ChunkEntityMap>,
     center_query: Query<'w, 's, &'static Transform, With<LandscapeCenter>>,
 }
@@ -107,14 +190,17 @@ fn update_landscape(
 
     params.meta.next_sync -= time.delta_seconds();
 
-    if center != params.meta.last_pos || params.meta.next_sync < 0.0 {
+    if center != params.meta.last_pos
+        || params.meta.next_sync < 0.0
+        || params.view_distance.is_changed()
+    {
         params.meta.next_sync = 1.0;
         params.meta.last_pos = center;
 
         let radius = IVec3::new(
-            landscape::HORIZONTAL_RADIUS as i32,
+            params.view_distance.radius(),
             0,
-            landscape::HORIZONTAL_RADIUS as i32,
+            params.view_distance.radius(),
         );
         let begin = center - radius;
         let end = center + radius;
@@ -138,16 +224,18 @@ fn update_landscape(
             let entity = commands
                 .spawn_bundle(ChunkBundle {
                     local: ChunkLocal(local),
-                    mesh_bundle: MaterialMeshBundle {
-                        material: params.material.clone(),
+                    spatial_bundle: SpatialBundle {
                         transform: Transform::from_translation(chunk::to_world(local)),
                         ..Default::default()
                     },
+                    ..Default::default()
                 })
                 .insert(Name::new(format!("Chunk {}", local)))
                 .id();
             params.entity_map.0.insert(local, entity);
-            params.writer.send(EvtChunkMeshDirty(local));
+            params
+                .writer
+                .send(EvtChunkMeshDirty(local, SectionFlags::all()));
 
             commands.entity(params.meta.root).add_child(entity);
         }
@@ -174,9 +262,9 @@ fn process_chunk_updated_events(
     mut writer: EventWriter<EvtChunkMeshDirty>,
     entity_map: Res<ChunkEntityMap>,
 ) {
-    for ChunkUpdated(chunk_local) in reader.iter() {
+    for ChunkUpdated(chunk_local, sections) in reader.iter() {
         if entity_map.0.get(chunk_local).is_some() {
-            writer.send(EvtChunkMeshDirty(*chunk_local));
+            writer.send(EvtChunkMeshDirty(*chunk_local, *sections));
         }
     }
 }
@@ -191,7 +279,10 @@ mod test {
     fn update_chunks() {
         // Arrange
         let mut added_events = Events::<ChunkUpdated>::default();
-        added_events.send(ChunkUpdated((1, 2, 3).into()));
+        added_events.send(ChunkUpdated(
+            (1, 2, 3).into(),
+            SectionFlags::from_voxel((0, 20, 0).into()),
+        ));
 
         let mut world = World::default();
         world.insert_resource(added_events);
@@ -211,15 +302,37 @@ mod test {
         stage.run(&mut world);
 
         // Assert
-        assert_eq!(
-            world
-                .get_resource::<Events<EvtChunkMeshDirty>>()
-                .unwrap()
-                .iter_current_update_events()
-                .next()
-                .unwrap()
-                .0,
-            (1, 2, 3).into()
-        );
+        let evt = world
+            .get_resource::<Events<EvtChunkMeshDirty>>()
+            .unwrap()
+            .iter_current_update_events()
+            .next()
+            .unwrap();
+
+        assert_eq!(evt.0, (1, 2, 3).into());
+        assert_eq!(evt.1.iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn clip_map_axis_size() {
+        assert_eq!(super::clip_map_axis_size(&ViewDistance::new(4)), 144);
+        assert_eq!(super::clip_map_axis_size(&ViewDistance::new(0)), 16);
+    }
+
+    #[test]
+    fn resize_clip_map_data() {
+        // Center column of a 3x3 clip map
+        let mut data = vec![0; 9];
+        data[4] = 7;
+
+        let (grown, offset) = super::resize_clip_map_data(&data, 3, 5);
+        assert_eq!(offset, -1);
+        assert_eq!(grown.len(), 25);
+        assert_eq!(grown[12], 7, "Center column should be kept");
+        assert_eq!(grown.iter().filter(|&&h| h > 0).count(), 1);
+
+        let (shrunk, offset) = super::resize_clip_map_data(&grown, 5, 1);
+        assert_eq!(offset, 2);
+        assert_eq!(shrunk, vec![7]);
     }
 }
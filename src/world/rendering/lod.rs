@@ -0,0 +1,164 @@
+use bevy::{
+    ecs::system::SystemParam, pbr::NotShadowCaster, prelude::*, render::primitives::Aabb,
+    utils::HashMap,
+};
+use projekto_core::{chunk, landscape::ViewDistance, query};
+use projekto_genesis::{events::ChunkLodGenerated, LodCommandBuffer};
+
+use super::{
+    meshing, ChunkEntityMap, ChunkLocal, ChunkLodMaterialHandle, ChunkMaterial, ChunkMeshed,
+    LandscapeCenter, LandscapeConfig,
+};
+
+pub(super) struct LodPlugin;
+
+impl Plugin for LodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkLodEntityMap>()
+            .add_system(update_lod_landscape)
+            .add_system(process_lod_generated_events);
+    }
+}
+
+/// Level of detail of a chunk entity. See [`ViewDistance::lod_level`] for more info.
+#[derive(Component, Debug, PartialEq, Eq)]
+pub struct ChunkLod(pub u8);
+
+/// Maps level of detail chunks local to their entities.
+#[derive(Default, Deref, DerefMut)]
+pub struct ChunkLodEntityMap(pub HashMap<IVec3, Entity>);
+
+#[derive(Bundle)]
+struct ChunkLodBundle {
+    local: ChunkLocal,
+    lod: ChunkLod,
+    #[bundle]
+    mesh_bundle: MaterialMeshBundle<ChunkMaterial>,
+    // Same as `ChunkSectionBundle`, since level of detail meshes are packed too
+    aabb: Aabb,
+    not_shadow_caster: NotShadowCaster,
+}
+
+#[derive(SystemParam)]
+struct UpdateLodLandscapeParams<'w, 's> {
+    config: Res<'w, LandscapeConfig>,
+    view_distance: Res<'w, ViewDistance>,
+    entity_map: Res<'w, ChunkEntityMap>,
+    lod_map: ResMut<'w, ChunkLodEntityMap>,
+    buffer: ResMut<'w, LodCommandBuffer>,
+    material: Res<'w, ChunkLodMaterialHandle>,
+    center_query: Query<'w, 's, &'static Transform, With<LandscapeCenter>>,
+    lod_query: Query<'w, 's, &'static mut ChunkLod>,
+    meshed_query: Query<'w, 's, (), With<ChunkMeshed>>,
+}
+
+/// Keeps a ring of level of detail chunks around the full detail landscape.
+///
+/// Full detail chunks which aren't meshed yet are also rendered at the first level of detail, so
+/// there is no holes while those chunks are being loaded and meshed.
+fn update_lod_landscape(mut commands: Commands, mut params: UpdateLodLandscapeParams) {
+    if params.config.paused {
+        return;
+    }
+
+    let center = match params.center_query.get_single() {
+        Ok(t) => chunk::to_local(t.translation),
+        Err(_) => return,
+    };
+
+    let view_distance = *params.view_distance;
+    let lod_radius = view_distance.lod_radius();
+    let max_radius = lod_radius[lod_radius.len() - 1];
+    let radius = IVec3::new(max_radius, 0, max_radius);
+
+    let is_meshed = |local: IVec3| {
+        params
+            .entity_map
+            .get(&local)
+            .map_or(false, |&entity| params.meshed_query.get(entity).is_ok())
+    };
+
+    let desired = query::range_inclusive(center - radius, center + radius)
+        .filter_map(|local| match view_distance.lod_level(center, local) {
+            Some(0) if !is_meshed(local) => Some((local, 1)),
+            Some(level) if level > 0 => Some((local, level)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    params.lod_map.retain(|local, entity| {
+        if desired.contains_key(local) {
+            true
+        } else {
+            commands.entity(*entity).despawn_recursive();
+            false
+        }
+    });
+
+    for (local, level) in desired {
+        if let Some(&entity) = params.lod_map.get(&local) {
+            if let Ok(mut lod) = params.lod_query.get_mut(entity) {
+                if lod.0 != level {
+                    lod.0 = level;
+                    params.buffer.generate(local, level);
+                }
+            }
+
+            continue;
+        }
+
+        let entity = commands
+            .spawn_bundle(ChunkLodBundle {
+                local: ChunkLocal(local),
+                lod: ChunkLod(level),
+                mesh_bundle: MaterialMeshBundle {
+                    material: params.material.clone(),
+                    transform: Transform::from_translation(chunk::to_world(local)),
+                    ..Default::default()
+                },
+                aabb: Aabb::from_min_max(
+                    Vec3::ZERO,
+                    Vec3::new(
+                        chunk::X_AXIS_SIZE as f32,
+                        chunk::Y_AXIS_SIZE as f32,
+                        chunk::Z_AXIS_SIZE as f32,
+                    ),
+                ),
+                not_shadow_caster: NotShadowCaster,
+            })
+            .insert(Name::new(format!("Chunk LOD {}", local)))
+            .id();
+
+        params.lod_map.insert(local, entity);
+        params.buffer.generate(local, level);
+    }
+}
+
+fn process_lod_generated_events(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut reader: EventReader<ChunkLodGenerated>,
+    lod_map: Res<ChunkLodEntityMap>,
+    q: Query<&ChunkLod>,
+) {
+    for ChunkLodGenerated {
+        local,
+        level,
+        vertices,
+    } in reader.iter()
+    {
+        // Skip results of chunks which were despawned or had level changed
+        let entity = match lod_map.get(local) {
+            Some(&entity) if q.get(entity).map_or(false, |lod| lod.0 == *level) => entity,
+            _ => continue,
+        };
+
+        if vertices.is_empty() {
+            commands.entity(entity).remove::<Handle<Mesh>>();
+        } else {
+            commands
+                .entity(entity)
+                .insert(meshes.add(meshing::generate_mesh(vertices)));
+        }
+    }
+}
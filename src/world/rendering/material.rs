@@ -21,6 +21,10 @@ use bevy::{
 #[derive(Reflect, Component, Debug, Deref, DerefMut)]
 pub struct ChunkMaterialHandle(pub Handle<ChunkMaterial>);
 
+/// Material used by level of detail chunks. Those chunks are never clipped.
+#[derive(Reflect, Component, Debug, Deref, DerefMut)]
+pub struct ChunkLodMaterialHandle(pub Handle<ChunkMaterial>);
+
 #[derive(Debug, Clone, TypeUuid, Reflect)]
 #[uuid = "f690fd1e-d5d8-45ab-8225-97e2a3f056e0"]
 pub struct ChunkMaterial {
@@ -33,8 +37,14 @@ pub struct ChunkMaterial {
     pub clip_map_origin: Vec2,
     // #[uniform(2)]
     pub clip_height: f32,
+    /// Number of voxels on each axis of the clip map, which covers the full detail landscape.
+    // #[uniform(2)]
+    pub clip_map_axis_size: u32,
     // #[texture(3)]
     pub clip_map: Handle<Image>,
+    /// Scales natural light of all vertices, from `0.0` to `1.0`. Artificial light isn't affected.
+    // #[uniform(2)]
+    pub sky_brightness: f32,
 
     pub show_back_faces: bool,
 }
@@ -44,6 +54,8 @@ struct ChunkMaterialUniform {
     tile_texture_size: f32,
     clip_map_origin: Vec2,
     clip_height: f32,
+    clip_map_axis_size: u32,
+    sky_brightness: f32,
 }
 
 impl From<&ChunkMaterial> for ChunkMaterialUniform {
@@ -52,19 +64,35 @@ impl From<&ChunkMaterial> for ChunkMaterialUniform {
             tile_texture_size: mat.tile_texture_size,
             clip_map_origin: mat.clip_map_origin,
             clip_height: mat.clip_height,
+            clip_map_axis_size: mat.clip_map_axis_size,
+            sky_brightness: mat.sky_brightness,
         }
     }
 }
 
+/// Chunk meshes uses packed [`projekto_core::voxel::VoxelVertex`] attributes, which are decoded on
+/// `voxel.wgsl`.
+///
+/// Bevy mesh pipeline requires position, normal and uv attributes to exist, so packed attributes
+/// reuses their ids, but each one has its own name, so errors point to the right attribute. The
+/// remaining packed attributes use ids unknown to Bevy, so they don't enable any mesh feature.
+/// Since those attributes aren't [`VertexFormat::Float32x3`], chunk meshes can't cast shadows nor
+/// compute their own bounds.
 impl ChunkMaterial {
-    pub const ATTRIBUTE_TILE_COORD_START: MeshVertexAttribute =
-        MeshVertexAttribute::new("TileCoordStart", 66438, VertexFormat::Float32x2);
+    pub const ATTRIBUTE_POSITION: MeshVertexAttribute =
+        MeshVertexAttribute::new("Vertex_Position", 0, VertexFormat::Uint32);
 
     pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
-        MeshVertexAttribute::new("Light", 66439, VertexFormat::Float32x3);
+        MeshVertexAttribute::new("Vertex_Light", 1, VertexFormat::Uint32);
+
+    pub const ATTRIBUTE_TILE: MeshVertexAttribute =
+        MeshVertexAttribute::new("Vertex_Tile", 2, VertexFormat::Uint32);
 
-    pub const ATTRIBUTE_VOXEL: MeshVertexAttribute =
-        MeshVertexAttribute::new("Voxel", 66440, VertexFormat::Uint32);
+    pub const ATTRIBUTE_ARTIFICIAL_LIGHT: MeshVertexAttribute = MeshVertexAttribute::new(
+        "Vertex_Artificial_Light",
+        1_946_232_083,
+        VertexFormat::Uint32,
+    );
 }
 
 impl Material for ChunkMaterial {
@@ -87,12 +115,10 @@ impl Material for ChunkMaterial {
         _key: MaterialPipelineKey<Self>,
     ) -> Result<(), bevy::render::render_resource::SpecializedMeshPipelineError> {
         let vertex_layout = layout.get_layout(&[
-            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
-            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
-            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
-            ChunkMaterial::ATTRIBUTE_TILE_COORD_START.at_shader_location(3),
-            ChunkMaterial::ATTRIBUTE_LIGHT.at_shader_location(4),
-            ChunkMaterial::ATTRIBUTE_VOXEL.at_shader_location(5),
+            ChunkMaterial::ATTRIBUTE_POSITION.at_shader_location(0),
+            ChunkMaterial::ATTRIBUTE_LIGHT.at_shader_location(1),
+            ChunkMaterial::ATTRIBUTE_TILE.at_shader_location(2),
+            ChunkMaterial::ATTRIBUTE_ARTIFICIAL_LIGHT.at_shader_location(3),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
 
@@ -206,7 +232,7 @@ impl AsBindGroup for ChunkMaterial {
                     visibility: ShaderStages::VERTEX_FRAGMENT,
                     ty: BindingType::Texture {
                         sample_type: TextureSampleType::Uint,
-                        view_dimension: TextureViewDimension::D1,
+                        view_dimension: TextureViewDimension::D2,
                         multisampled: false,
                     },
                     count: None,
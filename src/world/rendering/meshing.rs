@@ -1,86 +1,346 @@
-use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 use bevy::{
+    ecs::system::SystemParam,
     prelude::*,
     render::mesh::{Indices, PrimitiveTopology},
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
 };
-use projekto_core::voxel::VoxelVertex;
+use futures_lite::future;
+use projekto_core::{chunk, voxel::VoxelVertex};
 
 use projekto_genesis::ChunkVertexRes;
 use projekto_shaping as shaping;
 
-use super::{ChunkEntityMap, ChunkMaterial, EvtChunkMeshDirty};
+use super::{
+    ChunkEntityMap, ChunkMaterial, ChunkMaterialHandle, ChunkMeshed, ChunkSectionBundle,
+    ChunkSections, EvtChunkMeshDirty, LandscapeCenter,
+};
 
 pub(super) struct MeshingPlugin;
 
 impl Plugin for MeshingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(mesh_generation_system);
+        app.init_resource::<MeshingConfig>()
+            .init_resource::<MeshGenerationMeta>()
+            .add_system(dispatch_mesh_generation.label(MeshingLabel::Dispatch))
+            .add_system(upload_generated_meshes.after(MeshingLabel::Dispatch));
+    }
+}
+
+#[derive(SystemLabel, Debug, Hash, PartialEq, Eq, Clone)]
+enum MeshingLabel {
+    Dispatch,
+}
+
+/// Controls how mesh generation is spread across frames.
+pub struct MeshingConfig {
+    /// Frame time which the upload budget tries to keep.
+    pub target_frame_time: Duration,
+    /// Minimum time spent uploading meshes each frame. At least one mesh is always uploaded.
+    pub min_budget: Duration,
+    /// Maximum time spent uploading meshes each frame.
+    pub max_budget: Duration,
+    /// Maximum number of mesh generation tasks running at same time.
+    pub max_running_tasks: usize,
+}
+
+impl Default for MeshingConfig {
+    fn default() -> Self {
+        Self {
+            target_frame_time: Duration::from_secs_f32(1.0 / 60.0),
+            min_budget: Duration::from_micros(500),
+            max_budget: Duration::from_millis(4),
+            max_running_tasks: 16,
+        }
     }
 }
 
+/// Chunk local and section index.
+type SectionKey = (IVec3, usize);
+
 #[derive(Default)]
 struct MeshGenerationMeta {
-    pending_chunks: VecDeque<IVec3>,
+    pending: HashSet<SectionKey>,
+    running: HashMap<SectionKey, Task<Mesh>>,
+    /// Generated meshes waiting to be uploaded. Empty sections has no mesh. Only the latest mesh
+    /// of each section is kept, so stale meshes are never uploaded.
+    generated: HashMap<SectionKey, Option<Mesh>>,
+    budget: Duration,
 }
 
-fn mesh_generation_system(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
+fn landscape_center(q: &Query<&Transform, With<LandscapeCenter>>) -> IVec3 {
+    q.get_single()
+        .map(|t| chunk::to_local(t.translation))
+        .unwrap_or_default()
+}
+
+/// Sorts the given list of chunks locals, so the nearest chunk to the given center is the last one.
+fn sort_by_farthest<T>(center: IVec3, list: &mut [T], local: impl Fn(&T) -> IVec3) {
+    list.sort_by_key(|item| {
+        let distance = local(item) - center;
+        std::cmp::Reverse(
+            distance.x * distance.x + distance.y * distance.y + distance.z * distance.z,
+        )
+    });
+}
+
+fn dispatch_mesh_generation(
+    config: Res<MeshingConfig>,
     vertices: Res<ChunkVertexRes>,
-    entity_map: Res<ChunkEntityMap>,
+    mut meta: ResMut<MeshGenerationMeta>,
     mut reader: EventReader<EvtChunkMeshDirty>,
-    mut meta: Local<MeshGenerationMeta>,
+    q: Query<&Transform, With<LandscapeCenter>>,
 ) {
-    meta.pending_chunks.extend(reader.iter().map(|evt| evt.0));
+    for EvtChunkMeshDirty(local, sections) in reader.iter() {
+        meta.pending
+            .extend(sections.iter().map(|section| (*local, section)));
+    }
+
+    let available = config.max_running_tasks.saturating_sub(meta.running.len());
+    if meta.pending.is_empty() || available == 0 {
+        return;
+    }
 
-    let limit = usize::min(meta.pending_chunks.len(), 1);
+    // Sections which are already running will be dispatched again when the running task finishes
+    let mut pending = meta
+        .pending
+        .iter()
+        .filter(|key| !meta.running.contains_key(key))
+        .copied()
+        .collect::<Vec<_>>();
 
-    for local in meta.pending_chunks.drain(..limit) {
-        if let Some(&e) = entity_map.0.get(&local) {
-            if let Some(vertices) = vertices.get(local) {
-                debug_assert!(!vertices.is_empty());
+    sort_by_farthest(landscape_center(&q), &mut pending, |&(local, _)| local);
 
-                let mesh = generate_mesh(vertices);
-                commands.entity(e).insert(meshes.add(mesh));
+    let mut dispatched = 0;
+
+    for key in pending.into_iter().rev() {
+        if dispatched == available {
+            break;
+        }
+
+        meta.pending.remove(&key);
+
+        let (local, section) = key;
+
+        let vertices = match vertices.get(local) {
+            Some(vertices) => &vertices[section],
+            None => {
+                warn!(
+                    "Skipping mesh generation since chunk {} has no vertices",
+                    local
+                );
                 continue;
             }
+        };
+
+        // Empty sections doesn't need any task, just remove it's mesh
+        if vertices.is_empty() {
+            meta.generated.insert(key, None);
+            continue;
         }
 
-        warn!(
-            "Skipping mesh generation since chunk {} wasn't found on entity map",
-            local
-        );
+        let vertices = vertices.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move { generate_mesh(&vertices) });
+        meta.running.insert(key, task);
+        dispatched += 1;
+    }
+}
+
+#[derive(SystemParam)]
+struct UploadMeshesParams<'w, 's> {
+    meshes: ResMut<'w, Assets<Mesh>>,
+    material: Res<'w, ChunkMaterialHandle>,
+    entity_map: Res<'w, ChunkEntityMap>,
+    q_sections: Query<'w, 's, &'static mut ChunkSections>,
+}
+
+fn upload_generated_meshes(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<MeshingConfig>,
+    mut meta: ResMut<MeshGenerationMeta>,
+    mut params: UploadMeshesParams,
+    q: Query<&Transform, With<LandscapeCenter>>,
+) {
+    let MeshGenerationMeta {
+        pending,
+        running,
+        generated,
+        budget,
+    } = &mut *meta;
+
+    running.retain(|&key, task| {
+        if let Some(mesh) = future::block_on(future::poll_once(task)) {
+            generated.insert(key, Some(mesh));
+            false
+        } else {
+            true
+        }
+    });
+
+    if generated.is_empty() {
+        return;
+    }
+
+    *budget = adapt_budget(*budget, time.delta(), &config);
+
+    let mut keys = generated.keys().copied().collect::<Vec<_>>();
+    sort_by_farthest(landscape_center(&q), &mut keys, |&(local, _)| local);
+
+    let begin = Instant::now();
+
+    while let Some(key) = keys.pop() {
+        let mesh = generated
+            .remove(&key)
+            .expect("Every key should have a generated mesh");
+        let (local, section) = key;
+        let chunk_entity = params.entity_map.0.get(&local).copied();
+
+        let (chunk_entity, mut sections) = match chunk_entity.and_then(|e| {
+            params
+                .q_sections
+                .get_mut(e)
+                .ok()
+                .map(|sections| (e, sections))
+        }) {
+            Some(found) => found,
+            None => {
+                warn!(
+                    "Skipping mesh generation since chunk {} wasn't found on entity map",
+                    local
+                );
+                continue;
+            }
+        };
+
+        match (sections.0[section], mesh) {
+            (Some(e), Some(mesh)) => {
+                commands.entity(e).insert(params.meshes.add(mesh));
+            }
+            (None, Some(mesh)) => {
+                let e = commands
+                    .spawn_bundle(ChunkSectionBundle::new(
+                        section,
+                        params.meshes.add(mesh),
+                        params.material.clone(),
+                    ))
+                    .insert(Name::new(format!("Section {}", section)))
+                    .id();
+
+                commands.entity(chunk_entity).add_child(e);
+                sections.0[section] = Some(e);
+            }
+            (Some(e), None) => {
+                commands.entity(e).despawn_recursive();
+                sections.0[section] = None;
+            }
+            (None, None) => (),
+        }
+
+        let meshing = (0..chunk::SECTION_COUNT).any(|section| {
+            let key = (local, section);
+            pending.contains(&key) || running.contains_key(&key) || generated.contains_key(&key)
+        });
+
+        if !meshing {
+            commands.entity(chunk_entity).insert(ChunkMeshed);
+        }
+
+        if begin.elapsed() >= *budget {
+            break;
+        }
     }
 }
 
-fn generate_mesh(vertices: &Vec<VoxelVertex>) -> Mesh {
+/// Shrinks the upload budget by half when last frame took longer than target frame time and grows
+/// it slowly otherwise.
+///
+/// **Returns** the new budget clamped to [`MeshingConfig`] bounds.
+fn adapt_budget(budget: Duration, frame_time: Duration, config: &MeshingConfig) -> Duration {
+    let budget = if frame_time > config.target_frame_time {
+        budget / 2
+    } else {
+        budget + config.min_budget / 2
+    };
+
+    budget.clamp(config.min_budget, config.max_budget)
+}
+
+pub(super) fn generate_mesh(vertices: &Vec<VoxelVertex>) -> Mesh {
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
-    let mut positions: Vec<[f32; 3]> = vec![];
-    let mut normals: Vec<[f32; 3]> = vec![];
-    let mut uvs: Vec<[f32; 2]> = vec![];
-    let mut tile_coord_start: Vec<[f32; 2]> = vec![];
-    let mut lights: Vec<[f32; 3]> = vec![];
-    let mut voxels: Vec<u32> = vec![];
+    let mut positions: Vec<u32> = vec![];
+    let mut lights: Vec<u32> = vec![];
+    let mut artificial_lights: Vec<u32> = vec![];
+    let mut tiles: Vec<u32> = vec![];
 
     let vertex_count = vertices.len();
 
     for vertex in vertices {
-        positions.push(vertex.position.into());
-        normals.push(vertex.normal.into());
-        uvs.push(vertex.uv.into());
-        tile_coord_start.push(vertex.tile_coord_start.into());
-        lights.push(vertex.light.into());
-        voxels.push(vertex.voxel);
+        positions.push(vertex.position);
+        lights.push(vertex.light);
+        artificial_lights.push(vertex.artificial_light);
+        tiles.push(vertex.tile);
     }
 
     mesh.set_indices(Some(Indices::U32(shaping::compute_indices(vertex_count))));
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_TILE_COORD_START, tile_coord_start);
+    mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_POSITION, positions);
     mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_LIGHT, lights);
-    mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_VOXEL, voxels);
+    mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_ARTIFICIAL_LIGHT, artificial_lights);
+    mesh.insert_attribute(ChunkMaterial::ATTRIBUTE_TILE, tiles);
     mesh
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_by_farthest() {
+        let mut locals = vec![
+            IVec3::new(0, 0, 0),
+            IVec3::new(3, 0, 0),
+            IVec3::new(-1, 0, 1),
+            IVec3::new(0, 0, -5),
+        ];
+
+        super::sort_by_farthest(IVec3::new(1, 0, 0), &mut locals, |&l| l);
+
+        assert_eq!(
+            locals,
+            vec![
+                IVec3::new(0, 0, -5),
+                IVec3::new(-1, 0, 1),
+                IVec3::new(3, 0, 0),
+                IVec3::new(0, 0, 0),
+            ],
+            "Nearest chunk should be the last one"
+        );
+    }
+
+    #[test]
+    fn adapt_budget() {
+        let config = MeshingConfig::default();
+
+        let budget = super::adapt_budget(config.max_budget, config.target_frame_time * 2, &config);
+        assert_eq!(
+            budget,
+            config.max_budget / 2,
+            "Slow frames should shrink budget"
+        );
+
+        let budget = super::adapt_budget(Duration::ZERO, Duration::ZERO, &config);
+        assert_eq!(
+            budget, config.min_budget,
+            "Budget should never be below min"
+        );
+
+        let budget = super::adapt_budget(config.max_budget, Duration::ZERO, &config);
+        assert_eq!(
+            budget, config.max_budget,
+            "Budget should never be above max"
+        );
+    }
+}
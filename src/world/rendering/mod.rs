@@ -1,14 +1,22 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{pbr::NotShadowCaster, prelude::*, render::primitives::Aabb, utils::HashMap};
+use projekto_core::chunk::{self, SectionFlags};
 
-use self::{landscaping::LandscapingPlugin, meshing::MeshingPlugin};
+use super::WorldTime;
+
+use self::{
+    culling::CullingPlugin, landscaping::LandscapingPlugin, lod::LodPlugin, meshing::MeshingPlugin,
+};
 
 pub use landscaping::LandscapeConfig;
+pub use meshing::MeshingConfig;
 
+mod culling;
 mod landscaping;
+mod lod;
 mod material;
 mod meshing;
 
-pub use material::{ChunkMaterial, ChunkMaterialHandle};
+pub use material::{ChunkLodMaterialHandle, ChunkMaterial, ChunkMaterialHandle};
 
 #[derive(Component)]
 pub struct LandscapeCenter;
@@ -18,31 +26,113 @@ impl Plugin for PipelinePlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<ChunkMaterial>()
             .add_plugin(LandscapingPlugin)
-            .add_plugin(MeshingPlugin);
+            .add_plugin(MeshingPlugin)
+            .add_plugin(LodPlugin)
+            .add_plugin(CullingPlugin)
+            .add_system(update_sky_brightness);
     }
 }
 
-/// This event is raised whenever a chunk mesh needs to be redrawn
-pub struct EvtChunkMeshDirty(pub IVec3);
+/// Min sky brightness change which updates chunk materials. Each update rebuilds material bind
+/// groups, so tiny changes are skipped.
+const SKY_BRIGHTNESS_STEP: f32 = 0.005;
+
+/// Sets sky brightness of chunk materials using [`WorldTime`], so natural light follows the time
+/// of day.
+fn update_sky_brightness(
+    world_time: Res<WorldTime>,
+    material: Res<ChunkMaterialHandle>,
+    lod_material: Res<ChunkLodMaterialHandle>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    let brightness =
+        (world_time.sky_brightness() / SKY_BRIGHTNESS_STEP).round() * SKY_BRIGHTNESS_STEP;
+
+    for handle in [&material.0, &lod_material.0] {
+        let outdated = materials
+            .get(handle)
+            .map_or(false, |material| material.sky_brightness != brightness);
+
+        // Getting a mutable material marks it as modified, so only do it when needed
+        if outdated {
+            if let Some(material) = materials.get_mut(handle) {
+                material.sky_brightness = brightness;
+            }
+        }
+    }
+}
+
+/// This event is raised whenever a chunk mesh needs to be redrawn. Only the given sections are
+/// redrawn.
+pub struct EvtChunkMeshDirty(pub IVec3, pub SectionFlags);
 
 #[derive(Component)]
 pub struct ChunkLocal(pub IVec3);
 
+/// Entities of each chunk section. Sections without any vertex has no entity.
+#[derive(Component, Default)]
+pub struct ChunkSections(pub [Option<Entity>; chunk::SECTION_COUNT]);
+
+#[derive(Component)]
+pub struct ChunkSection(pub usize);
+
+/// Marks chunks which had all their sections meshed at least once.
+#[derive(Component)]
+pub struct ChunkMeshed;
+
 #[derive(Component, Deref, DerefMut)]
 pub struct ChunkEntityMap(pub HashMap<IVec3, Entity>);
 
 #[derive(Bundle)]
 pub struct ChunkBundle {
     local: ChunkLocal,
+    sections: ChunkSections,
     #[bundle]
-    mesh_bundle: MaterialMeshBundle<material::ChunkMaterial>,
+    spatial_bundle: SpatialBundle,
 }
 
 impl Default for ChunkBundle {
     fn default() -> Self {
         Self {
             local: ChunkLocal(IVec3::ZERO),
-            mesh_bundle: MaterialMeshBundle::default(),
+            sections: ChunkSections::default(),
+            spatial_bundle: SpatialBundle::default(),
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct ChunkSectionBundle {
+    section: ChunkSection,
+    #[bundle]
+    mesh_bundle: MaterialMeshBundle<material::ChunkMaterial>,
+    // Chunk meshes uses packed positions, so bevy can't compute neither bounds nor shadows
+    aabb: Aabb,
+    not_shadow_caster: NotShadowCaster,
+}
+
+impl ChunkSectionBundle {
+    /// Creates a new section bundle. Section mesh positions are relative to chunk, so section
+    /// transform is always at chunk origin.
+    pub fn new(section: usize, mesh: Handle<Mesh>, material: Handle<ChunkMaterial>) -> Self {
+        let begin = (section * chunk::SECTION_AXIS_SIZE) as f32;
+
+        Self {
+            section: ChunkSection(section),
+            mesh_bundle: MaterialMeshBundle {
+                mesh,
+                material,
+                ..Default::default()
+            },
+            aabb: Aabb::from_min_max(
+                Vec3::new(0.0, begin, 0.0),
+                Vec3::new(
+                    chunk::X_AXIS_SIZE as f32,
+                    begin + chunk::SECTION_AXIS_SIZE as f32,
+                    chunk::Z_AXIS_SIZE as f32,
+                ),
+            ),
+            not_shadow_caster: NotShadowCaster,
         }
     }
 }
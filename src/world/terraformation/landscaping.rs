@@ -1,6 +1,6 @@
-use bevy::{prelude::*, utils::HashSet};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashSet};
 
-use projekto_core::{chunk, query};
+use projekto_core::{chunk, landscape::ViewDistance, query};
 use projekto_genesis::{ChunkKindRes, GenesisCommandBuffer};
 
 use super::{TerraformationCenter, TerraformationConfig};
@@ -21,42 +21,113 @@ struct UpdateLandscapeMeta {
 fn update_landscape(
     time: Res<Time>,
     config: Res<TerraformationConfig>,
+    view_distance: Res<ViewDistance>,
     kinds: Res<ChunkKindRes>,
     mut meta: Local<UpdateLandscapeMeta>,
     mut cmd_buffer: ResMut<GenesisCommandBuffer>,
     q: Query<&Transform, With<TerraformationCenter>>,
 ) {
-    let center = match q.get_single() {
-        Ok(t) => chunk::to_local(t.translation),
+    let (center, forward) = match q.get_single() {
+        Ok(t) => (chunk::to_local(t.translation), t.forward().xz()),
         Err(_) => return,
     };
 
     meta.next_sync -= time.delta_seconds();
 
-    if center != meta.last_pos || meta.next_sync < 0.0 {
+    if center != meta.last_pos
+        || meta.next_sync < 0.0
+        || view_distance.is_changed()
+        || config.is_changed()
+    {
         meta.next_sync = 1.0;
         meta.last_pos = center;
 
-        let radius = IVec3::new(
-            config.horizontal_radius as i32,
-            0,
-            config.horizontal_radius as i32,
-        );
+        let load_radius = (view_distance.get() + config.horizontal_margin) as i32;
+        let unload_radius = load_radius + config.unload_margin as i32;
+
+        let radius = IVec3::new(load_radius, 0, load_radius);
 
         let begin = center - radius;
         let end = center + radius;
 
-        let visible_range = query::range_inclusive(begin, end).collect::<HashSet<_>>();
         let existing_chunks = HashSet::from_iter(kinds.list_chunks().into_iter());
 
-        visible_range
-            .iter()
-            .filter(|&i| !existing_chunks.contains(i))
-            .for_each(|&v| cmd_buffer.load(v));
+        let mut load = query::range_inclusive(begin, end)
+            .filter(|local| !existing_chunks.contains(local))
+            .collect::<Vec<_>>();
+
+        load.sort_by(|&a, &b| {
+            load_priority(center, forward, a).total_cmp(&load_priority(center, forward, b))
+        });
+
+        // Pending loads may be too far away already or using an outdated priority
+        cmd_buffer.cancel_pending_loads();
+        load.into_iter().for_each(|local| cmd_buffer.load(local));
 
         existing_chunks
             .iter()
-            .filter(|&i| !visible_range.contains(i))
-            .for_each(|&v| cmd_buffer.unload(v));
+            .filter(|&&local| horizontal_distance(center, local) > unload_radius)
+            .for_each(|&local| cmd_buffer.unload(local));
+    }
+}
+
+fn horizontal_distance(center: IVec3, local: IVec3) -> i32 {
+    let distance = (local - center).abs();
+    distance.x.max(distance.z)
+}
+
+/// Computes the load priority of a chunk. Lower values should be loaded first.
+///
+/// Priority is based on distance to center chunk, weighted by view direction, so chunks in front of
+/// center are loaded before chunks behind it.
+fn load_priority(center: IVec3, forward: Vec2, local: IVec3) -> f32 {
+    let offset = (local - center).as_vec3().xz();
+    let distance = offset.length();
+
+    if distance == 0.0 {
+        return 0.0;
+    }
+
+    let alignment = offset.dot(forward.normalize_or_zero()) / distance;
+
+    // Chunks right in front has their distance unchanged, while chunks behind has it doubled
+    distance * (1.5 - alignment * 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_priority() {
+        let center = IVec3::new(1, 0, 1);
+        let forward = Vec2::new(0.0, -1.0);
+
+        assert_eq!(super::load_priority(center, forward, center), 0.0);
+
+        let front = super::load_priority(center, forward, center + IVec3::new(0, 0, -2));
+        let side = super::load_priority(center, forward, center + IVec3::new(2, 0, 0));
+        let back = super::load_priority(center, forward, center + IVec3::new(0, 0, 2));
+        let near_back = super::load_priority(center, forward, center + IVec3::new(0, 0, 1));
+
+        assert!(front < side);
+        assert!(side < back);
+        assert!(near_back < back, "Nearer chunks should be loaded first");
+        assert_eq!(front, 2.0);
+    }
+
+    #[test]
+    fn horizontal_distance() {
+        let center = IVec3::new(-1, 0, 2);
+
+        assert_eq!(super::horizontal_distance(center, center), 0);
+        assert_eq!(
+            super::horizontal_distance(center, center + IVec3::new(3, 0, -1)),
+            3
+        );
+        assert_eq!(
+            super::horizontal_distance(center, center + IVec3::new(-1, 0, -4)),
+            4
+        );
     }
 }
@@ -1,7 +1,9 @@
+use std::net::SocketAddr;
+
 use bevy::prelude::*;
 
-use projekto_core::landscape;
-use projekto_genesis::GenesisPlugin;
+use projekto_genesis::{GenesisClientPlugin, GenesisPlugin, GenesisServerPlugin};
+use projekto_shaping::{GeneratorConfig, HEIGHTMAP_ENV};
 
 mod landscaping;
 
@@ -9,18 +11,74 @@ pub struct TerraformationPlugin;
 
 impl Plugin for TerraformationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(GenesisPlugin)
-            .add_plugin(landscaping::LandscapingPlugin)
-            .insert_resource(TerraformationConfig {
-                horizontal_radius: (landscape::HORIZONTAL_RADIUS + 2) as u32,
-            });
+        app.add_plugin(landscaping::LandscapingPlugin)
+            .init_resource::<TerraformationConfig>();
+
+        if let Some(addr) = env_addr(CONNECT_ENV) {
+            // Generator config is received from server, so local level of detail chunks match the
+            // replicated chunks. See `GenesisClientPlugin`
+            info!("Connecting to genesis server {}", addr);
+            app.add_plugin(GenesisClientPlugin { addr });
+            return;
+        }
+
+        app.add_plugin(GenesisPlugin);
+
+        if let Some(addr) = env_addr(SERVER_ENV) {
+            app.add_plugin(GenesisServerPlugin { addr });
+        }
+
+        match GeneratorConfig::from_env() {
+            Ok(config) => {
+                if let Some(heightmap) = &config.heightmap {
+                    // Chunks already on cache aren't generated again, so the cache must be cleared
+                    // to see the changes.
+                    info!("Generating terrain using heightmap {:?}", heightmap);
+                }
+
+                app.insert_resource(config);
+            }
+            Err(err) => error!("Failed to load {}: {}", HEIGHTMAP_ENV, err),
+        }
+    }
+}
+
+/// Environment variable with the address to listen for remote clients, like `0.0.0.0:7373`.
+const SERVER_ENV: &str = "PROJEKTO_SERVER";
+
+/// Environment variable with the address of a server to connect to. When set, the world is
+/// received from the server instead of generated locally.
+const CONNECT_ENV: &str = "PROJEKTO_CONNECT";
+
+fn env_addr(var: &str) -> Option<SocketAddr> {
+    let value = std::env::var(var).ok()?;
+
+    match value.parse() {
+        Ok(addr) => Some(addr),
+        Err(err) => {
+            error!("Invalid address {} on {}: {}", value, var, err);
+            None
+        }
     }
 }
 
 #[derive(Component)]
 pub struct TerraformationCenter;
 
-#[derive(Default)]
 pub struct TerraformationConfig {
-    pub horizontal_radius: u32,
+    /// Extra chunks loaded beyond [`projekto_core::landscape::ViewDistance`], so visible chunks
+    /// always have their neighborhood loaded.
+    pub horizontal_margin: u32,
+    /// Extra chunks kept loaded beyond the load radius. Chunks are unloaded only when they are
+    /// farther than this, so walking back and forth across a chunk border doesn't reload chunks.
+    pub unload_margin: u32,
+}
+
+impl Default for TerraformationConfig {
+    fn default() -> Self {
+        Self {
+            horizontal_margin: 2,
+            unload_margin: 2,
+        }
+    }
 }